@@ -0,0 +1,73 @@
+// benches/routes.rs
+//
+// Route setup + throughput benchmark for a program with many endpoints.
+//
+// Run with (request logs go to stdout, so filter them out):
+//   cargo bench --bench routes | grep -v '^{'
+//
+// Builds a synthetic 200-endpoint program, then measures:
+// - compiling endpoints into shared server state (once per server)
+// - building the Actix app from that state (once per worker)
+// - request throughput against the last registered route
+
+use actix_web::{test, App};
+use shrimpl::interpreter::http::{configure, ServerState};
+use shrimpl::parser::parse_program;
+use std::time::Instant;
+
+const ENDPOINTS: usize = 200;
+const APP_BUILDS: usize = 50;
+const REQUESTS: usize = 5_000;
+
+fn synthetic_source() -> String {
+    let mut src = String::from("server 3000\n\n");
+    for i in 0..ENDPOINTS {
+        src.push_str(&format!(
+            "endpoint GET \"/items{i}/:id\":\n  \"item {i} \" + id\n\n"
+        ));
+        src.push_str(&format!(
+            "endpoint POST \"/items{i}\":\n  \"created {i}\"\n\n"
+        ));
+    }
+    src
+}
+
+fn main() {
+    let program = parse_program(&synthetic_source()).expect("synthetic program parses");
+
+    let started = Instant::now();
+    let state = ServerState::new(program);
+    println!(
+        "compile {} routes:        {:>8.2?}",
+        state.endpoints.len(),
+        started.elapsed()
+    );
+
+    actix_web::rt::System::new().block_on(async move {
+        let started = Instant::now();
+        for _ in 0..APP_BUILDS {
+            let _app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+        }
+        println!(
+            "build app (per worker):    {:>8.2?}",
+            started.elapsed() / APP_BUILDS as u32
+        );
+
+        let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+        let uri = format!("/items{}/42", ENDPOINTS - 1);
+
+        let started = Instant::now();
+        for _ in 0..REQUESTS {
+            let req = test::TestRequest::get().uri(&uri).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert!(resp.status().is_success());
+        }
+        let elapsed = started.elapsed();
+        println!(
+            "{} requests:            {:>8.2?} ({:.0} req/s)",
+            REQUESTS,
+            elapsed,
+            REQUESTS as f64 / elapsed.as_secs_f64()
+        );
+    });
+}
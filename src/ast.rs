@@ -2,9 +2,10 @@
 //
 // Core AST types for Shrimpl programs.
 
-use std::collections::HashMap;
+use indexmap::IndexMap;
+use serde::Serialize;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ServerDecl {
     /// TCP port to listen on (e.g. 3000, 443).
     pub port: u16,
@@ -12,19 +13,37 @@ pub struct ServerDecl {
     pub tls: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub enum Method {
     Get,
     Post,
+    Put,
+    Delete,
+    Patch,
 }
 
-#[derive(Debug, Clone)]
+impl Method {
+    /// The HTTP verb as written in `endpoint` declarations.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Patch => "PATCH",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub enum BinOp {
     // arithmetic
     Add,
     Sub,
     Mul,
     Div,
+    /// Remainder; takes the sign of the left operand (`-7 % 3 == -1`).
+    Mod,
     // comparisons
     Eq,
     Ne,
@@ -37,14 +56,30 @@ pub enum BinOp {
     Or,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum Expr {
+    /// Whole-number literal, kept as an exact 64-bit integer (large IDs).
+    Int(i64),
     Number(f64),
     Str(String),
     Bool(bool),
 
     Var(String),
 
+    /// Unary minus: `-x`, `-(1 + 2)`. Binds tighter than `*` and `/`, so
+    /// `-2 * 3` is `(-2) * 3`.
+    Neg(Box<Expr>),
+
+    /// Logical negation: `not x`. Binds looser than comparisons and tighter
+    /// than `and` / `or`, so `not a == b` is `not (a == b)`.
+    Not(Box<Expr>),
+
+    /// Indexing: `list[0]`, `row["name"]`. Binds tighter than unary minus.
+    Index {
+        target: Box<Expr>,
+        index: Box<Expr>,
+    },
+
     /// First-class list literal:
     ///
     ///   [1, 2, "x"]
@@ -79,6 +114,13 @@ pub enum Expr {
         args: Vec<Expr>,
     },
 
+    /// Class constant access: `Config.BASE_URL` (a `.name` with no
+    /// argument list).
+    ClassConst {
+        class_name: String,
+        name: String,
+    },
+
     /// if / elif / else as an expression
     ///
     /// Example:
@@ -104,9 +146,42 @@ pub enum Expr {
     ///
     /// Evaluates `count` once, coerces to integer N (floor),
     /// executes `body` N times, returns the last value (or "" if N == 0).
+    /// The 0-based iteration index is bound as `index_var` in the body
+    /// (`i` by default, or `repeat N times as k: ...`).
+    ///
+    /// Accumulator form:
+    ///
+    ///   repeat N times with acc = 0: acc + i
+    ///
+    /// `accumulator` is `Some(("acc", init))`. The body sees `acc` (the
+    /// previous iteration's result, starting at `init`); the final
+    /// accumulator value is returned.
     Repeat {
         count: Box<Expr>,
         body: Box<Expr>,
+        index_var: String,
+        accumulator: Option<(String, Box<Expr>)>,
+    },
+
+    /// Condition-driven bounded loop:
+    ///
+    ///   repeat while cond: body_expr
+    ///
+    /// Re-evaluates `cond` before each iteration and runs `body` while it is
+    /// truthy. Returns the last body value (or "" if it never ran). Hitting
+    /// the iteration cap (10,000) is a runtime error.
+    ///
+    /// Accumulator form:
+    ///
+    ///   repeat while n < 100 with n = 1: n * 2
+    ///
+    /// `accumulator` is `Some(("n", init))`. Both `cond` and `body` see `n`,
+    /// which is rebound to each body result, so the condition can make
+    /// progress on local state; the final value of `n` is returned.
+    While {
+        cond: Box<Expr>,
+        body: Box<Expr>,
+        accumulator: Option<(String, Box<Expr>)>,
     },
 
     /// try / catch / finally as an expression:
@@ -129,42 +204,73 @@ pub enum Expr {
     },
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum Body {
     TextExpr(Expr),
     JsonRaw(String),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct FunctionDef {
     pub name: String,
     pub params: Vec<String>,
+    /// Inline parameter types by position (`func f(a: number)`); None where a
+    /// parameter is unannotated.
+    pub param_types: Vec<Option<String>>,
+    /// Inline result type (`func f() -> number: ...`), if given.
+    pub result_type: Option<String>,
     pub body: Expr,
+    /// `#` comment lines directly above the declaration.
+    pub doc: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+impl FunctionDef {
+    /// Whether the definition carries any inline type annotation.
+    pub fn has_inline_types(&self) -> bool {
+        self.result_type.is_some() || self.param_types.iter().any(|t| t.is_some())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct ClassDef {
     pub name: String,
-    pub methods: HashMap<String, FunctionDef>,
+    pub methods: IndexMap<String, FunctionDef>,
+    /// `NAME = expr` lines in the class body, read as `Class.NAME`.
+    pub constants: IndexMap<String, Expr>,
 }
 
 /// Optional rate limit decoration for an endpoint, provided via
 /// @rate_limit(max_requests, window_secs) or @rate_limit max window_secs.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct RateLimit {
-    #[allow(dead_code)]
     pub max_requests: u32,
-    #[allow(dead_code)]
     pub window_secs: u32,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct EndpointDecl {
     pub method: Method,
     pub path: String,
     pub body: Body,
     /// Optional rate limit applied to this endpoint.
     pub rate_limit: Option<RateLimit>,
+    /// Seconds to reuse a GET response, from `@cache(ttl_secs)`.
+    pub cache_ttl: Option<u64>,
+    /// Environments the endpoint is registered in, from `@env(...)` or
+    /// `@only ...`. `None` means every environment.
+    pub envs: Option<Vec<String>>,
+    /// `#` comment lines directly above the declaration (or its attributes).
+    pub doc: Option<String>,
+}
+
+impl EndpointDecl {
+    /// Whether the endpoint should be registered when running in `env`.
+    pub fn enabled_in(&self, env: &str) -> bool {
+        match &self.envs {
+            Some(envs) => envs.iter().any(|e| e == env),
+            None => true,
+        }
+    }
 }
 
 /// Secret declarations, mapping a logical name used in Shrimpl code to an
@@ -172,7 +278,7 @@ pub struct EndpointDecl {
 ///
 /// Example Shrimpl:
 ///   secret OPENAI = "SHRIMPL_OPENAI_API_KEY"
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct SecretDecl {
     pub name: String,
     pub key: String,
@@ -191,16 +297,26 @@ pub struct SecretDecl {
 ///   - `id`  -> ty="int",   is_primary_key=true,  is_optional=false
 ///   - `name`-> ty="string",is_primary_key=false, is_optional=false
 ///   - `age` -> ty="int",   is_primary_key=false, is_optional=true
-#[derive(Debug, Clone)]
+///
+/// Validation rules follow the type: `age: int min(0) max(150)` or
+/// `email: string match("^[^@]+@[^@]+$")`. The ORM checks them before
+/// writing a row.
+#[derive(Debug, Clone, Serialize)]
 pub struct ModelField {
     pub name: String,
     pub ty: String,
     pub is_primary_key: bool,
     pub is_optional: bool,
+    /// Inclusive lower bound for numeric fields (`min(..)`).
+    pub min: Option<f64>,
+    /// Inclusive upper bound for numeric fields (`max(..)`).
+    pub max: Option<f64>,
+    /// Regex that string values must match (`match("...")`).
+    pub pattern: Option<String>,
 }
 
 /// A model definition describing a table/entity for the ORM layer.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ModelDef {
     /// Model name as written in Shrimpl (e.g. "User").
     pub name: String,
@@ -208,31 +324,66 @@ pub struct ModelDef {
     /// as-is; callers may downcase or pluralize.
     pub table_name: String,
     pub fields: Vec<ModelField>,
+    /// `#` comment lines directly above the declaration.
+    pub doc: Option<String>,
 }
 
 /// A single test case defined in Shrimpl source:
 ///
 ///   test "name":
+///     let name = <expr>
 ///     assert <expr>
-///     assert <expr>
-#[derive(Debug, Clone)]
+///     assert <expr>, "message"
+///     teardown:
+///       <expr>
+///
+/// A table-driven test runs its lines once per row, with the row bound to
+/// `case`:
+///
+///   test "add" cases [[1, 2, 3], [2, 2, 4]]:
+///     assert add(case[0], case[1]) == case[2]
+#[derive(Debug, Clone, Serialize)]
 pub struct TestCase {
     #[allow(dead_code)]
     pub name: String,
-    #[allow(dead_code)]
-    pub assertions: Vec<Expr>,
+    /// The `cases <list>` expression of a table-driven test.
+    pub cases: Option<Expr>,
+    /// `let` bindings and assertions, run in source order.
+    pub steps: Vec<TestStep>,
+    /// Expressions under `teardown:`, run after the steps whether or not
+    /// they passed.
+    pub teardown: Vec<Expr>,
+}
+
+/// One line of a test block.
+#[derive(Debug, Clone, Serialize)]
+pub enum TestStep {
+    /// `let name = <expr>`: bind a variable for the following lines.
+    Let {
+        name: String,
+        expr: Expr,
+    },
+    Assert(Assertion),
+}
+
+/// One `assert` line inside a test block.
+#[derive(Debug, Clone, Serialize)]
+pub struct Assertion {
+    pub expr: Expr,
+    /// Optional trailing `, "message"` shown when the assertion fails.
+    pub message: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Program {
     pub server: ServerDecl,
     pub endpoints: Vec<EndpointDecl>,
-    pub functions: HashMap<String, FunctionDef>,
-    pub classes: HashMap<String, ClassDef>,
+    pub functions: IndexMap<String, FunctionDef>,
+    pub classes: IndexMap<String, ClassDef>,
     pub secrets: Vec<SecretDecl>,
     /// All tests defined via `test "name": ...`.
     #[allow(dead_code)]
     pub tests: Vec<TestCase>,
     /// All `model` declarations keyed by model name.
-    pub models: HashMap<String, ModelDef>,
+    pub models: IndexMap<String, ModelDef>,
 }
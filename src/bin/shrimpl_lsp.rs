@@ -7,9 +7,11 @@ use tower_lsp::jsonrpc::Result;
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer, LspService, Server};
 
+use shrimpl::config;
 use shrimpl::docs;
-use shrimpl::parser::ast::Program;
+use shrimpl::parser::ast::{Body, EndpointDecl, Program};
 use shrimpl::parser::parse_program;
+use shrimpl::typecheck;
 
 /// Backend state for the LSP server.
 #[derive(Debug)]
@@ -35,11 +37,13 @@ impl Backend {
     }
 
     async fn reanalyze(&self, uri: Url, text: String) {
+        let outline = parse_outline(&text);
         let (mut diagnostics, program_opt) = analyze_source(text);
 
         if let Some(program) = program_opt {
-            let diags_json: Value = docs::build_diagnostics(&program);
-            diagnostics.extend(convert_static_diagnostics(&diags_json));
+            let mut diags_json: Value = docs::build_diagnostics(&program);
+            typecheck::merge_type_diagnostics(&mut diags_json, &program);
+            diagnostics.extend(convert_static_diagnostics(&diags_json, &outline));
         }
 
         let _ = self
@@ -88,8 +92,10 @@ fn analyze_source(source: String) -> (Vec<Diagnostic>, Option<Program>) {
     }
 }
 
-/// Convert JSON diagnostics from docs::build_diagnostics into LSP diagnostics.
-fn convert_static_diagnostics(json: &Value) -> Vec<Diagnostic> {
+/// Convert JSON diagnostics from docs::build_diagnostics (plus merged type
+/// diagnostics) into LSP diagnostics. Function- and endpoint-scoped items are
+/// placed on their declaration; the rest go on the first line.
+fn convert_static_diagnostics(json: &Value, outline: &Outline) -> Vec<Diagnostic> {
     let mut out = Vec::new();
 
     let errors = json
@@ -103,7 +109,7 @@ fn convert_static_diagnostics(json: &Value) -> Vec<Diagnostic> {
         .cloned()
         .unwrap_or_default();
 
-    for item in errors.into_iter().chain(warnings.into_iter()) {
+    for item in errors.into_iter().chain(warnings) {
         let message = item
             .get("message")
             .and_then(|v| v.as_str())
@@ -122,16 +128,7 @@ fn convert_static_diagnostics(json: &Value) -> Vec<Diagnostic> {
         };
 
         let diagnostic = Diagnostic {
-            range: Range {
-                start: Position {
-                    line: 0,
-                    character: 0,
-                },
-                end: Position {
-                    line: 0,
-                    character: 1,
-                },
-            },
+            range: static_diagnostic_range(&item, outline),
             severity: Some(severity),
             code: None,
             code_description: None,
@@ -148,6 +145,88 @@ fn convert_static_diagnostics(json: &Value) -> Vec<Diagnostic> {
     out
 }
 
+/// Range of the declaration a static diagnostic refers to, by its
+/// `scope`/`name`, falling back to the start of the file.
+fn static_diagnostic_range(item: &Value, outline: &Outline) -> Range {
+    let scope = item.get("scope").and_then(|v| v.as_str()).unwrap_or("");
+    let name = item.get("name").and_then(|v| v.as_str()).unwrap_or("");
+
+    let found = match scope {
+        "function" => outline
+            .functions
+            .iter()
+            .find(|f| f.name == name)
+            .map(|f| make_range(f.line, f.start_char, f.end_char)),
+        "endpoint" => outline
+            .endpoints
+            .iter()
+            .find(|e| e.path == name)
+            .map(|e| make_range(e.line, e.start_char, e.end_char)),
+        _ => None,
+    };
+
+    found.unwrap_or_else(|| make_range(0, 0, 1))
+}
+
+/// Hover details for one parsed endpoint: doc comment, body, rate limit and
+/// auth requirement. `decl_line` is the 0-based `endpoint` line in `lines`.
+fn endpoint_hover_details(ep: &EndpointDecl, lines: &[&str], decl_line: usize) -> String {
+    let mut out = String::new();
+
+    if let Some(doc) = &ep.doc {
+        out.push_str(&format!("\n\n{}", doc));
+    }
+
+    // Show the declaration as written: the `endpoint` line plus every
+    // following line indented deeper (the body).
+    let indent = |l: &str| l.len() - l.trim_start().len();
+    let base = lines.get(decl_line).map(|l| indent(l)).unwrap_or(0);
+    let mut source: Vec<&str> = Vec::new();
+    for (i, line) in lines.iter().enumerate().skip(decl_line) {
+        if i > decl_line && !line.trim().is_empty() && indent(line) <= base {
+            break;
+        }
+        source.push(line);
+    }
+    while source.last().map(|l| l.trim().is_empty()).unwrap_or(false) {
+        source.pop();
+    }
+    let body_kind = match ep.body {
+        Body::TextExpr(_) => "text expression",
+        Body::JsonRaw(_) => "constant JSON",
+    };
+    out.push_str(&format!(
+        "\n\n```shrimpl\n{}\n```\n\n- Body: {}",
+        source.join("\n"),
+        body_kind
+    ));
+
+    match &ep.rate_limit {
+        Some(rl) => out.push_str(&format!(
+            "\n- Rate limit: {} requests per {}s",
+            rl.max_requests, rl.window_secs
+        )),
+        None => out.push_str("\n- Rate limit: none"),
+    }
+    if let Some(ttl) = ep.cache_ttl {
+        out.push_str(&format!("\n- Cached: {}s (ETag)", ttl));
+    }
+    if let Some(envs) = &ep.envs {
+        out.push_str(&format!("\n- Environments: {}", envs.join(", ")));
+    }
+
+    let auth = if !config::path_requires_auth(&ep.path) {
+        "none"
+    } else if config::auth_uses_basic() {
+        "HTTP Basic credentials required"
+    } else {
+        "JWT bearer token required"
+    };
+    out.push_str(&format!("\n- Auth: {}", auth));
+
+    out
+}
+
 #[derive(Debug, Clone)]
 struct ServerOutline {
     port: Option<u16>,
@@ -306,7 +385,12 @@ fn parse_outline(text: &str) -> Outline {
                     break;
                 }
 
-                if let Some(paren_idx) = trimmed2.find('(') {
+                // `NAME = expr` lines are class constants, not methods.
+                let is_constant = trimmed2
+                    .split_once('=')
+                    .is_some_and(|(lhs, rhs)| !lhs.contains('(') && !rhs.starts_with('='));
+
+                if let Some(paren_idx) = trimmed2.find('(').filter(|_| !is_constant) {
                     let method_name = trimmed2[..paren_idx].trim().to_string();
                     let line2_no = i as u32;
                     let end2_char = line2.len() as u32;
@@ -458,6 +542,30 @@ fn keyword_completions() -> Vec<CompletionItem> {
             insert_text_format: Some(InsertTextFormat::PLAIN_TEXT),
             ..CompletionItem::default()
         },
+        CompletionItem {
+            label: "PUT".to_string(),
+            kind: Some(CompletionItemKind::KEYWORD),
+            detail: Some("HTTP PUT method".to_string()),
+            insert_text: Some("PUT".to_string()),
+            insert_text_format: Some(InsertTextFormat::PLAIN_TEXT),
+            ..CompletionItem::default()
+        },
+        CompletionItem {
+            label: "PATCH".to_string(),
+            kind: Some(CompletionItemKind::KEYWORD),
+            detail: Some("HTTP PATCH method".to_string()),
+            insert_text: Some("PATCH".to_string()),
+            insert_text_format: Some(InsertTextFormat::PLAIN_TEXT),
+            ..CompletionItem::default()
+        },
+        CompletionItem {
+            label: "DELETE".to_string(),
+            kind: Some(CompletionItemKind::KEYWORD),
+            detail: Some("HTTP DELETE method".to_string()),
+            insert_text: Some("DELETE".to_string()),
+            insert_text_format: Some(InsertTextFormat::PLAIN_TEXT),
+            ..CompletionItem::default()
+        },
         CompletionItem {
             label: "json".to_string(),
             kind: Some(CompletionItemKind::KEYWORD),
@@ -575,7 +683,22 @@ fn compute_rename_edits(
 
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        // Load config/config.<env>.json from the workspace root so type
+        // annotations (config.types) feed the diagnostics.
+        #[allow(deprecated)]
+        let root = params
+            .workspace_folders
+            .as_ref()
+            .and_then(|folders| folders.first())
+            .map(|f| f.uri.clone())
+            .or(params.root_uri)
+            .and_then(|uri| uri.to_file_path().ok());
+        match root {
+            Some(dir) => config::init_in(&dir),
+            None => config::init(),
+        }
+
         let capabilities = ServerCapabilities {
             text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
             hover_provider: Some(HoverProviderCapability::Simple(true)),
@@ -681,13 +804,19 @@ impl LanguageServer for Backend {
             return Ok(None);
         }
         let mut word = line_str[start..end].to_string();
-        word = word.trim_matches('"').to_string();
+        // `"/path":` on an endpoint line: drop the body colon, then quotes.
+        word = word.trim_end_matches(':').trim_matches('"').to_string();
 
         if word.is_empty() {
             return Ok(None);
         }
 
         let outline = parse_outline(&text);
+        // Doc comments come from the parsed program; hover still works
+        // (without them) while the file has syntax errors.
+        let program = parse_program(&text).ok();
+        let doc_suffix =
+            |doc: Option<&String>| doc.map(|d| format!("\n\n---\n\n{}", d)).unwrap_or_default();
 
         let mut func_names = HashMap::<String, FunctionOutline>::new();
         for f in &outline.functions {
@@ -737,7 +866,7 @@ impl LanguageServer for Backend {
             }
         } else if word == "endpoint" {
             Some(
-                "Shrimpl endpoint declaration.\n\nSyntax: `endpoint METHOD \"/path\": expr`\n\nMethods currently supported: `GET`, `POST`."
+                "Shrimpl endpoint declaration.\n\nSyntax: `endpoint METHOD \"/path\": expr`\n\nMethods currently supported: `GET`, `POST`, `PUT`, `PATCH`, `DELETE`."
                     .to_string(),
             )
         } else if word == "func" {
@@ -747,7 +876,7 @@ impl LanguageServer for Backend {
             )
         } else if word == "class" {
             Some(
-                "Shrimpl class definition.\n\nSyntax: `class Name:` followed by indented methods:\n`  methodName(args): expr`."
+                "Shrimpl class definition.\n\nSyntax: `class Name:` followed by indented methods and constants:\n`  methodName(args): expr`\n`  NAME = expr` (read as `Name.NAME`)."
                     .to_string(),
             )
         } else if word == "model" {
@@ -755,17 +884,22 @@ impl LanguageServer for Backend {
                 "Shrimpl ORM model definition.\n\nSyntax:\n```shrimpl\nmodel User:\n  id: int pk\n  name: string\n  age?: int\n```"
                     .to_string(),
             )
-        } else if word == "GET" || word == "POST" {
+        } else if matches!(word.as_str(), "GET" | "POST" | "PUT" | "PATCH" | "DELETE") {
             Some(format!(
                 "HTTP `{}` endpoint method.\n\nUsed in `endpoint` declarations, for example:\n```shrimpl\nendpoint {} \"/hello\": \"Hello!\"\n```",
                 word, word
             ))
         } else if let Some(f) = func_names.get(&word) {
+            let doc = program
+                .as_ref()
+                .and_then(|p| p.functions.get(&f.name))
+                .and_then(|func| func.doc.as_ref());
             Some(format!(
-                "Function `{}`.\n\nDeclared on line {}.\n\nSyntax: `func {}(...): expr`.",
+                "Function `{}`.\n\nDeclared on line {}.\n\nSyntax: `func {}(...): expr`.{}",
                 f.name,
                 f.line + 1,
-                f.name
+                f.name,
+                doc_suffix(doc)
             ))
         } else if let Some(c) = class_names.get(&word) {
             let method_list = if c.methods.is_empty() {
@@ -782,10 +916,15 @@ impl LanguageServer for Backend {
                 method_list
             ))
         } else if let Some(m) = model_names.get(&word) {
+            let doc = program
+                .as_ref()
+                .and_then(|p| p.models.get(&m.name))
+                .and_then(|model| model.doc.as_ref());
             Some(format!(
-                "Model `{}`.\n\nDeclared on line {}.\n\nUsed by the ORM layer to create tables and map records to rows.",
+                "Model `{}`.\n\nDeclared on line {}.\n\nUsed by the ORM layer to create tables and map records to rows.{}",
                 m.name,
-                m.line + 1
+                m.line + 1,
+                doc_suffix(doc)
             ))
         } else if let Some(methods) = method_names.get(&word) {
             let mut entries = Vec::new();
@@ -803,19 +942,25 @@ impl LanguageServer for Backend {
                 entries.join("\n")
             ))
         } else if let Some(eps) = endpoint_paths.get(&word) {
+            // Outline entries and parsed endpoints share source order, so the
+            // n-th outline entry for this path is the n-th parsed one.
+            let parsed: Vec<&EndpointDecl> = program
+                .iter()
+                .flat_map(|p| p.endpoints.iter())
+                .filter(|e| e.path == word)
+                .collect();
             let mut entries = Vec::new();
-            for ep in eps {
-                entries.push(format!(
-                    "- `{}` `{}` (line {})",
-                    ep.method,
-                    ep.path,
-                    ep.line + 1
-                ));
+            for (n, ep) in eps.iter().enumerate() {
+                let mut entry = format!("### `{} {}` (line {})", ep.method, ep.path, ep.line + 1);
+                if let Some(decl) = parsed.get(n) {
+                    entry.push_str(&endpoint_hover_details(decl, &lines, ep.line as usize));
+                }
+                entries.push(entry);
             }
             Some(format!(
-                "Endpoint path `{}`.\n\nDeclared as:\n{}",
+                "Endpoint path `{}`.\n\n{}",
                 word,
-                entries.join("\n")
+                entries.join("\n\n")
             ))
         } else {
             None
@@ -2,20 +2,24 @@
 //
 // Simple in-memory TTL cache for Shrimpl.
 //
-// This is intentionally straightforward and safe to use from async
-// contexts via a Mutex + Instant. It can be wrapped by interpreter
-// builtins like:
+// This is intentionally straightforward: a non-poisoning Mutex + Instant,
+// with a synchronous API so the (synchronous) evaluator can call it from
+// request handlers. The interpreter exposes it to Shrimpl code as:
 //
 //   cache_set(key, value, ttl_seconds)
 //   cache_get(key)
 //   cache_delete(key)
+//
+// Internal users namespace their keys (e.g. "openai:...", "http:...", and
+// "app:..." for the builtins above) so they can be cleared with
+// `cache_clear_prefix` without touching other entries.
 
 use std::collections::HashMap;
 use std::time::{Duration, Instant};
 
 use once_cell::sync::Lazy;
+use parking_lot::Mutex;
 use serde_json::Value as JsonValue;
-use tokio::sync::Mutex;
 
 #[derive(Debug, Clone)]
 struct CacheEntry {
@@ -27,22 +31,22 @@ static GLOBAL_CACHE: Lazy<Mutex<HashMap<String, CacheEntry>>> =
     Lazy::new(|| Mutex::new(HashMap::new()));
 
 /// Set a cache key to a JSON value with optional TTL in seconds.
-/// ttl_secs == None => no expiration.
-pub async fn cache_set(key: String, value: JsonValue, ttl_secs: Option<u64>) {
+/// ttl_secs == None => no expiration; Some(0) => already expired.
+pub fn cache_set(key: String, value: JsonValue, ttl_secs: Option<u64>) {
     let expires_at = ttl_secs.map(|s| Instant::now() + Duration::from_secs(s));
     let entry = CacheEntry { value, expires_at };
 
-    let mut cache = GLOBAL_CACHE.lock().await;
+    let mut cache = GLOBAL_CACHE.lock();
     cache.insert(key, entry);
 }
 
 /// Get a value from the cache if present and not expired.
-pub async fn cache_get(key: &str) -> Option<JsonValue> {
-    let mut cache = GLOBAL_CACHE.lock().await;
+pub fn cache_get(key: &str) -> Option<JsonValue> {
+    let mut cache = GLOBAL_CACHE.lock();
 
     if let Some(entry) = cache.get(key) {
         if let Some(deadline) = entry.expires_at {
-            if Instant::now() > deadline {
+            if Instant::now() >= deadline {
                 cache.remove(key);
                 return None;
             }
@@ -54,29 +58,35 @@ pub async fn cache_get(key: &str) -> Option<JsonValue> {
 }
 
 /// Delete a key from the cache, ignoring missing keys.
-pub async fn cache_delete(key: &str) {
-    let mut cache = GLOBAL_CACHE.lock().await;
+pub fn cache_delete(key: &str) {
+    let mut cache = GLOBAL_CACHE.lock();
     cache.remove(key);
 }
 
+/// Delete every key starting with `prefix`. Returns how many were removed.
+pub fn cache_clear_prefix(prefix: &str) -> usize {
+    let mut cache = GLOBAL_CACHE.lock();
+    let before = cache.len();
+    cache.retain(|key, _| !key.starts_with(prefix));
+    before - cache.len()
+}
+
 /// Convenience helper for Shrimpl builtins using strings.
 ///
 /// - `value_json` is a JSON string representation of the value.
 /// - `ttl_secs` is optional TTL in seconds.
 ///
 /// Returns `Ok(())` or an error string.
-pub async fn cache_set_json(
-    key: &str,
-    value_json: &str,
-    ttl_secs: Option<u64>,
-) -> Result<(), String> {
+#[allow(dead_code)]
+pub fn cache_set_json(key: &str, value_json: &str, ttl_secs: Option<u64>) -> Result<(), String> {
     let value: JsonValue =
         serde_json::from_str(value_json).map_err(|e| format!("invalid JSON: {e}"))?;
-    cache_set(key.to_string(), value, ttl_secs).await;
+    cache_set(key.to_string(), value, ttl_secs);
     Ok(())
 }
 
 /// Get a JSON string from cache, if present.
-pub async fn cache_get_json(key: &str) -> Option<String> {
-    cache_get(key).await.map(|v| v.to_string())
+#[allow(dead_code)]
+pub fn cache_get_json(key: &str) -> Option<String> {
+    cache_get(key).map(|v| v.to_string())
 }
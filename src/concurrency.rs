@@ -7,30 +7,68 @@
 // Example builtin usage:
 //   - http_get_many(urls_json) -> JSON array of bodies
 //   - http_get_json_many(urls_json) -> JSON array of parsed values
+//
+// Also drives `shrimpl bench` via `run_load`.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::{Duration, Instant};
 
 use futures::future::join_all;
-use reqwest::Client;
+use once_cell::sync::Lazy;
+use reqwest::{Client, Method};
 use serde_json::Value as JsonValue;
 
-/// Concurrently GET multiple URLs and return their bodies as strings.
-///
-/// `urls` is a vector of absolute URLs.
-pub async fn http_get_many(urls: Vec<String>) -> Result<Vec<String>, reqwest::Error> {
-    let client = Client::new();
+use crate::http_client;
+
+// Runtime for builtins that fan out async work. The interpreter is
+// synchronous and may itself run on an Actix worker, so work is spawned here
+// rather than driven with `Runtime::block_on` on the caller's thread.
+static RUNTIME: Lazy<tokio::runtime::Runtime> = Lazy::new(|| {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .thread_name("shrimpl-concurrency")
+        .build()
+        .expect("failed to start the concurrency runtime")
+});
 
+/// Run `fut` on the shared runtime and block the current thread until it
+/// finishes. Safe to call from inside another Tokio runtime.
+pub fn block_on<F>(fut: F) -> F::Output
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    match futures::executor::block_on(RUNTIME.spawn(fut)) {
+        Ok(out) => out,
+        Err(err) => std::panic::resume_unwind(err.into_panic()),
+    }
+}
+
+/// Concurrently GET multiple URLs and return their bodies as strings, in the
+/// same order as `urls`.
+///
+/// `urls` is a vector of absolute URLs. Requests go through the shared
+/// `http_client` agent (one blocking task each), so they follow the same
+/// redirect, timeout and host rules as `http_get`; a 4xx/5xx status is an
+/// error too.
+pub async fn http_get_many(urls: Vec<String>) -> Result<Vec<String>, String> {
     let futures = urls.into_iter().map(|url| {
-        let client = client.clone();
-        async move {
-            let body = client.get(url).send().await?.text().await?;
-            Ok::<String, reqwest::Error>(body)
-        }
+        tokio::task::spawn_blocking(move || {
+            http_client::agent()
+                .get(&url)
+                .call()
+                .map_err(|e| e.to_string())?
+                .into_string()
+                .map_err(|e| format!("{}: failed to read body: {}", url, e))
+        })
     });
 
     let results = join_all(futures).await;
 
     let mut out = Vec::new();
     for res in results {
-        out.push(res?);
+        out.push(res.map_err(|e| e.to_string())??);
     }
 
     Ok(out)
@@ -40,7 +78,8 @@ pub async fn http_get_many(urls: Vec<String>) -> Result<Vec<String>, reqwest::Er
 ///
 /// Returns a JSON array of each response body; failures are surfaced as
 /// a single error (the first encountered).
-pub async fn http_get_json_many(urls: Vec<String>) -> Result<JsonValue, reqwest::Error> {
+#[allow(dead_code)]
+pub async fn http_get_json_many(urls: Vec<String>) -> Result<JsonValue, String> {
     let bodies = http_get_many(urls).await?;
     let mut arr = Vec::new();
     for body in bodies {
@@ -49,3 +88,92 @@ pub async fn http_get_json_many(urls: Vec<String>) -> Result<JsonValue, reqwest:
     }
     Ok(JsonValue::Array(arr))
 }
+
+/// Outcome of a `run_load` session.
+pub struct LoadReport {
+    /// Latency of every request that got a response, sorted ascending.
+    pub latencies: Vec<Duration>,
+    /// Response count per HTTP status code.
+    pub statuses: BTreeMap<u16, usize>,
+    /// Requests that failed without a response (connect/timeout errors).
+    pub errors: usize,
+    /// Wall-clock time from the first request to the last response.
+    pub elapsed: Duration,
+}
+
+impl LoadReport {
+    /// Latency at percentile `p` (0-100), or zero when nothing completed.
+    pub fn percentile(&self, p: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = (p / 100.0 * (self.latencies.len() - 1) as f64).round() as usize;
+        self.latencies[rank.min(self.latencies.len() - 1)]
+    }
+}
+
+/// Send `method url` back to back from `concurrency` workers until
+/// `duration` has passed, recording per-request latency.
+///
+/// Certificate checks are skipped so a local `server 443 tls` with a
+/// self-signed certificate can be benchmarked.
+pub async fn run_load(
+    method: Method,
+    url: String,
+    concurrency: usize,
+    duration: Duration,
+) -> Result<LoadReport, reqwest::Error> {
+    let client = Client::builder()
+        .danger_accept_invalid_certs(true)
+        .build()?;
+
+    let started = Instant::now();
+    let deadline = started + duration;
+
+    let workers = (0..concurrency.max(1)).map(|_| {
+        let client = client.clone();
+        let method = method.clone();
+        let url = url.clone();
+        async move {
+            let mut latencies = Vec::new();
+            let mut statuses: Vec<u16> = Vec::new();
+            let mut errors = 0;
+            while Instant::now() < deadline {
+                let sent = Instant::now();
+                let res = client.request(method.clone(), &url).send().await;
+                match res {
+                    Ok(resp) => {
+                        let status = resp.status().as_u16();
+                        // Read the whole body so the timing covers it.
+                        if resp.bytes().await.is_ok() {
+                            latencies.push(sent.elapsed());
+                            statuses.push(status);
+                        } else {
+                            errors += 1;
+                        }
+                    }
+                    Err(_) => errors += 1,
+                }
+            }
+            (latencies, statuses, errors)
+        }
+    });
+
+    let mut report = LoadReport {
+        latencies: Vec::new(),
+        statuses: BTreeMap::new(),
+        errors: 0,
+        elapsed: Duration::ZERO,
+    };
+    for (latencies, statuses, errors) in join_all(workers).await {
+        report.latencies.extend(latencies);
+        for status in statuses {
+            *report.statuses.entry(status).or_insert(0) += 1;
+        }
+        report.errors += errors;
+    }
+    report.elapsed = started.elapsed();
+    report.latencies.sort();
+
+    Ok(report)
+}
@@ -4,21 +4,42 @@
 //
 // Features:
 // - Loads JSON from config/config.<env>.json where env = SHRIMPL_ENV or "dev".
-// - Exposes server overrides (port, tls).
-// - Exposes secret mappings (logical -> env var).
-// - Exposes auth (JWT) configuration.
+// - Loads the optional project manifest `shrimpl.toml` underneath it: the
+//   manifest uses the same sections (plus `[project] entry`), and anything
+//   the env JSON sets wins.
+// - Exposes server overrides (port, tls, default response headers, global
+//   per-IP rate limit).
+// - Exposes secret mappings (logical -> env var), unified with the program's
+//   `secret` declarations.
+// - Exposes auth (JWT or HTTP Basic) configuration.
 // - Exposes per-path validation schemas.
 // - Exposes type annotations for functions (used by type checker).
 // - Exposes generic key/value config for config_get/config_set.
+// - Exposes a data directory that local file helpers are confined to.
+// - Exposes OpenAI options (response cache TTL, calls-per-minute cap).
+// - Exposes the SQLite database path and boolean feature flags
+//   (SHRIMPL_FEATURE_<NAME> env vars override both files).
+// - Exposes outbound HTTP client options for http_get and friends
+//   (redirect cap, timeout, host allowlist, private-address blocking).
 //
 // Example config/dev file (config/config.dev.json):
 //
 // {
-//   "server": { "port": 3000, "tls": false },
+//   "server": {
+//     "port": 3000,
+//     "tls": false,
+//     "default_headers": { "X-Content-Type-Options": "nosniff" },
+//     "rate_limit": { "max_requests": 600, "window_secs": 60 },
+//     "trusted_proxies": ["10.0.0.0/8"]
+//   },
 //   "auth": {
+//     "mode": "jwt",
 //     "jwt_secret_env": "SHRIMPL_JWT_SECRET",
+//     "jwt_secret_ref": "JWT",
 //     "protected_paths": ["/secure", "/admin"],
-//     "allow_missing_on": ["/health"]
+//     "allow_missing_on": ["/health"],
+//     "verify_if_present": false,
+//     "basic": { "users": { "ops": "SHRIMPL_BASIC_OPS_PASSWORD" } }
 //   },
 //   "validation": {
 //     "schemas": {
@@ -53,19 +74,61 @@
 //     "greeting": "Hello Shrimpl",
 //     "threshold": 0.75,
 //     "debug": true
-//   }
+//   },
+//   "data_dir": "data",
+//   "csv": { "inference": "strict" },
+//   "openai": { "cache_ttl": 300, "max_calls_per_min": 30 },
+//   "database": { "path": "data/app.db", "auto_transactions": true },
+//   "http_client": {
+//     "max_redirects": 3,
+//     "timeout_ms": 10000,
+//     "allow_hosts": ["api.example.com", "*.example.org"],
+//     "deny_private": true,
+//     "deny_cidrs": ["169.254.0.0/16", "10.20.0.0/16"]
+//   },
+//   "features": { "new_checkout": true }
 // }
+//
+// Example manifest (shrimpl.toml):
+//
+// [project]
+// entry = "src/app.shr"
+//
+// [server]
+// port = 8080
+//
+// [database]
+// path = "data/app.db"
+//
+// [features]
+// new_checkout = false
 
 use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
 use serde::Deserialize;
 use serde_json::Value;
 use std::collections::HashMap;
-use std::{env, fs, path::Path, sync::Mutex};
+use std::{env, fs, path::Path};
 
 #[derive(Debug, Clone, Deserialize, Default)]
 pub struct ServerConfigFile {
     pub port: Option<u16>,
     pub tls: Option<bool>,
+    /// Headers added to every response unless the response already sets them.
+    pub default_headers: Option<HashMap<String, String>>,
+    /// Global per-client-IP request budget applied across all routes.
+    pub rate_limit: Option<GlobalRateLimitFile>,
+    /// Reverse proxies (IPs or CIDRs) whose `X-Forwarded-For` names the
+    /// client. Without it the client is always the connecting address.
+    pub trusted_proxies: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct GlobalRateLimitFile {
+    /// Requests allowed per client IP within one window.
+    pub max_requests: u32,
+    /// Window length in seconds.
+    pub window_secs: u32,
 }
 
 #[derive(Debug, Clone, Deserialize, Default)]
@@ -76,12 +139,27 @@ pub struct SecretsConfigFile {
 
 #[derive(Debug, Clone, Deserialize, Default)]
 pub struct AuthConfigFile {
+    /// "jwt" (default) or "basic": how protected paths authenticate requests.
+    pub mode: Option<String>,
+    /// HTTP Basic credentials, used when mode is "basic".
+    pub basic: Option<BasicAuthConfigFile>,
     /// Env var name that holds the HMAC/secret used to sign JWTs.
     pub jwt_secret_env: Option<String>,
+    /// Logical secret name (a Shrimpl `secret` declaration or `secrets.env`
+    /// entry) holding the JWT secret. Takes precedence over jwt_secret_env.
+    pub jwt_secret_ref: Option<String>,
     /// Paths that require a valid JWT (prefix match).
     pub protected_paths: Option<Vec<String>>,
     /// Paths that are always allowed even if protected_paths is used.
     pub allow_missing_on: Option<Vec<String>>,
+    /// Also verify credentials on unprotected paths when they are presented.
+    pub verify_if_present: Option<bool>,
+}
+
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct BasicAuthConfigFile {
+    /// Username -> env var name that holds that user's password.
+    pub users: HashMap<String, String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Default)]
@@ -93,20 +171,58 @@ pub struct ValidationConfigFile {
 #[derive(Debug, Clone, Deserialize, Default)]
 pub struct FunctionTypeFile {
     /// Parameter types by position: "number", "string", "bool", "any".
-    #[allow(dead_code)]
     pub params: Vec<String>,
     /// Optional result type.
-    #[allow(dead_code)]
     pub result: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Default)]
 pub struct TypesConfigFile {
     /// Function name -> type info.
-    #[allow(dead_code)]
     pub functions: HashMap<String, FunctionTypeFile>,
 }
 
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct OpenAIConfigFile {
+    /// Seconds to cache identical openai_chat responses; absent or 0 disables.
+    pub cache_ttl: Option<u64>,
+    /// Maximum OpenAI API calls per rolling minute; absent or 0 means no cap.
+    pub max_calls_per_min: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct CsvConfigFile {
+    /// How CSV cells become values: "loose" (per cell, default) or
+    /// "strict" (per column).
+    pub inference: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct DatabaseConfigFile {
+    /// SQLite file backing the ORM (default: shrimpl.db in the CWD).
+    pub path: Option<String>,
+    /// Wrap each write request (POST/PUT/PATCH/DELETE) in one transaction
+    /// (default: true).
+    pub auto_transactions: Option<bool>,
+}
+
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct HttpClientConfigFile {
+    /// Redirects to follow per request (default: 5; 0 returns the 3xx
+    /// response itself).
+    pub max_redirects: Option<u32>,
+    /// Whole-request timeout in milliseconds (default: none).
+    pub timeout_ms: Option<u64>,
+    /// Hosts that may be called: exact names or `*.suffix` patterns
+    /// (default: any host).
+    pub allow_hosts: Option<Vec<String>>,
+    /// Refuse loopback, private and link-local addresses (default: false).
+    pub deny_private: Option<bool>,
+    /// Address ranges that may never be called: CIDRs or single IPs
+    /// (default: link-local ranges in prod, none elsewhere).
+    pub deny_cidrs: Option<Vec<String>>,
+}
+
 #[derive(Debug, Clone, Deserialize, Default)]
 pub struct FileConfig {
     pub server: Option<ServerConfigFile>,
@@ -114,10 +230,21 @@ pub struct FileConfig {
     pub auth: Option<AuthConfigFile>,
     pub validation: Option<ValidationConfigFile>,
     /// Optional static typing configuration for Shrimpl programs.
-    #[allow(dead_code)]
     pub types: Option<TypesConfigFile>,
     /// Arbitrary key/value config for config_get/config_set.
     pub values: Option<HashMap<String, Value>>,
+    /// Directory that local data files (e.g. df_from_csv_file) must live in.
+    pub data_dir: Option<String>,
+    /// OpenAI helper options.
+    pub openai: Option<OpenAIConfigFile>,
+    /// CSV parsing options for the df_from_csv* helpers.
+    pub csv: Option<CsvConfigFile>,
+    /// ORM database options.
+    pub database: Option<DatabaseConfigFile>,
+    /// Named on/off switches read with `feature(name)`.
+    pub features: Option<HashMap<String, bool>>,
+    /// Outbound HTTP options for the http_* builtins and df_from_csv.
+    pub http_client: Option<HttpClientConfigFile>,
 }
 
 #[derive(Debug, Default)]
@@ -125,8 +252,18 @@ pub struct RuntimeConfig {
     pub file: FileConfig,
     pub env_name: String,
     pub values: HashMap<String, Value>,
+    /// `secret NAME = "ENV_VAR"` declarations from the running program.
+    pub declared_secrets: HashMap<String, String>,
+    /// In-process secret values set via `secret_set` (dev/test only).
+    /// Consulted before the environment.
+    pub secret_overrides: HashMap<String, String>,
+    /// `[project] entry` from shrimpl.toml, resolved against the project root.
+    pub manifest_entry: Option<String>,
 }
 
+/// Project manifest file name, looked up in the project root.
+pub const MANIFEST_FILE: &str = "shrimpl.toml";
+
 static RUNTIME_CONFIG: OnceCell<Mutex<RuntimeConfig>> = OnceCell::new();
 
 fn runtime() -> &'static Mutex<RuntimeConfig> {
@@ -135,21 +272,32 @@ fn runtime() -> &'static Mutex<RuntimeConfig> {
 
 /// Initialize configuration from disk (idempotent).
 pub fn init() {
+    init_in(Path::new(""));
+}
+
+/// Like `init`, but reads `<root>/shrimpl.toml` and
+/// `<root>/config/config.<env>.json`. Used by tools (e.g. the LSP) whose
+/// working directory isn't the project root.
+pub fn init_in(root: &Path) {
     let env_name = env::var("SHRIMPL_ENV").unwrap_or_else(|_| "dev".to_string());
     let file_name = format!("config.{}.json", env_name);
-    let path = Path::new("config").join(file_name);
+    let path = root.join("config").join(file_name);
 
-    let file_cfg: FileConfig = match fs::read_to_string(&path) {
-        Ok(text) => match serde_json::from_str::<FileConfig>(&text) {
-            Ok(cfg) => cfg,
-            Err(err) => {
-                eprintln!(
-                    "[shrimpl-config] Failed to parse {}: {} (using defaults)",
-                    path.display(),
-                    err
-                );
-                FileConfig::default()
-            }
+    // The manifest is the base layer; the env JSON is merged over it.
+    let mut merged = load_manifest(&root.join(MANIFEST_FILE));
+    let manifest_entry = merged
+        .pointer("/project/entry")
+        .and_then(Value::as_str)
+        .map(|entry| root.join(entry).to_string_lossy().into_owned());
+
+    match fs::read_to_string(&path) {
+        Ok(text) => match serde_json::from_str::<Value>(&text) {
+            Ok(json) => merge_json(&mut merged, json),
+            Err(err) => eprintln!(
+                "[shrimpl-config] Failed to parse {}: {} (using defaults)",
+                path.display(),
+                err
+            ),
         },
         Err(err) => {
             if path.exists() {
@@ -159,66 +307,224 @@ pub fn init() {
                     err
                 );
             }
+        }
+    }
+
+    let file_cfg: FileConfig = match serde_json::from_value(merged) {
+        Ok(cfg) => cfg,
+        Err(err) => {
+            eprintln!(
+                "[shrimpl-config] Invalid configuration in {} / {}: {} (using defaults)",
+                MANIFEST_FILE,
+                path.display(),
+                err
+            );
             FileConfig::default()
         }
     };
 
-    let mut guard = runtime()
-        .lock()
-        .expect("shrimpl runtime config mutex poisoned");
+    let mut guard = runtime().lock();
     guard.env_name = env_name;
     guard.values = file_cfg.values.clone().unwrap_or_default();
     guard.file = file_cfg;
+    guard.manifest_entry = manifest_entry;
 }
 
-/// Current logical environment name (e.g. "dev", "prod").
-pub fn env_name() -> String {
+/// Read `shrimpl.toml` as JSON, or an empty object when it is missing or
+/// invalid (with a warning).
+fn load_manifest(path: &Path) -> Value {
+    let empty = Value::Object(Default::default());
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) => {
+            if path.exists() {
+                eprintln!(
+                    "[shrimpl-config] Failed to read {}: {} (ignoring manifest)",
+                    path.display(),
+                    err
+                );
+            }
+            return empty;
+        }
+    };
+    match crate::dataformats::from_toml(&text) {
+        Ok(json) => json,
+        Err(err) => {
+            eprintln!(
+                "[shrimpl-config] Failed to parse {}: {} (ignoring manifest)",
+                path.display(),
+                err
+            );
+            empty
+        }
+    }
+}
+
+/// Recursively merge `over` into `base`; objects merge key by key and any
+/// other value in `over` replaces the one in `base`.
+pub fn merge_json(base: &mut Value, over: Value) {
+    match (base, over) {
+        (Value::Object(base_map), Value::Object(over_map)) => {
+            for (key, value) in over_map {
+                match base_map.get_mut(&key) {
+                    Some(existing) => merge_json(existing, value),
+                    None => {
+                        base_map.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, over) => *base = over,
+    }
+}
+
+/// Entry file named by `[project] entry` in shrimpl.toml, if any.
+pub fn manifest_entry() -> Option<String> {
+    runtime().lock().manifest_entry.clone()
+}
+
+/// SQLite database file for the ORM (`database.path`, default shrimpl.db).
+pub fn database_path() -> String {
     runtime()
         .lock()
-        .expect("shrimpl runtime config mutex poisoned")
-        .env_name
-        .clone()
+        .file
+        .database
+        .as_ref()
+        .and_then(|db| db.path.clone())
+        .unwrap_or_else(|| "shrimpl.db".to_string())
 }
 
-/// Server overrides loaded from config file, if present.
-pub fn server_section() -> Option<ServerConfigFile> {
+/// Whether write endpoints run inside a per-request ORM transaction
+/// (`database.auto_transactions`, default true).
+pub fn auto_transactions_enabled() -> bool {
     runtime()
         .lock()
-        .expect("shrimpl runtime config mutex poisoned")
         .file
-        .server
-        .clone()
+        .database
+        .as_ref()
+        .and_then(|db| db.auto_transactions)
+        .unwrap_or(true)
 }
 
-/// Auth config section, if present.
-pub fn auth_section() -> Option<AuthConfigFile> {
+/// Whether feature flag `name` is on. `SHRIMPL_FEATURE_<NAME>` (true/false,
+/// 1/0, on/off) overrides the config files; unknown flags are off.
+pub fn feature_enabled(name: &str) -> bool {
+    let env_key = format!(
+        "SHRIMPL_FEATURE_{}",
+        name.to_ascii_uppercase().replace(['-', '.'], "_")
+    );
+    if let Ok(raw) = env::var(&env_key) {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "1" | "true" | "on" | "yes" => return true,
+            "0" | "false" | "off" | "no" => return false,
+            _ => eprintln!(
+                "[shrimpl-config] Ignoring {}={:?}: expected true or false",
+                env_key, raw
+            ),
+        }
+    }
     runtime()
         .lock()
-        .expect("shrimpl runtime config mutex poisoned")
         .file
-        .auth
-        .clone()
+        .features
+        .as_ref()
+        .and_then(|features| features.get(name).copied())
+        .unwrap_or(false)
+}
+
+/// Current logical environment name (e.g. "dev", "prod").
+pub fn env_name() -> String {
+    runtime().lock().env_name.clone()
+}
+
+/// Server overrides loaded from config file, if present.
+pub fn server_section() -> Option<ServerConfigFile> {
+    runtime().lock().file.server.clone()
+}
+
+/// Auth config section, if present.
+pub fn auth_section() -> Option<AuthConfigFile> {
+    runtime().lock().file.auth.clone()
 }
 
 /// Types config section, if present.
-#[allow(dead_code)]
 pub fn types_section() -> Option<TypesConfigFile> {
+    runtime().lock().file.types.clone()
+}
+
+/// Validation config section, if present.
+pub fn validation_section() -> Option<ValidationConfigFile> {
+    runtime().lock().file.validation.clone()
+}
+
+/// Data directory for local file helpers, if configured.
+pub fn data_dir() -> Option<String> {
+    runtime().lock().file.data_dir.clone()
+}
+
+/// CSV type inference mode (`csv.inference`), if configured.
+pub fn csv_inference() -> Option<String> {
     runtime()
         .lock()
-        .expect("shrimpl runtime config mutex poisoned")
         .file
-        .types
-        .clone()
+        .csv
+        .as_ref()
+        .and_then(|c| c.inference.clone())
 }
 
-/// Validation config section, if present.
-pub fn validation_section() -> Option<ValidationConfigFile> {
+/// TTL for cached openai_chat responses, if caching is enabled.
+pub fn openai_cache_ttl() -> Option<u64> {
+    runtime()
+        .lock()
+        .file
+        .openai
+        .as_ref()
+        .and_then(|o| o.cache_ttl)
+        .filter(|ttl| *ttl > 0)
+}
+
+/// Cap on OpenAI API calls per rolling minute, if configured.
+pub fn openai_max_calls_per_min() -> Option<u32> {
     runtime()
         .lock()
-        .expect("shrimpl runtime config mutex poisoned")
         .file
-        .validation
+        .openai
+        .as_ref()
+        .and_then(|o| o.max_calls_per_min)
+        .filter(|max| *max > 0)
+}
+
+/// Outbound HTTP client options (defaults when the section is absent).
+pub fn http_client_section() -> HttpClientConfigFile {
+    runtime()
+        .lock()
+        .file
+        .http_client
         .clone()
+        .unwrap_or_default()
+}
+
+/// Global per-IP rate limit as (max_requests, window_secs), if configured.
+pub fn server_rate_limit() -> Option<(u32, u32)> {
+    runtime()
+        .lock()
+        .file
+        .server
+        .as_ref()
+        .and_then(|s| s.rate_limit.as_ref())
+        .filter(|rl| rl.max_requests > 0 && rl.window_secs > 0)
+        .map(|rl| (rl.max_requests, rl.window_secs))
+}
+
+/// `config.server.trusted_proxies` entries, unparsed; empty when unset.
+pub fn server_trusted_proxies() -> Vec<String> {
+    runtime()
+        .lock()
+        .file
+        .server
+        .as_ref()
+        .and_then(|s| s.trusted_proxies.clone())
+        .unwrap_or_default()
 }
 
 /// Resolve a logical secret name to an environment-variable key using the
@@ -226,7 +532,6 @@ pub fn validation_section() -> Option<ValidationConfigFile> {
 pub fn secret_env_from_file(logical: &str) -> Option<String> {
     runtime()
         .lock()
-        .expect("shrimpl runtime config mutex poisoned")
         .file
         .secrets
         .as_ref()
@@ -234,32 +539,71 @@ pub fn secret_env_from_file(logical: &str) -> Option<String> {
         .cloned()
 }
 
-/// Get a configuration value, if defined.
-pub fn get_value(key: &str) -> Option<Value> {
+/// Record the program's `secret` declarations so config references
+/// (e.g. `auth.jwt_secret_ref`) can resolve them. Called at server startup.
+pub fn register_secret_decls(decls: &[crate::parser::ast::SecretDecl]) {
+    runtime().lock().declared_secrets = decls
+        .iter()
+        .map(|d| (d.name.clone(), d.key.clone()))
+        .collect();
+}
+
+/// Env var key for a logical secret: `secrets.env` in the config file first,
+/// then the program's `secret` declarations (`decls`, or the registered ones
+/// when None), then the logical name itself.
+pub fn secret_env_key(logical: &str, decls: Option<&[crate::parser::ast::SecretDecl]>) -> String {
+    if let Some(key) = secret_env_from_file(logical) {
+        return key;
+    }
+    let declared = match decls {
+        Some(decls) => decls
+            .iter()
+            .find(|d| d.name == logical)
+            .map(|d| d.key.clone()),
+        None => runtime().lock().declared_secrets.get(logical).cloned(),
+    };
+    declared.unwrap_or_else(|| logical.to_string())
+}
+
+/// Whether in-process secret overrides may be set. Only the "dev" and
+/// "test" environments allow it (an uninitialized config counts as dev).
+pub fn secret_overrides_allowed() -> bool {
+    matches!(env_name().as_str(), "" | "dev" | "test")
+}
+
+/// Override a logical secret in-process without touching the environment.
+pub fn set_secret_override(logical: &str, value: &str) {
     runtime()
         .lock()
-        .expect("shrimpl runtime config mutex poisoned")
-        .values
-        .get(key)
-        .cloned()
+        .secret_overrides
+        .insert(logical.to_string(), value.to_string());
+}
+
+/// Resolve a logical secret's value: an in-process override first, then the
+/// env var named by `secret_env_key`.
+pub fn secret_value(
+    logical: &str,
+    decls: Option<&[crate::parser::ast::SecretDecl]>,
+) -> Option<String> {
+    if let Some(v) = runtime().lock().secret_overrides.get(logical) {
+        return Some(v.clone());
+    }
+    std::env::var(secret_env_key(logical, decls)).ok()
+}
+
+/// Get a configuration value, if defined.
+pub fn get_value(key: &str) -> Option<Value> {
+    runtime().lock().values.get(key).cloned()
 }
 
 /// Set or override a configuration value at runtime.
 pub fn set_value(key: &str, value: Value) {
-    runtime()
-        .lock()
-        .expect("shrimpl runtime config mutex poisoned")
-        .values
-        .insert(key.to_string(), value);
+    runtime().lock().values.insert(key.to_string(), value);
 }
 
 /// Determine whether a key exists in configuration.
 pub fn has_value(key: &str) -> bool {
-    runtime()
-        .lock()
-        .expect("shrimpl runtime config mutex poisoned")
-        .values
-        .contains_key(key)
+    runtime().lock().values.contains_key(key)
 }
 
 /// Apply server overrides from configuration onto a parsed Program.
@@ -274,17 +618,63 @@ pub fn apply_server_to_program(program: &mut crate::parser::ast::Program) {
     }
 }
 
-/// Helper: load the JWT secret from the configured env var, if any.
+/// Helper: load the JWT secret, from the secret named by `auth.jwt_secret_ref`
+/// or else the env var named by `auth.jwt_secret_env`, if configured.
 pub fn jwt_secret_from_env() -> Option<String> {
-    let guard = runtime()
-        .lock()
-        .expect("shrimpl runtime config mutex poisoned");
-    if let Some(auth) = &guard.file.auth {
-        if let Some(env_key) = &auth.jwt_secret_env {
-            return std::env::var(env_key).ok();
-        }
+    let auth = auth_section()?;
+    if let Some(logical) = &auth.jwt_secret_ref {
+        return secret_value(logical, None);
     }
-    None
+    auth.jwt_secret_env
+        .as_ref()
+        .and_then(|env_key| std::env::var(env_key).ok())
+}
+
+/// Whether protected paths use HTTP Basic auth (`auth.mode = "basic"`)
+/// instead of JWTs.
+pub fn auth_uses_basic() -> bool {
+    auth_section()
+        .and_then(|a| a.mode)
+        .map(|m| m.eq_ignore_ascii_case("basic"))
+        .unwrap_or(false)
+}
+
+/// Whether credentials presented on unprotected paths are verified and bound
+/// (`auth.verify_if_present`).
+pub fn auth_verify_if_present() -> bool {
+    auth_section()
+        .and_then(|a| a.verify_if_present)
+        .unwrap_or(false)
+}
+
+/// Helper: load a basic-auth user's password from the env var mapped to it in
+/// `auth.basic.users`. None for unknown users or unset env vars.
+pub fn basic_password_from_env(username: &str) -> Option<String> {
+    let guard = runtime().lock();
+    let env_key = guard
+        .file
+        .auth
+        .as_ref()
+        .and_then(|a| a.basic.as_ref())
+        .and_then(|b| b.users.get(username))?;
+    std::env::var(env_key).ok()
+}
+
+/// Whether a Shrimpl endpoint path requires a valid JWT, based on the
+/// `auth.protected_paths` / `auth.allow_missing_on` prefixes.
+pub fn path_requires_auth(path: &str) -> bool {
+    let auth = match auth_section() {
+        Some(a) => a,
+        None => return false,
+    };
+
+    let allow_on = auth.allow_missing_on.unwrap_or_default();
+    if allow_on.iter().any(|p| path.starts_with(p)) {
+        return false;
+    }
+
+    let protected = auth.protected_paths.unwrap_or_default();
+    protected.iter().any(|p| path.starts_with(p))
 }
 
 /// Helper: get validation schema for a given Shrimpl endpoint path, if defined.
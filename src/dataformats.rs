@@ -0,0 +1,1369 @@
+// src/dataformats.rs
+//
+// YAML and TOML conversion to and from `serde_json::Value`.
+//
+// Neither format has a crate in our dependency tree, so this is a small
+// hand-written implementation of the parts configs and API payloads use:
+//
+// - YAML: block mappings and sequences, flow `[...]` / `{...}`, plain,
+//   single- and double-quoted scalars, `|` / `>` block scalars, comments.
+//   Anchors, aliases, tags and multi-document streams are rejected.
+// - TOML: tables, arrays of tables, dotted and quoted keys, basic/literal
+//   (and multi-line) strings, integers, floats, booleans, arrays and inline
+//   tables. Dates and times are kept as strings.
+//
+// Errors carry a line number so they can be shown to users as-is.
+
+use serde_json::{Map, Number, Value};
+use std::collections::HashSet;
+
+// ---------- YAML output ----------
+
+/// Render `value` as a block-style YAML document.
+pub fn to_yaml(value: &Value) -> String {
+    let mut out = String::new();
+    match value {
+        Value::Object(map) if !map.is_empty() => write_yaml_map(map, 0, false, &mut out),
+        Value::Array(items) if !items.is_empty() => write_yaml_seq(items, 0, &mut out),
+        scalar => {
+            out.push_str(&yaml_inline(scalar));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Write `key: value` lines at `indent`. With `inline_first`, the first key
+/// continues the current line (the `- key: value` form in sequences).
+fn write_yaml_map(map: &Map<String, Value>, indent: usize, inline_first: bool, out: &mut String) {
+    for (idx, (key, value)) in map.iter().enumerate() {
+        if idx > 0 || !inline_first {
+            out.push_str(&" ".repeat(indent));
+        }
+        out.push_str(&yaml_string(key));
+        out.push(':');
+        write_yaml_child(value, indent, out);
+    }
+}
+
+fn write_yaml_seq(items: &[Value], indent: usize, out: &mut String) {
+    for item in items {
+        out.push_str(&" ".repeat(indent));
+        out.push('-');
+        match item {
+            Value::Object(map) if !map.is_empty() => {
+                out.push(' ');
+                write_yaml_map(map, indent + 2, true, out);
+            }
+            Value::Array(inner) if !inner.is_empty() => {
+                out.push('\n');
+                write_yaml_seq(inner, indent + 2, out);
+            }
+            scalar => {
+                out.push(' ');
+                out.push_str(&yaml_inline(scalar));
+                out.push('\n');
+            }
+        }
+    }
+}
+
+/// Write the value that follows `key:`, nesting collections one level in.
+fn write_yaml_child(value: &Value, indent: usize, out: &mut String) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            out.push('\n');
+            write_yaml_map(map, indent + 2, false, out);
+        }
+        Value::Array(items) if !items.is_empty() => {
+            out.push('\n');
+            write_yaml_seq(items, indent + 2, out);
+        }
+        scalar => {
+            out.push(' ');
+            out.push_str(&yaml_inline(scalar));
+            out.push('\n');
+        }
+    }
+}
+
+/// Scalars and empty collections, which always fit on one line.
+fn yaml_inline(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => yaml_string(s),
+        Value::Array(_) => "[]".to_string(),
+        Value::Object(_) => "{}".to_string(),
+    }
+}
+
+/// A string as a plain scalar when that reads back unchanged, otherwise as
+/// a double-quoted one (JSON string syntax is valid YAML).
+fn yaml_string(s: &str) -> String {
+    let plain_ok = !s.is_empty()
+        && s.trim() == s
+        && !s.starts_with(|c: char| "-?:,[]{}#&*!|>'\"%@`".contains(c))
+        && !s.chars().any(|c| c.is_control() || "[]{},".contains(c))
+        && !s.contains(": ")
+        && !s.contains(" #")
+        && !s.ends_with(':')
+        && matches!(yaml_plain_scalar(s), Value::String(_));
+    if plain_ok {
+        s.to_string()
+    } else {
+        Value::String(s.to_string()).to_string()
+    }
+}
+
+// ---------- YAML input ----------
+
+/// Parse a single YAML document into JSON.
+pub fn from_yaml(text: &str) -> Result<Value, String> {
+    let mut parser = YamlParser {
+        lines: text.lines().collect(),
+        pos: 0,
+        pending: None,
+    };
+
+    // An optional `---` may open the document.
+    if let Some((_, content, _)) = parser.peek()? {
+        if content == "---" {
+            parser.advance();
+        }
+    }
+
+    let value = match parser.peek()? {
+        Some((indent, _, _)) => parser.parse_block(indent)?,
+        None => Value::Null,
+    };
+
+    if let Some((_, content, line_no)) = parser.peek()? {
+        return Err(if content == "---" {
+            format!(
+                "line {}: multiple YAML documents are not supported",
+                line_no
+            )
+        } else {
+            format!("line {}: unexpected '{}'", line_no, content)
+        });
+    }
+    Ok(value)
+}
+
+struct YamlParser<'a> {
+    lines: Vec<&'a str>,
+    pos: usize,
+    /// A node that starts mid-line, after `- ` (column, text, line number).
+    pending: Option<(usize, String, usize)>,
+}
+
+impl YamlParser<'_> {
+    /// The next line with content as (indent, text without comment, line
+    /// number). Blank and comment-only lines are skipped; `...` ends the
+    /// document.
+    fn peek(&mut self) -> Result<Option<(usize, String, usize)>, String> {
+        if let Some(pending) = &self.pending {
+            return Ok(Some(pending.clone()));
+        }
+        while self.pos < self.lines.len() {
+            let raw = self.lines[self.pos];
+            let line_no = self.pos + 1;
+            let body = raw.trim_start_matches(' ');
+            if body.starts_with('\t') {
+                return Err(format!(
+                    "line {}: tabs are not allowed for indentation",
+                    line_no
+                ));
+            }
+            let content = strip_yaml_comment(body).trim_end();
+            if content.is_empty() {
+                self.pos += 1;
+                continue;
+            }
+            if content == "..." {
+                self.pos = self.lines.len();
+                return Ok(None);
+            }
+            return Ok(Some((raw.len() - body.len(), content.to_string(), line_no)));
+        }
+        Ok(None)
+    }
+
+    fn advance(&mut self) {
+        if self.pending.take().is_none() {
+            self.pos += 1;
+        }
+    }
+
+    /// Parse the node whose first line sits at `indent`.
+    fn parse_block(&mut self, indent: usize) -> Result<Value, String> {
+        let Some((_, content, line_no)) = self.peek()? else {
+            return Ok(Value::Null);
+        };
+        if is_seq_item(&content) {
+            self.parse_seq(indent)
+        } else if find_map_colon(&content).is_some() {
+            self.parse_map(indent)
+        } else {
+            self.advance();
+            self.parse_inline(&content, indent, line_no)
+        }
+    }
+
+    fn parse_map(&mut self, indent: usize) -> Result<Value, String> {
+        let mut map = Map::new();
+        while let Some((line_indent, content, line_no)) = self.peek()? {
+            if line_indent < indent
+                || (line_indent == indent && is_seq_item(&content))
+                || content == "---"
+            {
+                break;
+            }
+            if line_indent > indent {
+                return Err(format!("line {}: unexpected indentation", line_no));
+            }
+            let colon = find_map_colon(&content)
+                .ok_or_else(|| format!("line {}: expected 'key: value'", line_no))?;
+            let key = yaml_key(content[..colon].trim(), line_no)?;
+            let rest = content[colon + 1..].trim().to_string();
+            self.advance();
+
+            let value = self.parse_value(&rest, indent, true, line_no)?;
+            if map.insert(key.clone(), value).is_some() {
+                return Err(format!("line {}: duplicate key '{}'", line_no, key));
+            }
+        }
+        Ok(Value::Object(map))
+    }
+
+    fn parse_seq(&mut self, indent: usize) -> Result<Value, String> {
+        let mut items = Vec::new();
+        while let Some((line_indent, content, line_no)) = self.peek()? {
+            if line_indent < indent {
+                break;
+            }
+            if line_indent > indent {
+                return Err(format!("line {}: unexpected indentation", line_no));
+            }
+            if !is_seq_item(&content) {
+                break;
+            }
+            let rest = content[1..].trim_start();
+            let column = indent + content.len() - rest.len();
+            let rest = rest.to_string();
+            self.advance();
+
+            let starts_collection = is_seq_item(&rest)
+                || (find_map_colon(&rest).is_some() && !rest.starts_with(['[', '{']));
+            let item = if starts_collection {
+                // `- - x` or `- key: value`: the node starts at this column.
+                self.pending = Some((column, rest, line_no));
+                self.parse_block(column)?
+            } else {
+                self.parse_value(&rest, indent, false, line_no)?
+            };
+            items.push(item);
+        }
+        Ok(Value::Array(items))
+    }
+
+    /// The value after `key:` or `- ` at `indent`. An empty `rest` means the
+    /// value (if any) is on the following, deeper-indented lines; a mapping
+    /// may also hold a sequence at its own indent.
+    fn parse_value(
+        &mut self,
+        rest: &str,
+        indent: usize,
+        in_map: bool,
+        line_no: usize,
+    ) -> Result<Value, String> {
+        if !rest.is_empty() {
+            return self.parse_inline(rest, indent, line_no);
+        }
+        match self.peek()? {
+            Some((next_indent, _, _)) if next_indent > indent => self.parse_block(next_indent),
+            Some((next_indent, content, _))
+                if in_map && next_indent == indent && is_seq_item(&content) =>
+            {
+                self.parse_seq(indent)
+            }
+            _ => Ok(Value::Null),
+        }
+    }
+
+    /// A value written on the same line: a block scalar header, a flow
+    /// collection, a quoted string, or a plain scalar.
+    fn parse_inline(&mut self, text: &str, indent: usize, line_no: usize) -> Result<Value, String> {
+        match text.chars().next() {
+            Some('|') | Some('>') => self.parse_block_scalar(text, indent, line_no),
+            Some('&') => Err(format!("line {}: YAML anchors are not supported", line_no)),
+            Some('*') => Err(format!("line {}: YAML aliases are not supported", line_no)),
+            Some('!') => Err(format!("line {}: YAML tags are not supported", line_no)),
+            Some('[') | Some('{') => {
+                // Flow collections may continue over the following lines.
+                let mut src = text.to_string();
+                while !flow_balanced(&src) {
+                    let Some((_, more, _)) = self.peek()? else {
+                        return Err(format!("line {}: unterminated flow collection", line_no));
+                    };
+                    self.advance();
+                    src.push(' ');
+                    src.push_str(&more);
+                }
+                let mut flow = FlowParser {
+                    chars: src.chars().collect(),
+                    pos: 0,
+                    line_no,
+                };
+                let value = flow.parse_value()?;
+                flow.skip_ws();
+                if flow.pos < flow.chars.len() {
+                    return Err(format!(
+                        "line {}: unexpected text after flow collection",
+                        line_no
+                    ));
+                }
+                Ok(value)
+            }
+            Some('"') | Some('\'') => {
+                let (s, tail) = yaml_quoted(text, line_no)?;
+                if !tail.trim().is_empty() {
+                    return Err(format!(
+                        "line {}: unexpected '{}' after quoted string",
+                        line_no,
+                        tail.trim()
+                    ));
+                }
+                Ok(Value::String(s))
+            }
+            _ => Ok(yaml_plain_scalar(text)),
+        }
+    }
+
+    /// `|` keeps line breaks, `>` folds lines into spaces; `-` strips the
+    /// final newline and `+` keeps all trailing ones.
+    fn parse_block_scalar(
+        &mut self,
+        header: &str,
+        indent: usize,
+        line_no: usize,
+    ) -> Result<Value, String> {
+        let literal = header.starts_with('|');
+        let chomp = header[1..].trim();
+        if !matches!(chomp, "" | "-" | "+") {
+            return Err(format!(
+                "line {}: unsupported block scalar header '{}'",
+                line_no, header
+            ));
+        }
+
+        let mut body: Vec<&str> = Vec::new();
+        let mut content_indent = None;
+        while self.pos < self.lines.len() {
+            let raw = self.lines[self.pos];
+            if raw.trim().is_empty() {
+                body.push("");
+                self.pos += 1;
+                continue;
+            }
+            let line_indent = raw.len() - raw.trim_start_matches(' ').len();
+            let min = *content_indent.get_or_insert(line_indent);
+            if line_indent <= indent || line_indent < min {
+                break;
+            }
+            body.push(&raw[min..]);
+            self.pos += 1;
+        }
+
+        let trailing = body.iter().rev().take_while(|l| l.is_empty()).count();
+        let lines = &body[..body.len() - trailing];
+
+        let mut text = String::new();
+        for (idx, line) in lines.iter().enumerate() {
+            if idx > 0 {
+                // Folding turns a single line break into a space; each
+                // blank line stands for one kept break.
+                let prev_blank = lines[idx - 1].is_empty();
+                if literal || line.is_empty() {
+                    text.push('\n');
+                } else if !prev_blank {
+                    text.push(' ');
+                }
+            }
+            text.push_str(line);
+        }
+        match chomp {
+            "-" => {}
+            "+" => text.push_str(&"\n".repeat(trailing + 1)),
+            _ if !lines.is_empty() => text.push('\n'),
+            _ => {}
+        }
+        Ok(Value::String(text))
+    }
+}
+
+fn is_seq_item(content: &str) -> bool {
+    content == "-" || content.starts_with("- ")
+}
+
+/// Drop a trailing `# comment`, ignoring `#` inside quotes or glued to a word.
+fn strip_yaml_comment(line: &str) -> &str {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut prev = ' ';
+    for (idx, c) in line.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '#' && prev.is_whitespace() => return &line[..idx],
+            None if (c == '"' || c == '\'') && (prev.is_whitespace() || "[{,:-".contains(prev)) => {
+                quote = Some(c)
+            }
+            None => {}
+        }
+        prev = c;
+    }
+    line
+}
+
+/// Byte offset of the `:` that separates a mapping key from its value, if
+/// `content` is a mapping entry.
+fn find_map_colon(content: &str) -> Option<usize> {
+    if content.starts_with(['"', '\'']) {
+        let (_, tail) = yaml_quoted(content, 0).ok()?;
+        let tail = tail.trim_start();
+        let colon = content.len() - tail.len();
+        return (tail == ":" || tail.starts_with(": ")).then_some(colon);
+    }
+    if content.starts_with(['[', '{']) {
+        return None;
+    }
+    let bytes = content.as_bytes();
+    (0..bytes.len()).find(|&i| bytes[i] == b':' && (i + 1 == bytes.len() || bytes[i + 1] == b' '))
+}
+
+fn yaml_key(raw: &str, line_no: usize) -> Result<String, String> {
+    if raw.starts_with(['"', '\'']) {
+        Ok(yaml_quoted(raw, line_no)?.0)
+    } else if raw.is_empty() {
+        Err(format!("line {}: empty mapping key", line_no))
+    } else if raw.starts_with(['&', '*', '!', '?']) {
+        Err(format!(
+            "line {}: unsupported YAML key syntax '{}'",
+            line_no, raw
+        ))
+    } else {
+        Ok(raw.to_string())
+    }
+}
+
+/// Parse a quoted scalar at the start of `s`, returning it and the rest.
+fn yaml_quoted(s: &str, line_no: usize) -> Result<(String, &str), String> {
+    let mut chars = s.char_indices();
+    let quote = match chars.next() {
+        Some((_, q @ ('"' | '\''))) => q,
+        _ => return Err(format!("line {}: expected a quoted string", line_no)),
+    };
+    let mut out = String::new();
+    while let Some((idx, c)) = chars.next() {
+        if c == quote {
+            if quote == '\'' && s[idx + 1..].starts_with('\'') {
+                chars.next();
+                out.push('\'');
+                continue;
+            }
+            return Ok((out, &s[idx + 1..]));
+        }
+        if quote == '"' && c == '\\' {
+            let Some((_, esc)) = chars.next() else { break };
+            match esc {
+                'n' => out.push('\n'),
+                't' => out.push('\t'),
+                'r' => out.push('\r'),
+                '0' => out.push('\0'),
+                'b' => out.push('\u{8}'),
+                'f' => out.push('\u{c}'),
+                '"' | '\\' | '/' | ' ' => out.push(esc),
+                'x' | 'u' | 'U' => {
+                    let len = match esc {
+                        'x' => 2,
+                        'u' => 4,
+                        _ => 8,
+                    };
+                    let hex: String = (0..len)
+                        .filter_map(|_| chars.next().map(|(_, h)| h))
+                        .collect();
+                    let ch = u32::from_str_radix(&hex, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .ok_or_else(|| {
+                            format!("line {}: invalid escape '\\{}{}'", line_no, esc, hex)
+                        })?;
+                    out.push(ch);
+                }
+                other => {
+                    return Err(format!(
+                        "line {}: unknown escape '\\{}' in string",
+                        line_no, other
+                    ))
+                }
+            }
+            continue;
+        }
+        out.push(c);
+    }
+    Err(format!("line {}: unterminated quoted string", line_no))
+}
+
+/// Resolve a plain scalar with the YAML 1.2 core schema.
+fn yaml_plain_scalar(s: &str) -> Value {
+    match s {
+        "" | "~" | "null" | "Null" | "NULL" => return Value::Null,
+        "true" | "True" | "TRUE" => return Value::Bool(true),
+        "false" | "False" | "FALSE" => return Value::Bool(false),
+        _ => {}
+    }
+    let (sign, digits) = match s.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let radix = if let Some(hex) = digits.strip_prefix("0x") {
+        Some((hex, 16))
+    } else {
+        digits.strip_prefix("0o").map(|oct| (oct, 8))
+    };
+    if let Some((body, radix)) = radix {
+        if let Ok(n) = i64::from_str_radix(body, radix) {
+            return Value::from(sign * n);
+        }
+    }
+    if !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_digit() || b == b'-' || b == b'+')
+    {
+        if let Ok(n) = s.parse::<i64>() {
+            return Value::from(n);
+        }
+    }
+    let numeric_chars = s
+        .bytes()
+        .all(|b| b.is_ascii_digit() || b"+-.eE".contains(&b));
+    if numeric_chars && s.bytes().any(|b| b.is_ascii_digit()) {
+        if let Some(n) = s.parse::<f64>().ok().and_then(Number::from_f64) {
+            return Value::Number(n);
+        }
+    }
+    Value::String(s.to_string())
+}
+
+fn flow_balanced(src: &str) -> bool {
+    let mut depth = 0i32;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for c in src.chars() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                '[' | '{' => depth += 1,
+                ']' | '}' => depth -= 1,
+                _ => {}
+            },
+        }
+    }
+    depth <= 0 && quote.is_none()
+}
+
+/// Parser for YAML flow collections (`[a, b]`, `{k: v}`), which also
+/// accepts plain JSON.
+struct FlowParser {
+    chars: Vec<char>,
+    pos: usize,
+    line_no: usize,
+}
+
+impl FlowParser {
+    fn skip_ws(&mut self) {
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn err(&self, msg: &str) -> String {
+        format!("line {}: {} in flow collection", self.line_no, msg)
+    }
+
+    fn parse_value(&mut self) -> Result<Value, String> {
+        self.skip_ws();
+        match self.peek() {
+            Some('[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                loop {
+                    self.skip_ws();
+                    if self.peek() == Some(']') {
+                        self.pos += 1;
+                        return Ok(Value::Array(items));
+                    }
+                    items.push(self.parse_value()?);
+                    self.skip_ws();
+                    match self.peek() {
+                        Some(',') => self.pos += 1,
+                        Some(']') => {}
+                        _ => return Err(self.err("expected ',' or ']'")),
+                    }
+                }
+            }
+            Some('{') => {
+                self.pos += 1;
+                let mut map = Map::new();
+                loop {
+                    self.skip_ws();
+                    if self.peek() == Some('}') {
+                        self.pos += 1;
+                        return Ok(Value::Object(map));
+                    }
+                    let key = match self.parse_scalar(true)? {
+                        Value::String(s) => s,
+                        Value::Null => String::new(),
+                        other => other.to_string(),
+                    };
+                    self.skip_ws();
+                    let value = if self.peek() == Some(':') {
+                        self.pos += 1;
+                        self.parse_value()?
+                    } else {
+                        Value::Null
+                    };
+                    map.insert(key, value);
+                    self.skip_ws();
+                    match self.peek() {
+                        Some(',') => self.pos += 1,
+                        Some('}') => {}
+                        _ => return Err(self.err("expected ',' or '}'")),
+                    }
+                }
+            }
+            _ => self.parse_scalar(false),
+        }
+    }
+
+    /// A quoted or plain scalar. Plain scalars end at `,`, `]`, `}`, and
+    /// (for keys) at a `:` followed by a space or JSON-style punctuation.
+    fn parse_scalar(&mut self, is_key: bool) -> Result<Value, String> {
+        if let Some('"' | '\'') = self.peek() {
+            let rest: String = self.chars[self.pos..].iter().collect();
+            let (s, tail) = yaml_quoted(&rest, self.line_no)?;
+            self.pos = self.chars.len() - tail.chars().count();
+            return Ok(Value::String(s));
+        }
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if ",]}".contains(c) {
+                break;
+            }
+            if c == ':' {
+                let next = self.chars.get(self.pos + 1).copied();
+                let ends = next.is_none_or(|n| n.is_whitespace() || ",]}[{\"".contains(n));
+                if is_key || ends {
+                    break;
+                }
+            }
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        Ok(yaml_plain_scalar(text.trim()))
+    }
+}
+
+// ---------- TOML output ----------
+
+/// Render a JSON object as a TOML document. TOML has no `null` and no
+/// top-level arrays or scalars, so those are errors.
+pub fn to_toml(value: &Value) -> Result<String, String> {
+    let map = value
+        .as_object()
+        .ok_or_else(|| "TOML documents must be a JSON object at the top level".to_string())?;
+    let mut out = String::new();
+    write_toml_table(map, &[], &mut out)?;
+    Ok(out)
+}
+
+fn is_table_array(value: &Value) -> bool {
+    matches!(value, Value::Array(items) if !items.is_empty() && items.iter().all(Value::is_object))
+}
+
+fn write_toml_table(
+    map: &Map<String, Value>,
+    path: &[String],
+    out: &mut String,
+) -> Result<(), String> {
+    // Plain keys must come before any [sub.table] header.
+    for (key, value) in map {
+        if !value.is_object() && !is_table_array(value) {
+            let mut key_path = path.to_vec();
+            key_path.push(key.clone());
+            out.push_str(&toml_key(key));
+            out.push_str(" = ");
+            out.push_str(&toml_inline(value, &key_path)?);
+            out.push('\n');
+        }
+    }
+
+    for (key, value) in map {
+        let mut sub_path = path.to_vec();
+        sub_path.push(key.clone());
+        let header = sub_path
+            .iter()
+            .map(|k| toml_key(k))
+            .collect::<Vec<_>>()
+            .join(".");
+        match value {
+            Value::Object(sub) => {
+                if !out.is_empty() {
+                    out.push('\n');
+                }
+                out.push_str(&format!("[{}]\n", header));
+                write_toml_table(sub, &sub_path, out)?;
+            }
+            Value::Array(items) if is_table_array(value) => {
+                for item in items {
+                    if !out.is_empty() {
+                        out.push('\n');
+                    }
+                    out.push_str(&format!("[[{}]]\n", header));
+                    if let Value::Object(sub) = item {
+                        write_toml_table(sub, &sub_path, out)?;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn toml_inline(value: &Value, path: &[String]) -> Result<String, String> {
+    Ok(match value {
+        Value::Null => {
+            return Err(format!(
+                "TOML has no null value (at key '{}')",
+                path.join(".")
+            ))
+        }
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => Value::String(s.clone()).to_string(),
+        Value::Array(items) => {
+            let parts: Result<Vec<String>, String> =
+                items.iter().map(|v| toml_inline(v, path)).collect();
+            format!("[{}]", parts?.join(", "))
+        }
+        Value::Object(map) if map.is_empty() => "{}".to_string(),
+        Value::Object(map) => {
+            let mut parts = Vec::new();
+            for (key, v) in map {
+                let mut key_path = path.to_vec();
+                key_path.push(key.clone());
+                parts.push(format!(
+                    "{} = {}",
+                    toml_key(key),
+                    toml_inline(v, &key_path)?
+                ));
+            }
+            format!("{{ {} }}", parts.join(", "))
+        }
+    })
+}
+
+fn toml_key(key: &str) -> String {
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if bare {
+        key.to_string()
+    } else {
+        Value::String(key.to_string()).to_string()
+    }
+}
+
+// ---------- TOML input ----------
+
+/// Parse a TOML document into a JSON object.
+pub fn from_toml(text: &str) -> Result<Value, String> {
+    let mut parser = TomlParser {
+        chars: text.chars().collect(),
+        pos: 0,
+        line: 1,
+    };
+    let mut root = Map::new();
+    let mut current: Vec<String> = Vec::new();
+    let mut defined: HashSet<Vec<String>> = HashSet::new();
+
+    loop {
+        parser.skip_blank_lines();
+        let Some(c) = parser.peek() else { break };
+        let line = parser.line;
+
+        if c == '[' {
+            parser.pos += 1;
+            let is_array = parser.peek() == Some('[');
+            if is_array {
+                parser.pos += 1;
+            }
+            let path = parser.parse_key()?;
+            parser.expect(']')?;
+            if is_array {
+                parser.expect(']')?;
+                // Sub-tables of the previous element may be defined again.
+                defined.retain(|p| !p.starts_with(&path));
+                let (parent, last) = path.split_at(path.len() - 1);
+                let table = toml_table_at(&mut root, parent, line)?;
+                let slot = table
+                    .entry(last[0].clone())
+                    .or_insert_with(|| Value::Array(Vec::new()));
+                match slot {
+                    Value::Array(items) if items.iter().all(Value::is_object) => {
+                        items.push(Value::Object(Map::new()))
+                    }
+                    _ => {
+                        return Err(format!(
+                            "line {}: key '{}' is not an array of tables",
+                            line,
+                            path.join(".")
+                        ))
+                    }
+                }
+            } else {
+                if !defined.insert(path.clone()) {
+                    return Err(format!(
+                        "line {}: table [{}] is defined twice",
+                        line,
+                        path.join(".")
+                    ));
+                }
+                toml_table_at(&mut root, &path, line)?;
+            }
+            current = path;
+        } else {
+            let key = parser.parse_key()?;
+            parser.skip_ws();
+            parser.expect('=')?;
+            let value = parser.parse_value()?;
+            let mut full = current.clone();
+            full.extend_from_slice(&key[..key.len() - 1]);
+            let table = toml_table_at(&mut root, &full, line)?;
+            let name = key[key.len() - 1].clone();
+            if table.insert(name, value).is_some() {
+                return Err(format!("line {}: duplicate key '{}'", line, key.join(".")));
+            }
+        }
+        parser.end_of_line()?;
+    }
+
+    Ok(Value::Object(root))
+}
+
+/// The table at `path` under `root`, creating missing tables. A path
+/// segment naming an array of tables refers to its last element.
+fn toml_table_at<'a>(
+    root: &'a mut Map<String, Value>,
+    path: &[String],
+    line: usize,
+) -> Result<&'a mut Map<String, Value>, String> {
+    let mut table = root;
+    for (idx, key) in path.iter().enumerate() {
+        let slot = table
+            .entry(key.clone())
+            .or_insert_with(|| Value::Object(Map::new()));
+        let slot = match slot {
+            Value::Array(items) => items.last_mut().filter(|v| v.is_object()),
+            other => Some(other),
+        };
+        table = slot.and_then(Value::as_object_mut).ok_or_else(|| {
+            format!(
+                "line {}: key '{}' is not a table",
+                line,
+                path[..=idx].join(".")
+            )
+        })?;
+    }
+    Ok(table)
+}
+
+struct TomlParser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl TomlParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars()
+            .enumerate()
+            .all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+    }
+
+    fn err(&self, msg: impl std::fmt::Display) -> String {
+        format!("line {}: {}", self.line, msg)
+    }
+
+    fn expect(&mut self, want: char) -> Result<(), String> {
+        self.skip_ws();
+        match self.bump() {
+            Some(c) if c == want => Ok(()),
+            Some(c) => Err(self.err(format!("expected '{}', found '{}'", want, c))),
+            None => Err(self.err(format!("expected '{}', found end of input", want))),
+        }
+    }
+
+    /// Spaces and tabs only.
+    fn skip_ws(&mut self) {
+        while matches!(self.peek(), Some(' ') | Some('\t')) {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.pos += 1;
+            }
+        }
+    }
+
+    /// Whitespace, newlines and comments (between statements and inside
+    /// arrays).
+    fn skip_blank_lines(&mut self) {
+        loop {
+            self.skip_ws();
+            self.skip_comment();
+            match self.peek() {
+                Some('\n') | Some('\r') => {
+                    self.bump();
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_ws();
+        self.skip_comment();
+        if self.peek() == Some('\r') {
+            self.pos += 1;
+        }
+        match self.peek() {
+            None => Ok(()),
+            Some('\n') => {
+                self.bump();
+                Ok(())
+            }
+            Some(c) => Err(self.err(format!("unexpected '{}' after value", c))),
+        }
+    }
+
+    /// A dotted key: `a`, `a.b`, `"quoted key".c`.
+    fn parse_key(&mut self) -> Result<Vec<String>, String> {
+        let mut parts = Vec::new();
+        loop {
+            self.skip_ws();
+            let part = match self.peek() {
+                Some('"') => self.parse_basic_string()?,
+                Some('\'') => self.parse_literal_string()?,
+                _ => {
+                    let start = self.pos;
+                    while self
+                        .peek()
+                        .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+                    {
+                        self.pos += 1;
+                    }
+                    if self.pos == start {
+                        return Err(match self.peek() {
+                            Some(c) => self.err(format!("expected a key, found '{}'", c)),
+                            None => self.err("expected a key"),
+                        });
+                    }
+                    self.chars[start..self.pos].iter().collect()
+                }
+            };
+            parts.push(part);
+            self.skip_ws();
+            if self.peek() == Some('.') {
+                self.pos += 1;
+            } else {
+                return Ok(parts);
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, String> {
+        self.skip_ws();
+        match self.peek() {
+            Some('"') if self.starts_with("\"\"\"") => {
+                self.parse_multiline_basic().map(Value::String)
+            }
+            Some('"') => self.parse_basic_string().map(Value::String),
+            Some('\'') if self.starts_with("'''") => {
+                self.parse_multiline_literal().map(Value::String)
+            }
+            Some('\'') => self.parse_literal_string().map(Value::String),
+            Some('[') => self.parse_array(),
+            Some('{') => self.parse_inline_table(),
+            Some(_) if self.starts_with("true") => {
+                self.pos += 4;
+                Ok(Value::Bool(true))
+            }
+            Some(_) if self.starts_with("false") => {
+                self.pos += 5;
+                Ok(Value::Bool(false))
+            }
+            Some(_) => self.parse_number_or_date(),
+            None => Err(self.err("expected a value")),
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Value, String> {
+        self.bump();
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank_lines();
+            if self.peek() == Some(']') {
+                self.bump();
+                return Ok(Value::Array(items));
+            }
+            items.push(self.parse_value()?);
+            self.skip_blank_lines();
+            match self.peek() {
+                Some(',') => {
+                    self.bump();
+                }
+                Some(']') => {}
+                _ => return Err(self.err("expected ',' or ']' in array")),
+            }
+        }
+    }
+
+    fn parse_inline_table(&mut self) -> Result<Value, String> {
+        self.bump();
+        let mut map = Map::new();
+        self.skip_ws();
+        if self.peek() == Some('}') {
+            self.bump();
+            return Ok(Value::Object(map));
+        }
+        loop {
+            let key = self.parse_key()?;
+            self.expect('=')?;
+            let value = self.parse_value()?;
+            let line = self.line;
+            let table = toml_table_at(&mut map, &key[..key.len() - 1], line)?;
+            if table.insert(key[key.len() - 1].clone(), value).is_some() {
+                return Err(self.err(format!("duplicate key '{}'", key.join("."))));
+            }
+            self.skip_ws();
+            match self.bump() {
+                Some(',') => {}
+                Some('}') => return Ok(Value::Object(map)),
+                _ => return Err(self.err("expected ',' or '}' in inline table")),
+            }
+        }
+    }
+
+    fn parse_escape(&mut self, out: &mut String) -> Result<(), String> {
+        let esc = self.bump().ok_or_else(|| self.err("unterminated string"))?;
+        match esc {
+            'n' => out.push('\n'),
+            't' => out.push('\t'),
+            'r' => out.push('\r'),
+            'b' => out.push('\u{8}'),
+            'f' => out.push('\u{c}'),
+            '"' | '\\' => out.push(esc),
+            'u' | 'U' => {
+                let len = if esc == 'u' { 4 } else { 8 };
+                let hex: String = (0..len).filter_map(|_| self.bump()).collect();
+                let ch = u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| self.err(format!("invalid escape '\\{}{}'", esc, hex)))?;
+                out.push(ch);
+            }
+            other => return Err(self.err(format!("unknown escape '\\{}' in string", other))),
+        }
+        Ok(())
+    }
+
+    fn parse_basic_string(&mut self) -> Result<String, String> {
+        self.bump();
+        let mut out = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(out),
+                Some('\\') => self.parse_escape(&mut out)?,
+                Some('\n') | None => return Err(self.err("unterminated string")),
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    fn parse_literal_string(&mut self) -> Result<String, String> {
+        self.bump();
+        let mut out = String::new();
+        loop {
+            match self.bump() {
+                Some('\'') => return Ok(out),
+                Some('\n') | None => return Err(self.err("unterminated string")),
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    fn parse_multiline_basic(&mut self) -> Result<String, String> {
+        self.pos += 3;
+        self.skip_leading_newline();
+        let mut out = String::new();
+        loop {
+            if self.starts_with("\"\"\"") {
+                self.pos += 3;
+                return Ok(out);
+            }
+            match self.bump() {
+                // A trailing backslash joins the next line, dropping the
+                // line break and leading whitespace.
+                Some('\\') if matches!(self.peek(), Some(' ' | '\t' | '\r' | '\n')) => {
+                    while matches!(self.peek(), Some(' ' | '\t' | '\r' | '\n')) {
+                        self.bump();
+                    }
+                }
+                Some('\\') => self.parse_escape(&mut out)?,
+                Some(c) => out.push(c),
+                None => return Err(self.err("unterminated multi-line string")),
+            }
+        }
+    }
+
+    fn parse_multiline_literal(&mut self) -> Result<String, String> {
+        self.pos += 3;
+        self.skip_leading_newline();
+        let mut out = String::new();
+        loop {
+            if self.starts_with("'''") {
+                self.pos += 3;
+                return Ok(out);
+            }
+            match self.bump() {
+                Some(c) => out.push(c),
+                None => return Err(self.err("unterminated multi-line string")),
+            }
+        }
+    }
+
+    fn skip_leading_newline(&mut self) {
+        if self.starts_with("\r\n") {
+            self.pos += 1;
+        }
+        if self.peek() == Some('\n') {
+            self.bump();
+        }
+    }
+
+    /// Integers (with `_`, `0x`, `0o`, `0b`), floats, and dates/times,
+    /// which JSON has no type for and are kept as strings.
+    fn parse_number_or_date(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || "_+-.:".contains(c))
+        {
+            self.pos += 1;
+        }
+        let mut token: String = self.chars[start..self.pos].iter().collect();
+
+        let is_date = token.len() >= 10
+            && token.as_bytes()[4] == b'-'
+            && token[..4].bytes().all(|b| b.is_ascii_digit());
+        if is_date
+            && self.peek() == Some(' ')
+            && self
+                .chars
+                .get(self.pos + 1)
+                .is_some_and(char::is_ascii_digit)
+        {
+            // `1979-05-27 07:32:00` uses a space instead of `T`.
+            self.pos += 1;
+            while self
+                .peek()
+                .is_some_and(|c| c.is_ascii_alphanumeric() || "+-.:".contains(c))
+            {
+                self.pos += 1;
+            }
+            token = self.chars[start..self.pos].iter().collect();
+        }
+        if is_date || (token.len() >= 8 && token.as_bytes().get(2) == Some(&b':')) {
+            return Ok(Value::String(token));
+        }
+
+        if token.is_empty() {
+            return Err(match self.peek() {
+                Some(c) => self.err(format!("unexpected '{}'", c)),
+                None => self.err("expected a value"),
+            });
+        }
+
+        let clean = token.replace('_', "");
+        let (sign, digits) = match clean.strip_prefix('-') {
+            Some(rest) => (-1, rest),
+            None => (1, clean.strip_prefix('+').unwrap_or(&clean)),
+        };
+        let radix = [("0x", 16), ("0o", 8), ("0b", 2)]
+            .iter()
+            .find_map(|(prefix, radix)| digits.strip_prefix(prefix).map(|d| (d, *radix)));
+        if let Some((body, radix)) = radix {
+            return i64::from_str_radix(body, radix)
+                .map(|n| Value::from(sign * n))
+                .map_err(|_| self.err(format!("invalid number '{}'", token)));
+        }
+        if let Ok(n) = clean.parse::<i64>() {
+            return Ok(Value::from(n));
+        }
+        if matches!(digits, "inf" | "nan") {
+            return Err(self.err(format!("'{}' cannot be represented in JSON", token)));
+        }
+        if digits.starts_with(|c: char| c.is_ascii_digit()) {
+            if let Some(n) = clean.parse::<f64>().ok().and_then(Number::from_f64) {
+                return Ok(Value::Number(n));
+            }
+        }
+        Err(self.err(format!("invalid value '{}'", token)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn yaml_parses() {
+        let cases = [
+            ("a: 1\nb: two", json!({"a": 1, "b": "two"})),
+            (r#"s: "x\ty""#, json!({"s": "x\ty"})),
+            ("s: 'it''s'", json!({"s": "it's"})),
+            (r#"s: "caf\u00e9 \x41""#, json!({"s": "café A"})),
+            (r#"s: "123""#, json!({"s": "123"})),
+            ("s: 'null'", json!({"s": "null"})),
+            (r#"s: "a: b""#, json!({"s": "a: b"})),
+            (
+                "n: ~\nt: true\nf: 1.5",
+                json!({"n": null, "t": true, "f": 1.5}),
+            ),
+            ("h: 0x1f\no: 0o17", json!({"h": 31, "o": 15})),
+            ("- a\n- b: 1\n  c: 2", json!(["a", {"b": 1, "c": 2}])),
+            ("f: [1, 'x', {k: v}]", json!({"f": [1, "x", {"k": "v"}]})),
+            ("f: [1,\n  2]", json!({"f": [1, 2]})),
+            ("t: |\n  one\n  two\n", json!({"t": "one\ntwo\n"})),
+            ("a: 1 # note\n# whole line\nb: 2", json!({"a": 1, "b": 2})),
+        ];
+        for (src, expected) in cases {
+            assert_eq!(from_yaml(src).unwrap(), expected, "{:?}", src);
+        }
+    }
+
+    #[test]
+    fn yaml_rejects() {
+        let cases = [
+            ("a: 1\na: 2", "duplicate"),
+            ("f: [1, 2", "unterminated"),
+            ("f: {k: v", "unterminated"),
+            (r#"s: "open"#, "unterminated quoted string"),
+            (r#"s: "\q""#, "unknown escape '\\q'"),
+            ("a: &x 1", "anchor"),
+        ];
+        for (src, expected) in cases {
+            let err = from_yaml(src).unwrap_err();
+            assert!(err.contains(expected), "{:?}: {}", src, err);
+        }
+    }
+
+    #[test]
+    fn toml_parses() {
+        let cases = [
+            ("a = 1\nb = \"two\"", json!({"a": 1, "b": "two"})),
+            (r#"s = "x\ty\u00e9""#, json!({"s": "x\tyé"})),
+            (r"s = 'C:\path'", json!({"s": "C:\\path"})),
+            ("[t]\nx.y = true", json!({"t": {"x": {"y": true}}})),
+            ("\"a b\" = 1", json!({"a b": 1})),
+            ("a = [1, 2.5, \"x\"]", json!({"a": [1, 2.5, "x"]})),
+            ("p = { x = 1, y = 2 }", json!({"p": {"x": 1, "y": 2}})),
+            (
+                "[[item]]\nn = 1\n[[item]]\nn = 2",
+                json!({"item": [{"n": 1}, {"n": 2}]}),
+            ),
+            ("d = 1979-05-27", json!({"d": "1979-05-27"})),
+        ];
+        for (src, expected) in cases {
+            assert_eq!(from_toml(src).unwrap(), expected, "{:?}", src);
+        }
+    }
+
+    #[test]
+    fn toml_rejects() {
+        let cases = [
+            ("a = 1\na = 2", "duplicate"),
+            ("[t]\n[t]", "defined twice"),
+            ("a = [1, 2", "expected ',' or ']' in array"),
+            ("a = \"open", "unterminated"),
+            ("a = \"\\q\"", "escape"),
+        ];
+        for (src, expected) in cases {
+            let err = from_toml(src).unwrap_err();
+            assert!(err.contains(expected), "{:?}: {}", src, err);
+        }
+    }
+
+    #[test]
+    fn round_trips_keep_strings_as_strings() {
+        let value = json!({
+            "num": "123",
+            "null": "null",
+            "bool": "true",
+            "colon": "a: b",
+            "hash": "x # y",
+            "quote": "it's \"q\"",
+            "lines": "one\ntwo",
+            "empty": "",
+            "list": ["1", "-", "[x]"],
+            "nested": {"n": 1, "f": 2.5, "b": false},
+            "tables": [{"k": "v"}, {"k": "w"}],
+        });
+
+        assert_eq!(from_yaml(&to_yaml(&value)).unwrap(), value);
+        assert_eq!(from_toml(&to_toml(&value).unwrap()).unwrap(), value);
+    }
+}
@@ -1,20 +1,19 @@
 // src/docs.rs
 //
-// Shrimpl API Studio: schema, diagnostics, and HTML UI.
+// Shrimpl API Studio: schema, diagnostics, Markdown docs, and HTML UI.
 
-use crate::ast::{Body, Expr, Method, Program};
+use crate::ast::{Body, Expr, Program, TestStep};
+use crate::config;
 use serde_json::{json, Value};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
 
 pub fn build_schema(program: &Program) -> Value {
     let endpoints: Vec<Value> = program
         .endpoints
         .iter()
         .map(|ep| {
-            let method_str = match ep.method {
-                Method::Get => "GET",
-                Method::Post => "POST",
-            };
+            let method_str = ep.method.as_str();
             let body_kind = match ep.body {
                 Body::TextExpr(_) => "text",
                 Body::JsonRaw(_) => "json",
@@ -23,13 +22,58 @@ pub fn build_schema(program: &Program) -> Value {
                 "method": method_str,
                 "path": ep.path,
                 "bodyKind": body_kind,
+                "doc": ep.doc,
+            })
+        })
+        .collect();
+
+    // Functions and models keep source order (Program uses IndexMap).
+    let functions: Vec<Value> = program
+        .functions
+        .values()
+        .map(|func| json!({ "name": func.name, "params": func.params, "doc": func.doc }))
+        .collect();
+
+    let models: Vec<Value> = program
+        .models
+        .values()
+        .map(|model| {
+            let fields: Vec<Value> = model
+                .fields
+                .iter()
+                .map(|f| {
+                    let mut field = json!({
+                        "name": f.name,
+                        "type": f.ty,
+                        "primaryKey": f.is_primary_key,
+                        "optional": f.is_optional,
+                    });
+                    if let Some(min) = f.min {
+                        field["minimum"] = json!(min);
+                    }
+                    if let Some(max) = f.max {
+                        field["maximum"] = json!(max);
+                    }
+                    if let Some(pattern) = &f.pattern {
+                        field["pattern"] = json!(pattern);
+                    }
+                    field
+                })
+                .collect();
+            json!({
+                "name": model.name,
+                "table": model.table_name,
+                "doc": model.doc,
+                "fields": fields,
             })
         })
         .collect();
 
     json!({
         "server": { "port": program.server.port },
-        "endpoints": endpoints
+        "endpoints": endpoints,
+        "functions": functions,
+        "models": models
     })
 }
 
@@ -38,17 +82,15 @@ pub fn build_schema(program: &Program) -> Value {
 /// - unused parameters in functions
 /// - unused parameters in methods
 /// - duplicate (method, path) endpoint definitions
+/// - functions/methods that always recurse (no if/else base case)
 pub fn build_diagnostics(program: &Program) -> Value {
     let mut warnings = Vec::<Value>::new();
-    let errors: Vec<Value> = Vec::new();
+    let mut errors: Vec<Value> = Vec::new();
 
     // 1) Duplicate endpoints (same method + path)
     let mut seen = std::collections::HashSet::<(String, String)>::new();
     for ep in &program.endpoints {
-        let m = match ep.method {
-            Method::Get => "GET".to_string(),
-            Method::Post => "POST".to_string(),
-        };
+        let m = ep.method.as_str().to_string();
         let key = (m.clone(), ep.path.clone());
         if !seen.insert(key.clone()) {
             warnings.push(json!({
@@ -126,12 +168,375 @@ pub fn build_diagnostics(program: &Program) -> Value {
         }
     }
 
+    // 5) Functions/methods that always call themselves (directly or through
+    // a cycle) with no branch that can stop the recursion.
+    warnings.extend(unguarded_recursion_warnings(program));
+
+    // 6) ORM builtins naming a model that is never declared. Declarations
+    // may appear anywhere in the file, so this only fires for real typos.
+    errors.extend(unknown_model_errors(program));
+
     json!({
         "errors": errors,
         "warnings": warnings,
     })
 }
 
+/// Warn about call cycles made only of *unguarded* calls: calls that run on
+/// every evaluation of the caller, i.e. not inside an if/elif/else branch, a
+/// loop body, or a catch block. Such a cycle can never terminate.
+///
+/// Nodes are function names and `Class.method` names. Each cycle is reported
+/// once, on its first member in source order.
+fn unguarded_recursion_warnings(program: &Program) -> Vec<Value> {
+    // (node, scope, unguarded callees) in source order
+    let mut nodes: Vec<(String, &str, Vec<String>)> = Vec::new();
+    for func in program.functions.values() {
+        let mut calls = Vec::new();
+        collect_unguarded_calls(&func.body, program, &mut calls);
+        nodes.push((func.name.clone(), "function", calls));
+    }
+    for class in program.classes.values() {
+        for method in class.methods.values() {
+            let mut calls = Vec::new();
+            collect_unguarded_calls(&method.body, program, &mut calls);
+            nodes.push((format!("{}.{}", class.name, method.name), "method", calls));
+        }
+    }
+
+    let edges: HashMap<&str, &[String]> = nodes
+        .iter()
+        .map(|(name, _, calls)| (name.as_str(), calls.as_slice()))
+        .collect();
+
+    let mut reported = HashSet::<Vec<String>>::new();
+    let mut warnings = Vec::new();
+    for (name, scope, _) in &nodes {
+        let cycle = match find_cycle(name, &edges) {
+            Some(c) => c,
+            None => continue,
+        };
+
+        let mut members = cycle.clone();
+        members.sort();
+        members.dedup();
+        if !reported.insert(members) {
+            continue;
+        }
+
+        let what = if *scope == "method" {
+            "Method"
+        } else {
+            "Function"
+        };
+        let how = if cycle.len() == 2 {
+            "always calls itself".to_string()
+        } else {
+            format!("always recurses via {}", cycle.join(" -> "))
+        };
+        warnings.push(json!({
+            "kind": "warning",
+            "scope": scope,
+            "name": name,
+            "message": format!(
+                "{} '{}' {} with no if/else branch to stop it; this recursion never ends",
+                what, name, how
+            ),
+        }));
+    }
+    warnings
+}
+
+/// Shortest path `start -> ... -> start` over unguarded call edges, if any.
+fn find_cycle(start: &str, edges: &HashMap<&str, &[String]>) -> Option<Vec<String>> {
+    let mut parent = HashMap::<&str, &str>::new();
+    let mut queue = std::collections::VecDeque::from([start]);
+    while let Some(node) = queue.pop_front() {
+        for next in edges.get(node).copied().unwrap_or_default() {
+            if next == start {
+                // Walk back from `node` to `start`, then read it forwards.
+                let mut chain = Vec::new();
+                let mut cur = node;
+                while cur != start {
+                    chain.push(cur.to_string());
+                    cur = parent[cur];
+                }
+                chain.reverse();
+                let mut path = vec![start.to_string()];
+                path.extend(chain);
+                path.push(start.to_string());
+                return Some(path);
+            }
+            if !parent.contains_key(next.as_str()) {
+                parent.insert(next.as_str(), node);
+                queue.push_back(next.as_str());
+            }
+        }
+    }
+    None
+}
+
+/// Collect user-defined functions/methods that `expr` calls on every
+/// evaluation. Branch bodies, loop bodies and catch blocks may not run, so
+/// calls inside them are skipped; conditions, loop counts and finally blocks
+/// always run.
+fn collect_unguarded_calls(expr: &Expr, program: &Program, out: &mut Vec<String>) {
+    match expr {
+        Expr::Int(_)
+        | Expr::Number(_)
+        | Expr::Str(_)
+        | Expr::Bool(_)
+        | Expr::Var(_)
+        | Expr::ClassConst { .. } => {}
+        Expr::Neg(operand) | Expr::Not(operand) => collect_unguarded_calls(operand, program, out),
+        Expr::List(items) => {
+            for e in items {
+                collect_unguarded_calls(e, program, out);
+            }
+        }
+        Expr::Map(entries) => {
+            for (_k, v) in entries {
+                collect_unguarded_calls(v, program, out);
+            }
+        }
+        // and/or evaluate both operands, so neither side is guarded.
+        Expr::Binary { left, right, .. }
+        | Expr::Index {
+            target: left,
+            index: right,
+        } => {
+            collect_unguarded_calls(left, program, out);
+            collect_unguarded_calls(right, program, out);
+        }
+        Expr::Call { name, args } => {
+            for a in args {
+                collect_unguarded_calls(a, program, out);
+            }
+            if program.functions.contains_key(name) {
+                out.push(name.clone());
+            }
+        }
+        Expr::MethodCall {
+            class_name,
+            method_name,
+            args,
+        } => {
+            for a in args {
+                collect_unguarded_calls(a, program, out);
+            }
+            let defined = program
+                .classes
+                .get(class_name)
+                .map(|c| c.methods.contains_key(method_name))
+                .unwrap_or(false);
+            if defined {
+                out.push(format!("{}.{}", class_name, method_name));
+            }
+        }
+        Expr::If { branches, .. } => {
+            // Only the first condition always runs.
+            if let Some((cond, _)) = branches.first() {
+                collect_unguarded_calls(cond, program, out);
+            }
+        }
+        Expr::Repeat {
+            count, accumulator, ..
+        } => {
+            collect_unguarded_calls(count, program, out);
+            if let Some((_, init)) = accumulator {
+                collect_unguarded_calls(init, program, out);
+            }
+        }
+        Expr::While {
+            cond, accumulator, ..
+        } => {
+            collect_unguarded_calls(cond, program, out);
+            if let Some((_, init)) = accumulator {
+                collect_unguarded_calls(init, program, out);
+            }
+        }
+        Expr::Try {
+            try_body,
+            finally_body,
+            ..
+        } => {
+            collect_unguarded_calls(try_body, program, out);
+            if let Some(finally_expr) = finally_body {
+                collect_unguarded_calls(finally_expr, program, out);
+            }
+        }
+    }
+}
+
+/// Builtins whose first argument is a model name.
+const MODEL_BUILTINS: &[&str] = &[
+    "orm_insert",
+    "orm_find_by_id",
+    "db_insert_many",
+    "db_count",
+    "db_count_where",
+    "db_update",
+    "db_delete",
+    "db_all",
+    "db_where",
+    "db_aggregate",
+    "db_stream",
+];
+
+/// Report ORM builtin calls whose literal model name has no `model`
+/// declaration, instead of leaving them to fail at request time.
+fn unknown_model_errors(program: &Program) -> Vec<Value> {
+    let mut bodies: Vec<(&str, String, &Expr)> = Vec::new();
+    for ep in &program.endpoints {
+        if let Body::TextExpr(ref expr) = ep.body {
+            bodies.push(("endpoint", ep.path.clone(), expr));
+        }
+    }
+    for func in program.functions.values() {
+        bodies.push(("function", func.name.clone(), &func.body));
+    }
+    for class in program.classes.values() {
+        for method in class.methods.values() {
+            bodies.push((
+                "method",
+                format!("{}.{}", class.name, method.name),
+                &method.body,
+            ));
+        }
+        for (name, expr) in &class.constants {
+            bodies.push(("constant", format!("{}.{}", class.name, name), expr));
+        }
+    }
+    for test in &program.tests {
+        for step in &test.steps {
+            let expr = match step {
+                TestStep::Let { expr, .. } => expr,
+                TestStep::Assert(assertion) => &assertion.expr,
+            };
+            bodies.push(("test", test.name.clone(), expr));
+        }
+        for expr in test.cases.iter().chain(&test.teardown) {
+            bodies.push(("test", test.name.clone(), expr));
+        }
+    }
+
+    let mut errors = Vec::new();
+    for (scope, name, expr) in bodies {
+        let mut refs = Vec::new();
+        collect_model_refs(expr, &mut refs);
+        for (builtin, model) in refs {
+            if !program.models.contains_key(&model) {
+                errors.push(json!({
+                    "kind": "error",
+                    "scope": scope,
+                    "name": name,
+                    "message": format!(
+                        "{}(\"{}\", ...) refers to unknown model '{}'; declare it with 'model {}:'",
+                        builtin, model, model, model
+                    ),
+                }));
+            }
+        }
+    }
+    errors
+}
+
+// Walk expression tree and collect (builtin, model) pairs for ORM calls
+// whose model name is a string literal.
+fn collect_model_refs(expr: &Expr, out: &mut Vec<(String, String)>) {
+    match expr {
+        Expr::Int(_)
+        | Expr::Number(_)
+        | Expr::Str(_)
+        | Expr::Bool(_)
+        | Expr::Var(_)
+        | Expr::ClassConst { .. } => {}
+        Expr::Neg(operand) | Expr::Not(operand) => collect_model_refs(operand, out),
+        Expr::List(items) => {
+            for e in items {
+                collect_model_refs(e, out);
+            }
+        }
+        Expr::Map(entries) => {
+            for (_k, v) in entries {
+                collect_model_refs(v, out);
+            }
+        }
+        Expr::Binary { left, right, .. }
+        | Expr::Index {
+            target: left,
+            index: right,
+        } => {
+            collect_model_refs(left, out);
+            collect_model_refs(right, out);
+        }
+        Expr::Call { name, args } => {
+            if MODEL_BUILTINS.contains(&name.as_str()) {
+                if let Some(Expr::Str(model)) = args.first() {
+                    out.push((name.clone(), model.clone()));
+                }
+            }
+            for a in args {
+                collect_model_refs(a, out);
+            }
+        }
+        Expr::MethodCall { args, .. } => {
+            for a in args {
+                collect_model_refs(a, out);
+            }
+        }
+        Expr::If {
+            branches,
+            else_branch,
+        } => {
+            for (cond, body) in branches {
+                collect_model_refs(cond, out);
+                collect_model_refs(body, out);
+            }
+            if let Some(else_expr) = else_branch {
+                collect_model_refs(else_expr, out);
+            }
+        }
+        Expr::Repeat {
+            count,
+            body,
+            accumulator,
+            ..
+        } => {
+            collect_model_refs(count, out);
+            collect_model_refs(body, out);
+            if let Some((_, init)) = accumulator {
+                collect_model_refs(init, out);
+            }
+        }
+        Expr::While {
+            cond,
+            body,
+            accumulator,
+        } => {
+            collect_model_refs(cond, out);
+            collect_model_refs(body, out);
+            if let Some((_, init)) = accumulator {
+                collect_model_refs(init, out);
+            }
+        }
+        Expr::Try {
+            try_body,
+            catch_body,
+            finally_body,
+            ..
+        } => {
+            collect_model_refs(try_body, out);
+            if let Some(catch_expr) = catch_body {
+                collect_model_refs(catch_expr, out);
+            }
+            if let Some(finally_expr) = finally_body {
+                collect_model_refs(finally_expr, out);
+            }
+        }
+    }
+}
+
 // Walk expression tree and collect variable names.
 fn collect_vars_expr(expr: &Expr, out: &mut HashSet<String>) {
     match expr {
@@ -140,8 +545,12 @@ fn collect_vars_expr(expr: &Expr, out: &mut HashSet<String>) {
             out.insert(name.clone());
         }
 
-        // Literals: they don't contain variable references
-        Expr::Number(_) | Expr::Str(_) | Expr::Bool(_) => {}
+        // Literals and class constants: they don't contain variable references
+        Expr::Int(_) | Expr::Number(_) | Expr::Str(_) | Expr::Bool(_) | Expr::ClassConst { .. } => {
+        }
+
+        // Unary minus / not – walk the operand
+        Expr::Neg(operand) | Expr::Not(operand) => collect_vars_expr(operand, out),
 
         // List literal – walk each element
         Expr::List(items) => {
@@ -158,7 +567,11 @@ fn collect_vars_expr(expr: &Expr, out: &mut HashSet<String>) {
         }
 
         // Binary operator: recurse into both sides
-        Expr::Binary { left, right, .. } => {
+        Expr::Binary { left, right, .. }
+        | Expr::Index {
+            target: left,
+            index: right,
+        } => {
             collect_vars_expr(left, out);
             collect_vars_expr(right, out);
         }
@@ -192,9 +605,30 @@ fn collect_vars_expr(expr: &Expr, out: &mut HashSet<String>) {
         }
 
         // Repeat: walk repeat count and body expression
-        Expr::Repeat { count, body } => {
+        Expr::Repeat {
+            count,
+            body,
+            accumulator,
+            ..
+        } => {
             collect_vars_expr(count, out);
             collect_vars_expr(body, out);
+            if let Some((_, init)) = accumulator {
+                collect_vars_expr(init, out);
+            }
+        }
+
+        // While: walk loop condition, body and accumulator init
+        Expr::While {
+            cond,
+            body,
+            accumulator,
+        } => {
+            collect_vars_expr(cond, out);
+            collect_vars_expr(body, out);
+            if let Some((_, init)) = accumulator {
+                collect_vars_expr(init, out);
+            }
         }
 
         // Try expression: walk try, catch, and finally bodies so diagnostics
@@ -216,6 +650,228 @@ fn collect_vars_expr(expr: &Expr, out: &mut HashSet<String>) {
     }
 }
 
+/// Build Markdown API docs for `shrimpl doc`:
+/// - one section per endpoint (doc comment, method, path, params, validation, auth)
+/// - models with their fields
+/// - functions and class methods
+pub fn build_markdown(program: &Program) -> String {
+    let schema = build_schema(program);
+    let mut out = String::new();
+
+    let scheme = if program.server.tls { "https" } else { "http" };
+    let _ = writeln!(out, "# API Reference");
+    let _ = writeln!(out);
+    let _ = writeln!(
+        out,
+        "Server: `{}://localhost:{}`",
+        scheme, program.server.port
+    );
+    let _ = writeln!(out);
+
+    // Endpoints
+    let _ = writeln!(out, "## Endpoints");
+    let _ = writeln!(out);
+
+    let endpoints = schema
+        .get("endpoints")
+        .and_then(|v| v.as_array())
+        .cloned()
+        .unwrap_or_default();
+    if endpoints.is_empty() {
+        let _ = writeln!(out, "_No endpoints defined._");
+        let _ = writeln!(out);
+    }
+
+    for (entry, ep) in endpoints.iter().zip(&program.endpoints) {
+        let method = entry.get("method").and_then(|v| v.as_str()).unwrap_or("");
+        let body_kind = entry.get("bodyKind").and_then(|v| v.as_str()).unwrap_or("");
+
+        let _ = writeln!(out, "### `{} {}`", method, ep.path);
+        let _ = writeln!(out);
+        if let Some(doc) = &ep.doc {
+            let _ = writeln!(out, "{}", doc);
+            let _ = writeln!(out);
+        }
+        let _ = writeln!(out, "- Response: {}", body_kind);
+
+        let path_params: Vec<String> = ep
+            .path
+            .split('/')
+            .filter(|p| p.starts_with(':') && p.len() > 1)
+            .map(|p| format!("`{}`", &p[1..]))
+            .collect();
+        if path_params.is_empty() {
+            let _ = writeln!(out, "- Path params: none");
+        } else {
+            let _ = writeln!(out, "- Path params: {}", path_params.join(", "));
+        }
+
+        let auth = if config::path_requires_auth(&ep.path) {
+            "JWT bearer token required"
+        } else {
+            "none"
+        };
+        let _ = writeln!(out, "- Auth: {}", auth);
+
+        match config::validation_schema_for_path(&ep.path) {
+            Some(schema) => {
+                let _ = writeln!(out, "- Validation: {}", summarize_schema(&schema));
+            }
+            None => {
+                let _ = writeln!(out, "- Validation: none");
+            }
+        }
+
+        if let Some(rl) = &ep.rate_limit {
+            let _ = writeln!(
+                out,
+                "- Rate limit: {} requests per {}s",
+                rl.max_requests, rl.window_secs
+            );
+        }
+        if let Some(ttl) = ep.cache_ttl {
+            let _ = writeln!(out, "- Cached: {}s (ETag)", ttl);
+        }
+        if let Some(envs) = &ep.envs {
+            let _ = writeln!(out, "- Environments: {}", envs.join(", "));
+        }
+        let _ = writeln!(out);
+    }
+
+    // Models
+    if !program.models.is_empty() {
+        let _ = writeln!(out, "## Models");
+        let _ = writeln!(out);
+
+        let mut names: Vec<&String> = program.models.keys().collect();
+        names.sort();
+        for name in names {
+            let model = &program.models[name];
+            let _ = writeln!(out, "### {} (table `{}`)", model.name, model.table_name);
+            let _ = writeln!(out);
+            if let Some(doc) = &model.doc {
+                let _ = writeln!(out, "{}", doc);
+                let _ = writeln!(out);
+            }
+            let _ = writeln!(out, "| Field | Type | Notes |");
+            let _ = writeln!(out, "| ----- | ---- | ----- |");
+            for field in &model.fields {
+                let mut notes = Vec::new();
+                if field.is_primary_key {
+                    notes.push("primary key".to_string());
+                }
+                if field.is_optional {
+                    notes.push("optional".to_string());
+                }
+                if let Some(min) = field.min {
+                    notes.push(format!("min {}", min));
+                }
+                if let Some(max) = field.max {
+                    notes.push(format!("max {}", max));
+                }
+                if let Some(pattern) = &field.pattern {
+                    notes.push(format!("matches `{}`", pattern));
+                }
+                let _ = writeln!(
+                    out,
+                    "| `{}` | {} | {} |",
+                    field.name,
+                    field.ty,
+                    notes.join(", ")
+                );
+            }
+            let _ = writeln!(out);
+        }
+    }
+
+    // Functions and class methods
+    if !program.functions.is_empty() || !program.classes.is_empty() {
+        let _ = writeln!(out, "## Functions");
+        let _ = writeln!(out);
+
+        let types = config::types_section();
+        let mut names: Vec<&String> = program.functions.keys().collect();
+        names.sort();
+        for name in names {
+            let func = &program.functions[name];
+            let _ = writeln!(out, "- `{}({})`", func.name, func.params.join(", "));
+            if let Some(doc) = &func.doc {
+                for line in doc.lines() {
+                    let _ = writeln!(out, "  {}", line);
+                }
+            }
+            if let Some(sig) = types.as_ref().and_then(|t| t.functions.get(name)) {
+                let _ = writeln!(
+                    out,
+                    "  - Types: ({}) -> {}",
+                    sig.params.join(", "),
+                    sig.result.as_deref().unwrap_or("any")
+                );
+            }
+        }
+
+        let mut class_names: Vec<&String> = program.classes.keys().collect();
+        class_names.sort();
+        for class_name in class_names {
+            let class = &program.classes[class_name];
+            let mut methods: Vec<&String> = class.methods.keys().collect();
+            methods.sort();
+            for m in methods {
+                let method = &class.methods[m];
+                let _ = writeln!(
+                    out,
+                    "- `{}.{}({})`",
+                    class.name,
+                    method.name,
+                    method.params.join(", ")
+                );
+            }
+            for constant in class.constants.keys() {
+                let _ = writeln!(out, "- `{}.{}` (constant)", class.name, constant);
+            }
+        }
+        let _ = writeln!(out);
+    }
+
+    out
+}
+
+/// One-line summary of a JSON Schema: required fields and property types.
+fn summarize_schema(schema: &Value) -> String {
+    let required: Vec<String> = schema
+        .get("required")
+        .and_then(|v| v.as_array())
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|v| v.as_str())
+                .map(|s| format!("`{}`", s))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let props: Vec<String> = schema
+        .get("properties")
+        .and_then(|v| v.as_object())
+        .map(|obj| {
+            obj.iter()
+                .map(|(k, v)| {
+                    let ty = v.get("type").and_then(|t| t.as_str()).unwrap_or("any");
+                    format!("`{}`: {}", k, ty)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut parts = vec!["JSON Schema".to_string()];
+    if !required.is_empty() {
+        parts.push(format!("required {}", required.join(", ")));
+    }
+    if !props.is_empty() {
+        parts.push(format!("properties {}", props.join(", ")));
+    }
+    parts.join("; ")
+}
+
 pub fn docs_html() -> &'static str {
     DOCS_HTML
 }
@@ -368,6 +1024,24 @@ const DOCS_HTML: &str = r#"<!DOCTYPE html>
       border-color: rgba(249,115,22,0.55);
     }
 
+    .method-pill.PUT {
+      background: rgba(59,130,246,0.12);
+      color: #60a5fa;
+      border-color: rgba(59,130,246,0.55);
+    }
+
+    .method-pill.PATCH {
+      background: rgba(168,85,247,0.12);
+      color: #c084fc;
+      border-color: rgba(168,85,247,0.55);
+    }
+
+    .method-pill.DELETE {
+      background: rgba(239,68,68,0.12);
+      color: #f87171;
+      border-color: rgba(239,68,68,0.55);
+    }
+
     .path {
       font-family: ui-monospace, SFMono-Regular, Menlo, Monaco, Consolas, "Liberation Mono", "Courier New", monospace;
       font-size: 0.78rem;
@@ -919,3 +1593,55 @@ const DOCS_HTML: &str = r#"<!DOCTYPE html>
 </body>
 </html>
 "#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_program;
+
+    fn diagnostic_errors(src: &str) -> Vec<String> {
+        let diagnostics = build_diagnostics(&parse_program(src).unwrap());
+        diagnostics["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["message"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn forward_references_are_not_errors() {
+        let src = r#"server 3000
+
+func total(): db_count("Task") + later(1)
+
+class Report:
+  size(): Stats.count()
+
+func later(x): x
+
+class Stats:
+  count(): db_count("Task")
+
+model Task:
+  id: int pk
+  title: string
+"#;
+        assert_eq!(diagnostic_errors(src), Vec::<String>::new());
+    }
+
+    #[test]
+    fn unknown_model_is_an_error() {
+        let src = r#"server 3000
+
+endpoint GET "/count": db_count("Tsak")
+
+model Task:
+  id: int pk
+"#;
+        assert_eq!(
+            diagnostic_errors(src),
+            ["db_count(\"Tsak\", ...) refers to unknown model 'Tsak'; declare it with 'model Tsak:'"]
+        );
+    }
+}
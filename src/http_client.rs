@@ -0,0 +1,199 @@
+// src/http_client.rs
+//
+// Shared outbound HTTP agent for the builtins that call user-supplied URLs
+// (http_get, http_get_json, http_get_many, http_post, http_post_json,
+// df_from_csv).
+//
+// The `http_client` config section is read once, when the first request is
+// made:
+//
+//   "http_client": {
+//     "max_redirects": 3,          // default 5; 0 = don't follow
+//     "timeout_ms": 10000,         // whole request; default none
+//     "allow_hosts": ["api.example.com", "*.example.org"],
+//     "deny_private": true,        // refuse loopback/private/link-local
+//     "deny_cidrs": ["10.20.0.0/16", "192.0.2.7"]
+//   }
+//
+// When `deny_cidrs` is not set and the environment is prod, it defaults to
+// the link-local ranges plus the AWS IPv6 metadata address, so cloud
+// metadata endpoints such as 169.254.169.254 can't be reached.
+//
+// Host rules live in the agent's resolver, which ureq calls for every
+// connection, including each redirect hop and IP-literal URLs. A name that
+// resolves to any denied address is refused as a whole rather than filtered,
+// and an invalid `deny_cidrs` entry refuses every request (fail closed).
+
+use std::io;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+
+use ipnet::IpNet;
+use once_cell::sync::Lazy;
+
+use crate::config;
+
+/// Redirects followed when `http_client.max_redirects` is not set.
+const DEFAULT_MAX_REDIRECTS: u32 = 5;
+
+/// `deny_cidrs` when it is not set and the environment is prod: link-local
+/// ranges and the AWS IPv6 metadata address.
+const PROD_DENY_CIDRS: &[&str] = &["169.254.0.0/16", "fe80::/10", "fd00:ec2::254/128"];
+
+static AGENT: Lazy<ureq::Agent> = Lazy::new(|| build_agent(&config::http_client_section()));
+
+/// The shared agent, configured from `http_client` on first use.
+pub fn agent() -> &'static ureq::Agent {
+    &AGENT
+}
+
+fn build_agent(cfg: &config::HttpClientConfigFile) -> ureq::Agent {
+    let max_redirects = cfg.max_redirects.unwrap_or(DEFAULT_MAX_REDIRECTS);
+    let guard = HostGuard {
+        allow_hosts: cfg.allow_hosts.as_ref().map(|hosts| {
+            hosts
+                .iter()
+                .map(|h| h.trim().to_ascii_lowercase())
+                .collect()
+        }),
+        deny_private: cfg.deny_private.unwrap_or(false),
+        deny_cidrs: parse_deny_cidrs(cfg),
+    };
+
+    // ureq counts the final request against its limit, so N redirects
+    // need N + 1; 0 turns redirect handling off entirely.
+    let redirects = match max_redirects {
+        0 => 0,
+        n => n + 1,
+    };
+    let mut builder = ureq::AgentBuilder::new()
+        .redirects(redirects)
+        .resolver(guard);
+    if let Some(ms) = cfg.timeout_ms.filter(|ms| *ms > 0) {
+        builder = builder.timeout(Duration::from_millis(ms));
+    }
+    builder.build()
+}
+
+/// The configured (or prod default) `deny_cidrs`, or the first invalid
+/// entry as an error.
+fn parse_deny_cidrs(cfg: &config::HttpClientConfigFile) -> Result<Vec<IpNet>, String> {
+    let entries: Vec<String> = match &cfg.deny_cidrs {
+        Some(entries) => entries.clone(),
+        None if config::env_name() == "prod" => {
+            PROD_DENY_CIDRS.iter().map(|s| s.to_string()).collect()
+        }
+        None => Vec::new(),
+    };
+    entries
+        .iter()
+        .map(|entry| {
+            let entry = entry.trim();
+            entry
+                .parse::<IpNet>()
+                .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| format!("http_client.deny_cidrs: invalid CIDR '{}'", entry))
+        })
+        .collect()
+}
+
+struct HostGuard {
+    /// Lowercased `allow_hosts` entries; `None` allows every host.
+    allow_hosts: Option<Vec<String>>,
+    deny_private: bool,
+    deny_cidrs: Result<Vec<IpNet>, String>,
+}
+
+impl HostGuard {
+    fn check(&self, host: &str) -> Result<(), String> {
+        let host = host.to_ascii_lowercase();
+        if let Some(allowed) = &self.allow_hosts {
+            if !allowed.iter().any(|pattern| host_matches(pattern, &host)) {
+                return Err(format!("host '{}' is not in http_client.allow_hosts", host));
+            }
+        }
+        Ok(())
+    }
+
+    fn check_addr(&self, host: &str, ip: IpAddr) -> Result<(), String> {
+        if self.deny_private && is_private(ip) {
+            return Err(format!(
+                "host '{}' resolves to private address {} (http_client.deny_private)",
+                host, ip
+            ));
+        }
+        let deny_cidrs = self.deny_cidrs.as_ref().map_err(String::clone)?;
+        // An IPv4-mapped IPv6 address must not slip past IPv4 ranges.
+        let mapped = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4),
+            IpAddr::V4(_) => None,
+        };
+        for net in deny_cidrs {
+            if net.contains(&ip) || mapped.is_some_and(|v4| net.contains(&v4)) {
+                return Err(format!(
+                    "host '{}' resolves to {}, which is in http_client.deny_cidrs ({})",
+                    host, ip, net
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ureq::Resolver for HostGuard {
+    fn resolve(&self, netloc: &str) -> io::Result<Vec<SocketAddr>> {
+        let host = netloc_host(netloc);
+        let denied = |msg: String| io::Error::new(io::ErrorKind::PermissionDenied, msg);
+
+        self.check(host).map_err(denied)?;
+        let addrs: Vec<SocketAddr> = netloc.to_socket_addrs()?.collect();
+        for addr in &addrs {
+            self.check_addr(host, addr.ip()).map_err(denied)?;
+        }
+        Ok(addrs)
+    }
+}
+
+/// `pattern` is an exact host name or `*.suffix`, which matches subdomains
+/// of `suffix` but not `suffix` itself.
+fn host_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => host
+            .strip_suffix(suffix)
+            .is_some_and(|rest| rest.ends_with('.')),
+        None => pattern == host,
+    }
+}
+
+/// Host part of a `host:port` / `[v6]:port` netloc.
+fn netloc_host(netloc: &str) -> &str {
+    let host = netloc.rsplit_once(':').map_or(netloc, |(host, _)| host);
+    host.trim_start_matches('[').trim_end_matches(']')
+}
+
+/// Loopback, private, link-local (including cloud metadata at
+/// 169.254.169.254), shared (100.64/10), unspecified and unique-local
+/// addresses.
+fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, ..] = v4.octets();
+            v4.is_loopback()
+                || v4.is_private()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast()
+                || (a == 100 && (b & 0xc0) == 64)
+        }
+        IpAddr::V6(v6) => {
+            let first = v6.segments()[0];
+            v6.is_loopback()
+                || v6.is_unspecified()
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+                || v6
+                    .to_ipv4_mapped()
+                    .is_some_and(|v4| is_private(IpAddr::V4(v4)))
+        }
+    }
+}
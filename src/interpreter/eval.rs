@@ -6,11 +6,18 @@
 //
 // String / basic helpers
 // ----------------------
-// len(x)    -> number (length of string)
+// len(x)    -> number (list elements, map keys, or string characters)
 // upper(x)  -> string (uppercase)
 // lower(x)  -> string (lowercase)
+// trim(x)   -> string without leading/trailing whitespace
+// split(s, sep)         -> string JSON array of the parts ("" splits into
+//                          characters)
+// join(list, sep)       -> string; elements joined with `sep` (non-strings
+//                          in their display form)
+// replace(s, from, to)  -> string with every `from` replaced by `to`
 // number(x) -> number (string/number -> number)
 // string(x) -> string (anything -> string)
+// type_of(x) -> string "number" | "string" | "bool" | "list" | "map" | "null"
 //
 // Numeric helpers (analysis)
 // --------------------------
@@ -18,25 +25,90 @@
 // avg(a, b, ...) -> number (average)
 // min(a, b, ...) -> number (minimum)
 // max(a, b, ...) -> number (maximum)
+// format_number(x, digits) -> string with exactly `digits` decimals
+//                             (0..=20), e.g. format_number(2.5, 2) -> "2.50"
+// floor(x) / ceil(x)       -> number rounded down / up
+// round(x)                 -> number rounded to the nearest whole number,
+//                             halves up (round(2.5) -> 3, round(-2.5) -> -2)
+// abs(x)                   -> number without its sign
+// sqrt(x)                  -> number (error for negative x)
+// pow(base, exp)           -> number base^exp (error if not a finite number)
+//
+// Non-integral numbers are printed with at most 15 significant digits, so
+// float noise like 0.30000000000000004 shows up as 0.3.
+//
+// Timing helpers
+// --------------
+// now_ms()                -> number of milliseconds on a monotonic clock
+//                            (only differences between calls are meaningful)
+// timeit(fn_name, args..) -> number of milliseconds taken by calling the user
+//                            function `fn_name` with `args`
+//
+// Resilience helpers (wrap a user function called with `args`)
+// ------------------------------------------------------------
+// retry(fn_name, times, args..)     -> result of the first successful call;
+//                                      at most `times` calls, then the last
+//                                      error
+// with_timeout(fn_name, ms, args..) -> result of the call, or an error if it
+//                                      takes longer than `ms` (the call runs
+//                                      on its own thread and is not stopped;
+//                                      ORM builtins fail there)
+// circuit_breaker(fn_name, max_failures, cooldown_ms, args..)
+//    -> result of the call; after `max_failures` failures in a row, calls
+//       fail at once for `cooldown_ms`, then one trial call is let through
 //
 // HTTP helpers (call other APIs)
 // ------------------------------
 // http_get(url)      -> string (raw response body)
 // http_get_json(url) -> string (pretty JSON or error)
+// http_get_many(urls) -> string JSON array of response bodies; the URLs (a
+//                        list or JSON array) are fetched concurrently and
+//                        the bodies keep their order
+// http_post(url, body[, token])      -> string response body; `body` is sent
+//                                       as text/plain
+// http_post_json(url, value[, token]) -> same, with `value` sent as JSON
+//    token sets `Authorization: Bearer <token>`; a 4xx/5xx status is an
+//    error that names the status
+//
+// URL helpers
+// -----------
+// url_parse(url)  -> string JSON object {scheme, host, port, path, query}
+//                    (port is the scheme default when not given; null if none)
+// query_parse(qs) -> string JSON object key -> value, split like endpoint
+//                    query parameters (leading "?" ignored, last key wins)
 //
 // Vector / tensor helpers (PyTorch-ish)
 // -------------------------------------
 // vec(a, b, c, ...)  -> string JSON array, e.g. "[1,2,3]"
+// range(n)           -> string JSON array [0, 1, ..., n-1]
+// range(start, end)  -> string JSON array [start, ..., end-1]
+// range(start, end, step) -> same, counting by step (may be negative)
+//                       (at most 10_000 elements)
 // tensor_add(a, b)   -> string JSON array, elementwise sum
 // tensor_dot(a, b)   -> number dot product
 //
+// List helpers
+// ------------
+// sort(list_json)      -> string JSON array, ascending (numeric if all
+//                         elements are numbers, lexicographic otherwise)
+// sort_desc(list_json) -> string JSON array, descending
+// unique(list_json)    -> string JSON array without duplicates (first kept)
+//
 // DataFrame helpers (pandas-ish)
 // ------------------------------
 // df_from_csv(url)        -> string JSON table
 //                            { "columns": [...], "rows": [[...], [...], ...] }
+// df_from_csv_text(csv)   -> string JSON table parsed from inline CSV text
+// df_from_csv_file(path)  -> string JSON table read from a local file
+//                            (restricted to config `data_dir` when set)
 // df_head(df_json, n)     -> string JSON table, first n rows
+//                            (n < 0 counts as 0; n > rows keeps all rows)
+// df_tail(df_json, n)     -> string JSON table, last n rows (same rules)
 // df_select(df_json, cols)-> string JSON table with selected columns
 //                            cols is "col1,col2"
+// df_from_json(rows_json) -> string JSON table built from an array of objects
+//                            (columns are the union of keys, in first-seen order)
+// df_to_csv(df_json)      -> string CSV text (header row + data rows)
 //
 // ML helpers (scikit-learn-ish, linear regression)
 // -------------------------------------------------
@@ -50,71 +122,226 @@
 // openai_set_api_key(key)        -> string "ok"
 // openai_set_system_prompt(text) -> string "ok"
 // openai_chat(user_message)      -> string assistant text
+//    (cached for config.openai.cache_ttl seconds when set)
+// openai_cache_clear()           -> number of cached responses removed
 // openai_chat_json(user_message) -> string pretty JSON
-// openai_mcp_call(server_id, tool_name, args_json) -> string pretty JSON
+// openai_chat_tools(user_message, tools_json) -> string JSON
+//    {"type":"tool_call","name","arguments","tool_calls":[..]} or {"type":"text","text"}
+// openai_mcp_call(server_id, tool_name, args_json) -> string JSON {"tool","result"}
+// openai_mcp_call(server_id, tool_name, args_json, options_json)
+//    options: {"model": "...", "raw": true, "server_url": "...", "server_label": "..."}
+//    raw=true returns the full Responses API payload (pretty JSON)
+//
+// JSON object helpers
+// -------------------
+// json_merge(a_json, b_json)   -> string JSON; deep merge, b wins on conflicts
+//                                 (nested objects merge key by key)
+// json_pick(obj_json, keys)    -> string JSON object with only `keys`
+// json_omit(obj_json, keys)    -> string JSON object without `keys`
+//    keys is "a,b" (like df_select) or a list such as ["a", "b"]
+// parse_json(text)             -> the value `text` encodes; errors on bad JSON
+//                                 (lists/maps stay JSON text)
+// to_json(value)               -> string JSON text; a string becomes a quoted
+//                                 JSON string, so parse_json(to_json(v)) == v
+//
+// List and map literals nest string elements that are JSON arrays/objects
+// (lists, maps, `body`, ORM rows); every other string stays a string, so
+// ["12"] != [12]. Use number(...) or parse_json(...) to convert a scalar.
+//
+// Validation helpers (JSON Schema, Draft 7 - same engine as request bodies)
+// -------------------------------------------------------------------------
+// validate(value_json, schema_json)        -> bool
+// validate_errors(value_json, schema_json) -> string JSON array of messages
+//                                             ("<error> at <path>"), [] if valid
+//
+// Response helpers
+// ----------------
+// download(filename, content)               -> download envelope; the server
+//                                              sends `content` as an attachment
+//                                              (type guessed from the extension)
+// download(filename, content, content_type) -> same, with an explicit type
+// with_content_type(content, content_type)  -> response envelope; the server
+//                                              sends `content` with the given
+//                                              Content-Type
+// db_stream(model_name)                     -> stream envelope; the server
+//                                              streams every row as a JSON
+//                                              array without buffering it
+// db_stream(model_name, format)             -> same; format is "json",
+//                                              "ndjson" or "csv"
+// template(source, vars_json)               -> string; `{{ key }}` is replaced
+//                                              by vars[key], HTML-escaped, and
+//                                              `{{{ key }}}` inserts it raw
+//                                              (missing keys render as "")
+// template(source, vars_json, strict)       -> same; strict=true makes a
+//                                              missing key an error
+//
+// YAML / TOML helpers
+// -------------------
+// to_yaml(json)   -> string YAML document
+// from_yaml(yaml) -> string JSON
+// to_toml(json)   -> string TOML document (JSON object without nulls)
+// from_toml(toml) -> string JSON object
 //
 // Generic config + env + secrets helpers
 // --------------------------------------
 // config_set(key, value)            -> string "ok"
 // config_get(key)                   -> stored value or ""
+//                                      (a bare `key` identifier also reads it
+//                                      when no variable of that name exists)
 // config_get(key, default)          -> stored value or default
 // config_has(key)                   -> bool
+// feature(name)                     -> bool; config `features.<name>`, or
+//                                      SHRIMPL_FEATURE_<NAME>, else false
 // env(name)                         -> string env var value or ""
 // secret(name)                      -> string secret value or error
 // secret(name, default)            -> secret or default (no error)
+// secret_set(name, value)           -> string "ok"; in-process override read
+//                                      by secret() (dev/test env only)
+//
+// In-memory cache helpers
+// -----------------------
+// cache_set(key, value)             -> string "ok"; kept until deleted
+// cache_set(key, value, ttl_secs)   -> string "ok"; expires after ttl_secs
+//                                      (0 expires immediately)
+// cache_get(key)                    -> stored value (lists/maps as JSON
+//                                      strings), or "" when absent/expired
+// cache_delete(key)                 -> string "ok"
 //
 // ORM helpers (SQLite via shrimpl.db)
 // -----------------------------------
 // orm_insert(model_name, record_json)   -> string primary key / rowid
+// db_insert_many(model_name, records_json) -> number of rows inserted
+//                                           (one transaction; all or none)
 // orm_find_by_id(model_name, id_json)  -> string JSON object or ""
+// db_count(model_name)                  -> number of rows
+// db_count_where(model_name, column, value) -> number of rows where column = value
+// db_update(model_name, id, patch)      -> number of rows changed; patch is a
+//                                          JSON object of model fields
+// db_delete(model_name, id)             -> number of rows deleted
+// db_all(model_name)                    -> string JSON array of every row
+// db_where(model_name, column, value)   -> string JSON array of rows where
+//                                          column = value
+// db_aggregate(model_name, column, func)    -> number (func: sum/avg/min/max) or ""
 //
 // All complex objects are passed as JSON strings in Shrimpl.
 // Kids only see numbers, strings, booleans, and function calls.
 
+use crate::cache;
+use crate::concurrency;
 use crate::config;
+use crate::dataformats;
+use crate::http_client;
+use crate::metrics;
 use crate::orm; // <--- hook into src/orm.rs
 
 use crate::parser::ast::{BinOp, Expr, FunctionDef, Program};
+use parking_lot::Mutex;
 
+use jsonschema::{Draft, JSONSchema};
 use serde_json::{json, Value};
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
 use std::fmt;
-use std::io::Cursor;
-use std::{
-    env,
-    sync::{Mutex, OnceLock},
-};
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{env, sync::OnceLock};
 use ureq;
 
 // ---------- Result alias ----------
 
 type EvalResult<T> = std::result::Result<T, String>;
 
+/// Safety cap for `repeat N times` and `repeat while` loops.
+const MAX_LOOP_ITERATIONS: usize = 10_000;
+
 // ---------- runtime values ----------
 
 #[derive(Debug, Clone)]
 enum ValueRuntime {
+    /// Exact whole number: integer literals, `number("...")` of an integer
+    /// string, and integers read from JSON. Arithmetic falls back to
+    /// `Number` on overflow or division.
+    Int(i64),
     Number(f64),
     Str(String),
     Bool(bool),
+    /// A response built by a response helper such as `download(...)`.
+    Reply(Box<Reply>),
+}
+
+/// A response built by a response helper. It exists only as a runtime
+/// value, never as text, so request input echoed by a body can't produce
+/// one; the HTTP layer builds the response from it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reply {
+    /// `download(filename, content[, content_type])`: an attachment.
+    Download {
+        filename: String,
+        content: String,
+        content_type: String,
+    },
+    /// `with_content_type(content, content_type)`: an inline body.
+    WithContentType {
+        content: String,
+        content_type: String,
+    },
+    /// `db_stream(model_name[, format])`: every row of a model, streamed.
+    Stream { model: String, format: String },
+}
+
+/// Text use of a reply (e.g. concatenation) sees only its content; a stream
+/// has none until it is sent.
+impl fmt::Display for Reply {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reply::Download { content, .. } | Reply::WithContentType { content, .. } => {
+                write!(f, "{}", content)
+            }
+            Reply::Stream { .. } => Ok(()),
+        }
+    }
+}
+
+/// What an endpoint body evaluated to.
+pub enum BodyValue {
+    Text(String),
+    Reply(Reply),
 }
 
 impl fmt::Display for ValueRuntime {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            ValueRuntime::Int(i) => write!(f, "{}", i),
             ValueRuntime::Number(n) => {
-                if n.fract() == 0.0 {
+                if n.fract() == 0.0 && n.abs() < i64::MAX as f64 {
                     write!(f, "{}", *n as i64)
+                } else if n.fract() == 0.0 {
+                    write!(f, "{:.0}", n)
                 } else {
-                    write!(f, "{}", n)
+                    write!(f, "{}", tidy_float(*n))
                 }
             }
             ValueRuntime::Str(s) => write!(f, "{}", s),
             ValueRuntime::Bool(b) => write!(f, "{}", b),
+            ValueRuntime::Reply(r) => write!(f, "{}", r),
         }
     }
 }
 
+/// Round a float to 15 significant digits so arithmetic noise such as
+/// `0.1 + 0.2 = 0.30000000000000004` prints as `0.3`. Integral and
+/// non-finite values are returned unchanged.
+fn tidy_float(n: f64) -> f64 {
+    if !n.is_finite() || n.fract() == 0.0 {
+        return n;
+    }
+    format!("{:.14e}", n).parse().unwrap_or(n)
+}
+
 #[derive(Debug, Clone)]
 struct Env {
     vars: HashMap<String, ValueRuntime>,
@@ -152,6 +379,70 @@ struct OpenAIConfig {
     base_url: String,
 }
 
+/// Output formats accepted by `db_stream(model_name, format)`.
+pub const STREAM_FORMATS: &[&str] = &["json", "ndjson", "csv"];
+
+/// Key prefix for cached openai_chat responses in the shared cache.
+const OPENAI_CACHE_PREFIX: &str = "openai:chat:";
+
+/// Key prefix for entries stored by the cache_* builtins, so Shrimpl code
+/// can't read or clobber the response and openai caches.
+const APP_CACHE_PREFIX: &str = "app:";
+
+/// Origin of the monotonic clock behind `now_ms()`.
+static CLOCK_START: OnceLock<Instant> = OnceLock::new();
+
+/// `with_timeout` threads that may be alive at once, counting calls that
+/// timed out but are still running. A call past this fails instead of
+/// starting another thread, so slow functions can't pile up threads.
+const MAX_TIMEOUT_THREADS: usize = 16;
+
+static TIMEOUT_THREADS: AtomicUsize = AtomicUsize::new(0);
+
+/// One of the `MAX_TIMEOUT_THREADS` slots; released when dropped.
+struct TimeoutSlot(());
+
+/// Claim a `with_timeout` thread slot, or None when all are in use.
+fn try_timeout_slot() -> Option<TimeoutSlot> {
+    TIMEOUT_THREADS
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+            (n < MAX_TIMEOUT_THREADS).then_some(n + 1)
+        })
+        .ok()
+        .map(|_| TimeoutSlot(()))
+}
+
+impl Drop for TimeoutSlot {
+    fn drop(&mut self) {
+        TIMEOUT_THREADS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// `circuit_breaker(...)` state per user function, shared by all requests.
+static CIRCUIT_BREAKERS: OnceLock<Mutex<HashMap<String, CircuitState>>> = OnceLock::new();
+
+thread_local! {
+    /// The server's shared copy of the program being run on this thread
+    /// (see `with_shared_program`), so `with_timeout` can hand it to its
+    /// worker thread without copying it.
+    static SHARED_PROGRAM: std::cell::RefCell<Option<Arc<Program>>> =
+        const { std::cell::RefCell::new(None) };
+
+    /// Class constants being evaluated on this thread (`Class.NAME`), so a
+    /// constant that refers back to itself fails instead of recursing
+    /// forever.
+    static CONSTS_IN_PROGRESS: std::cell::RefCell<Vec<String>> =
+        const { std::cell::RefCell::new(Vec::new()) };
+}
+
+#[derive(Default)]
+struct CircuitState {
+    /// Failures in a row; reset by a successful call.
+    failures: u64,
+    /// While set and in the future, calls fail without running.
+    open_until: Option<Instant>,
+}
+
 static OPENAI_CONFIG: OnceLock<Mutex<OpenAIConfig>> = OnceLock::new();
 
 fn get_openai_config() -> &'static Mutex<OpenAIConfig> {
@@ -170,11 +461,46 @@ fn get_openai_config() -> &'static Mutex<OpenAIConfig> {
     })
 }
 
+/// Timestamps of OpenAI calls in the last minute, for max_calls_per_min.
+static OPENAI_RECENT_CALLS: Mutex<VecDeque<Instant>> = Mutex::new(VecDeque::new());
+
+/// Enforce config.openai.max_calls_per_min over a rolling 60-second window.
+/// On success the call is recorded; on failure nothing is sent.
+fn openai_check_rate_limit() -> EvalResult<()> {
+    let max = match config::openai_max_calls_per_min() {
+        Some(max) => max as usize,
+        None => return Ok(()),
+    };
+
+    let window = Duration::from_secs(60);
+    let now = Instant::now();
+    let mut recent = OPENAI_RECENT_CALLS.lock();
+    while recent
+        .front()
+        .is_some_and(|t| now.duration_since(*t) >= window)
+    {
+        recent.pop_front();
+    }
+
+    if recent.len() >= max {
+        let retry_in = recent
+            .front()
+            .map(|t| window.saturating_sub(now.duration_since(*t)).as_secs() + 1)
+            .unwrap_or(60);
+        metrics::metrics_incr("shrimpl_openai_calls_rejected_total", 1.0);
+        return Err(format!(
+            "OpenAI call limit reached: {} calls per minute (config.openai.max_calls_per_min); try again in {}s",
+            max, retry_in
+        ));
+    }
+
+    recent.push_back(now);
+    Ok(())
+}
+
 fn openai_post(path: &str, body: &Value) -> EvalResult<Value> {
     let cfg_lock = get_openai_config();
-    let cfg = cfg_lock
-        .lock()
-        .map_err(|_| "OpenAI config mutex poisoned".to_string())?;
+    let cfg = cfg_lock.lock();
 
     let api_key = cfg.api_key.clone().ok_or_else(|| {
         "OpenAI API key is not set.\n\
@@ -199,6 +525,8 @@ fn openai_post(path: &str, body: &Value) -> EvalResult<Value> {
     let body_text =
         serde_json::to_string(body).map_err(|e| format!("OpenAI: failed to encode body: {}", e))?;
 
+    openai_check_rate_limit()?;
+    metrics::metrics_incr("shrimpl_openai_calls_total", 1.0);
     let resp = ureq::post(&url)
         .set("Authorization", &format!("Bearer {}", api_key))
         .set("Content-Type", "application/json")
@@ -213,38 +541,312 @@ fn openai_post(path: &str, body: &Value) -> EvalResult<Value> {
                 .map_err(|e| format!("OpenAI: response not valid JSON: {}", e))?;
             Ok(json_val)
         }
+        Err(ureq::Error::Status(code, r)) => {
+            // Prefer the API's own error message over the bare status line.
+            let detail = r
+                .into_string()
+                .ok()
+                .and_then(|text| serde_json::from_str::<Value>(&text).ok())
+                .and_then(|v| openai_error_message(&v));
+            match detail {
+                Some(msg) => Err(format!("OpenAI HTTP error {}: {}", code, msg)),
+                None => Err(format!("OpenAI HTTP error: status {}", code)),
+            }
+        }
         Err(err) => Err(format!("OpenAI HTTP error: {}", err)),
     }
 }
 
+/// Chat Completions request body: configured model, optional system prompt,
+/// then the user message.
+fn openai_chat_payload(user_msg: String) -> Value {
+    let cfg_lock = get_openai_config();
+    let cfg = cfg_lock.lock();
+    let model = cfg.model.clone();
+    let system_prompt = cfg.system_prompt.clone();
+    drop(cfg);
+
+    let mut messages: Vec<Value> = Vec::new();
+    if let Some(sp) = system_prompt {
+        messages.push(json!({ "role": "system", "content": sp }));
+    }
+    messages.push(json!({
+        "role": "user",
+        "content": user_msg
+    }));
+
+    json!({
+        "model": model,
+        "messages": messages,
+    })
+}
+
+/// Normalize a `tools_json` argument into Chat Completions `tools` entries.
+/// Bare function specs (`{"name": .., "parameters": ..}`) are wrapped as
+/// `{"type": "function", "function": ..}`.
+fn openai_tools_from_json(tools_raw: &str) -> EvalResult<Vec<Value>> {
+    let tools = parse_json_array("openai_chat_tools", tools_raw)?;
+    tools
+        .into_iter()
+        .map(|tool| match tool {
+            Value::Object(ref map) if map.contains_key("type") => Ok(tool),
+            Value::Object(ref map) if map.contains_key("name") => {
+                Ok(json!({ "type": "function", "function": tool }))
+            }
+            other => Err(format!(
+                "openai_chat_tools: each tool must be an object with 'type' or 'name', got {}",
+                other
+            )),
+        })
+        .collect()
+}
+
+/// Turn a Chat Completions response into Shrimpl's tool-call shape:
+/// `{"type":"tool_call","name","arguments","tool_calls":[..]}` when the model
+/// called a tool, otherwise `{"type":"text","text"}`.
+fn openai_tool_call_result(resp: &Value) -> EvalResult<Value> {
+    if let Some(msg) = openai_error_message(resp) {
+        return Err(format!("openai_chat_tools: API error: {}", msg));
+    }
+
+    let message = resp
+        .get("choices")
+        .and_then(|c| c.as_array())
+        .and_then(|arr| arr.first())
+        .and_then(|first| first.get("message"))
+        .ok_or_else(|| "openai_chat_tools: response has no choices[0].message".to_string())?;
+
+    let calls: Vec<Value> = message
+        .get("tool_calls")
+        .and_then(|t| t.as_array())
+        .map(|arr| {
+            arr.iter()
+                .map(|call| {
+                    let function = call.get("function").cloned().unwrap_or(Value::Null);
+                    let args_text = function
+                        .get("arguments")
+                        .and_then(|a| a.as_str())
+                        .unwrap_or("{}");
+                    json!({
+                        "id": call.get("id").cloned().unwrap_or(Value::Null),
+                        "name": function.get("name").cloned().unwrap_or(Value::Null),
+                        // Arguments arrive as JSON text; fall back to the raw string.
+                        "arguments": serde_json::from_str::<Value>(args_text)
+                            .unwrap_or_else(|_| Value::String(args_text.to_string())),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    if let Some(first) = calls.first() {
+        return Ok(json!({
+            "type": "tool_call",
+            "name": first["name"],
+            "arguments": first["arguments"],
+            "tool_calls": calls,
+        }));
+    }
+
+    let text = message
+        .get("content")
+        .and_then(|c| c.as_str())
+        .unwrap_or("");
+    Ok(json!({ "type": "text", "text": text }))
+}
+
+/// The `error.message` (or `error` string) of an OpenAI response, if any.
+fn openai_error_message(resp: &Value) -> Option<String> {
+    match resp.get("error")? {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        err => Some(
+            err.get("message")
+                .and_then(|m| m.as_str())
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| err.to_string()),
+        ),
+    }
+}
+
+/// Pull the tool result out of a Responses API payload.
+///
+/// Looks for an `mcp_call` output item for `tool_name` first, then falls
+/// back to the assistant's `output_text`. API-level failures, failed tool
+/// calls, and payloads with neither are errors.
+fn extract_mcp_result(resp: &Value, tool_name: &str) -> EvalResult<Value> {
+    if let Some(msg) = openai_error_message(resp) {
+        return Err(format!("openai_mcp_call: API error: {}", msg));
+    }
+    match resp.get("status").and_then(|s| s.as_str()) {
+        Some("failed") => {
+            return Err("openai_mcp_call: response status is 'failed'".to_string());
+        }
+        Some("incomplete") => {
+            let reason = resp
+                .get("incomplete_details")
+                .and_then(|d| d.get("reason"))
+                .and_then(|r| r.as_str())
+                .unwrap_or("unknown reason");
+            return Err(format!("openai_mcp_call: response incomplete ({})", reason));
+        }
+        _ => {}
+    }
+
+    let output = resp
+        .get("output")
+        .and_then(|o| o.as_array())
+        .ok_or_else(|| "openai_mcp_call: response has no 'output' array".to_string())?;
+
+    let tool_call = output.iter().find(|item| {
+        item.get("type").and_then(|t| t.as_str()) == Some("mcp_call")
+            && item.get("name").and_then(|n| n.as_str()) == Some(tool_name)
+    });
+    if let Some(call) = tool_call {
+        if let Some(err) = call.get("error").filter(|e| !e.is_null()) {
+            let msg = err
+                .as_str()
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| err.to_string());
+            return Err(format!(
+                "openai_mcp_call: tool '{}' failed: {}",
+                tool_name, msg
+            ));
+        }
+        let text = call.get("output").and_then(|o| o.as_str()).unwrap_or("");
+        // Tools often return JSON text; keep it structured when it is.
+        return Ok(serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_string())));
+    }
+
+    let text: String = output
+        .iter()
+        .filter(|item| item.get("type").and_then(|t| t.as_str()) == Some("message"))
+        .filter_map(|item| item.get("content").and_then(|c| c.as_array()))
+        .flatten()
+        .filter(|part| part.get("type").and_then(|t| t.as_str()) == Some("output_text"))
+        .filter_map(|part| part.get("text").and_then(|t| t.as_str()))
+        .collect();
+    if text.is_empty() {
+        return Err(format!(
+            "openai_mcp_call: response contained no result for tool '{}'",
+            tool_name
+        ));
+    }
+    Ok(Value::String(text))
+}
+
 // ---------- public entry point for endpoint bodies ----------
 
+#[allow(dead_code)]
 pub fn eval_body_expr(
     expr: &Expr,
     program: &Program,
     vars: &HashMap<String, String>,
 ) -> EvalResult<String> {
+    Ok(eval_body_value(expr, program, vars, &[])?.to_string())
+}
+
+/// Like `eval_body_expr`, plus boolean variables (e.g. `authenticated`) that
+/// must be real bools rather than strings, since any non-empty string is truthy.
+/// Flags take precedence over string vars of the same name. A body that is a
+/// response helper call comes back as `BodyValue::Reply`.
+pub fn eval_endpoint_body(
+    expr: &Expr,
+    program: &Program,
+    vars: &HashMap<String, String>,
+    flags: &[(&str, bool)],
+) -> EvalResult<BodyValue> {
+    Ok(match eval_body_value(expr, program, vars, flags)? {
+        ValueRuntime::Reply(reply) => BodyValue::Reply(*reply),
+        value => BodyValue::Text(value.to_string()),
+    })
+}
+
+fn eval_body_value(
+    expr: &Expr,
+    program: &Program,
+    vars: &HashMap<String, String>,
+    flags: &[(&str, bool)],
+) -> EvalResult<ValueRuntime> {
     let mut env = Env::new();
     for (k, v) in vars {
         env.set(k.clone(), ValueRuntime::Str(v.clone()));
     }
+    for (k, v) in flags {
+        env.set(k.to_string(), ValueRuntime::Bool(*v));
+    }
+
+    eval_expr(expr, program, &env)
+}
+
+/// Variables bound by `let` lines in a `test` block, visible to the lines
+/// after them (including `teardown:`). Values keep their runtime type.
+#[allow(dead_code)]
+pub struct TestEnv {
+    env: Env,
+}
+
+#[allow(dead_code)]
+impl TestEnv {
+    pub fn new() -> Self {
+        TestEnv { env: Env::new() }
+    }
+
+    /// Evaluate `expr` and bind the result to `name`.
+    pub fn bind(&mut self, name: &str, expr: &Expr, program: &Program) -> EvalResult<()> {
+        let value = eval_expr(expr, program, &self.env)?;
+        self.env.set(name.to_string(), value);
+        Ok(())
+    }
+
+    /// Bind a JSON value to `name` (lists and maps become JSON strings).
+    pub fn set_json(&mut self, name: &str, value: &Value) {
+        self.env.set(name.to_string(), json_to_runtime_value(value));
+    }
+
+    /// Evaluate `expr` with the bound variables, like `eval_body_expr`.
+    pub fn eval(&self, expr: &Expr, program: &Program) -> EvalResult<String> {
+        Ok(eval_expr(expr, program, &self.env)?.to_string())
+    }
+}
 
-    let value = eval_expr(expr, program, &env)?;
-    Ok(value.to_string())
+impl Default for TestEnv {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 // ---------- expression evaluation ----------
 
 fn eval_expr(expr: &Expr, program: &Program, env: &Env) -> EvalResult<ValueRuntime> {
     match expr {
+        Expr::Int(i) => Ok(ValueRuntime::Int(*i)),
         Expr::Number(n) => Ok(ValueRuntime::Number(*n)),
         Expr::Str(s) => Ok(ValueRuntime::Str(s.clone())),
         Expr::Bool(b) => Ok(ValueRuntime::Bool(*b)),
 
+        // Locals (params, loop vars, request vars) shadow config.values.
         Expr::Var(name) => env
             .get(name)
+            .or_else(|| config::get_value(name).map(|raw| json_to_runtime_value(&raw)))
             .ok_or_else(|| format!("Unknown variable '{}'", name)),
 
+        Expr::Neg(operand) => {
+            let v = eval_expr(operand, program, env)?;
+            eval_neg(&v)
+        }
+
+        Expr::Not(operand) => {
+            let v = eval_expr(operand, program, env)?;
+            Ok(ValueRuntime::Bool(!as_bool(&v)?))
+        }
+
+        Expr::Index { target, index } => {
+            let t = eval_expr(target, program, env)?;
+            let i = eval_expr(index, program, env)?;
+            eval_index(&t, &i)
+        }
+
         Expr::Binary { left, op, right } => {
             let lv = eval_expr(left, program, env)?;
             let rv = eval_expr(right, program, env)?;
@@ -279,6 +881,41 @@ fn eval_expr(expr: &Expr, program: &Program, env: &Env) -> EvalResult<ValueRunti
             eval_function(method, arg_vals, program, env)
         }
 
+        Expr::ClassConst { class_name, name } => {
+            let class = program
+                .classes
+                .get(class_name)
+                .ok_or_else(|| format!("Undefined class '{}'", class_name))?;
+
+            let expr = class.constants.get(name).ok_or_else(|| {
+                if class.methods.contains_key(name) {
+                    format!(
+                        "'{}.{}' is a method; call it as {}.{}(...)",
+                        class_name, name, class_name, name
+                    )
+                } else {
+                    format!("Class '{}' has no constant '{}'", class_name, name)
+                }
+            })?;
+
+            // Constants see only globals, not the caller's variables.
+            let qualified = format!("{}.{}", class_name, name);
+            let cyclic = CONSTS_IN_PROGRESS.with(|stack| {
+                let mut stack = stack.borrow_mut();
+                let cyclic = stack.contains(&qualified);
+                if !cyclic {
+                    stack.push(qualified.clone());
+                }
+                cyclic
+            });
+            if cyclic {
+                return Err(format!("Class constant '{}' refers to itself", qualified));
+            }
+            let result = eval_expr(expr, program, &Env::new());
+            CONSTS_IN_PROGRESS.with(|stack| stack.borrow_mut().pop());
+            result
+        }
+
         Expr::List(items) => {
             let mut arr = Vec::new();
             for item in items {
@@ -318,7 +955,12 @@ fn eval_expr(expr: &Expr, program: &Program, env: &Env) -> EvalResult<ValueRunti
             }
         }
 
-        Expr::Repeat { count, body } => {
+        Expr::Repeat {
+            count,
+            body,
+            index_var,
+            accumulator,
+        } => {
             let count_val = eval_expr(count, program, env)?;
             let n = as_number(&count_val)?;
             if n < 0.0 {
@@ -326,13 +968,59 @@ fn eval_expr(expr: &Expr, program: &Program, env: &Env) -> EvalResult<ValueRunti
             }
 
             let steps = n.floor() as usize;
-            if steps > 10_000 {
+            if steps > MAX_LOOP_ITERATIONS {
                 return Err("repeat N times: N is too large (max 10_000)".to_string());
             }
 
+            let mut local_env = Env::with_parent(env);
+
+            if let Some((acc_name, init)) = accumulator {
+                // Thread the accumulator through each iteration alongside the index.
+                let mut acc = eval_expr(init, program, env)?;
+                for idx in 0..steps {
+                    local_env.set(index_var.clone(), ValueRuntime::Number(idx as f64));
+                    local_env.set(acc_name.clone(), acc);
+                    acc = eval_expr(body, program, &local_env)?;
+                }
+                return Ok(acc);
+            }
+
             let mut last = ValueRuntime::Str(String::new());
-            for _ in 0..steps {
-                last = eval_expr(body, program, env)?;
+            for idx in 0..steps {
+                local_env.set(index_var.clone(), ValueRuntime::Number(idx as f64));
+                last = eval_expr(body, program, &local_env)?;
+            }
+            Ok(last)
+        }
+
+        Expr::While {
+            cond,
+            body,
+            accumulator,
+        } => {
+            // With an accumulator, both the condition and the body see it and
+            // it is rebound to each body result; otherwise `last` is just the
+            // most recent body value.
+            let mut local_env = Env::with_parent(env);
+            let mut last = match accumulator {
+                Some((_, init)) => eval_expr(init, program, env)?,
+                None => ValueRuntime::Str(String::new()),
+            };
+            let mut iterations = 0usize;
+            loop {
+                if let Some((acc_name, _)) = accumulator {
+                    local_env.set(acc_name.clone(), last.clone());
+                }
+                if !as_bool(&eval_expr(cond, program, &local_env)?)? {
+                    break;
+                }
+                if iterations >= MAX_LOOP_ITERATIONS {
+                    return Err(
+                        "repeat while: loop did not finish within 10_000 iterations".to_string()
+                    );
+                }
+                last = eval_expr(body, program, &local_env)?;
+                iterations += 1;
             }
             Ok(last)
         }
@@ -403,7 +1091,7 @@ fn eval_function(
     }
 
     let mut env = Env::with_parent(parent_env);
-    for (name, val) in func.params.iter().zip(arg_vals.into_iter()) {
+    for (name, val) in func.params.iter().zip(arg_vals) {
         env.set(name.clone(), val);
     }
 
@@ -412,6 +1100,18 @@ fn eval_function(
 
 // ---------- built-ins ----------
 
+/// Builtins whose latency is recorded in the metrics histograms.
+const TIMED_BUILTINS: &[&str] = &[
+    "http_get",
+    "http_get_many",
+    "http_post",
+    "http_post_json",
+    "openai_chat",
+    "df_from_csv",
+    "df_from_csv_text",
+    "df_from_csv_file",
+];
+
 fn eval_builtin(
     name: &str,
     args: &[Expr],
@@ -420,15 +1120,290 @@ fn eval_builtin(
 ) -> EvalResult<ValueRuntime> {
     let vals = eval_args(args, program, env)?;
 
+    // These call back into user code, so they need the caller's env.
+    match name {
+        "timeit" => return timeit(vals, program, env),
+        "retry" => return retry(vals, program, env),
+        "with_timeout" => return with_timeout(vals, program, env),
+        "circuit_breaker" => return circuit_breaker(vals, program, env),
+        _ => {}
+    }
+
+    // Time only the builtin itself, not the evaluation of its arguments.
+    if TIMED_BUILTINS.contains(&name) {
+        let started = Instant::now();
+        let result = apply_builtin(name, vals, program);
+        metrics::observe_builtin_latency(name, started.elapsed().as_secs_f64());
+        return result;
+    }
+
+    apply_builtin(name, vals, program)
+}
+
+/// `timeit(fn_name, args...)`: run a user function, discard its result and
+/// return the elapsed milliseconds.
+fn timeit(vals: Vec<ValueRuntime>, program: &Program, env: &Env) -> EvalResult<ValueRuntime> {
+    let mut vals = vals.into_iter();
+    let Some(fn_name) = vals.next() else {
+        return Err("timeit(fn_name, args...) expects at least 1 argument".to_string());
+    };
+    let fn_name = fn_name.to_string();
+    let func = program
+        .functions
+        .get(&fn_name)
+        .ok_or_else(|| format!("timeit: unknown function '{}'", fn_name))?;
+
+    let started = Instant::now();
+    eval_function(func, vals.collect(), program, env)?;
+    Ok(ValueRuntime::Number(
+        started.elapsed().as_secs_f64() * 1000.0,
+    ))
+}
+
+/// Split `fn_name, n.., args..` for the resilience helpers: the user
+/// function, `counts` whole numbers (each at least `min`), and the rest as
+/// the call's arguments.
+fn wrapper_args<'p>(
+    builtin: &str,
+    usage: &str,
+    counts: &[(&str, u64)],
+    vals: Vec<ValueRuntime>,
+    program: &'p Program,
+) -> EvalResult<(&'p FunctionDef, Vec<u64>, Vec<ValueRuntime>)> {
+    if vals.len() < 1 + counts.len() {
+        return Err(format!(
+            "{} expects at least {} arguments",
+            usage,
+            1 + counts.len()
+        ));
+    }
+    let mut vals = vals.into_iter();
+    let fn_name = vals.next().map(|v| v.to_string()).unwrap_or_default();
+    let func = program
+        .functions
+        .get(&fn_name)
+        .ok_or_else(|| format!("{}: unknown function '{}'", builtin, fn_name))?;
+
+    let mut numbers = Vec::new();
+    for (what, min) in counts {
+        let v = vals.next().unwrap_or(ValueRuntime::Int(0));
+        let n = as_number(&v).unwrap_or(f64::NAN);
+        if n.fract() != 0.0 || n < *min as f64 {
+            return Err(format!(
+                "{}: {} must be a whole number >= {}, got '{}'",
+                builtin, what, min, v
+            ));
+        }
+        numbers.push(n as u64);
+    }
+    Ok((func, numbers, vals.collect()))
+}
+
+/// `retry(fn_name, times, args...)`: call until one call succeeds.
+fn retry(vals: Vec<ValueRuntime>, program: &Program, env: &Env) -> EvalResult<ValueRuntime> {
+    let (func, counts, args) = wrapper_args(
+        "retry",
+        "retry(fn_name, times, args...)",
+        &[("times", 1)],
+        vals,
+        program,
+    )?;
+    let times = counts[0];
+
+    let mut last_err = String::new();
+    for _ in 0..times {
+        match eval_function(func, args.clone(), program, env) {
+            Ok(v) => return Ok(v),
+            Err(e) => last_err = e,
+        }
+    }
+    Err(format!(
+        "retry: '{}' failed {} times; last error: {}",
+        func.name, times, last_err
+    ))
+}
+
+/// Run `f` with `program` registered as this thread's shared program, so
+/// `with_timeout` calls inside it share the `Arc` instead of cloning the
+/// whole Program.
+pub fn with_shared_program<T>(program: &Arc<Program>, f: impl FnOnce() -> T) -> T {
+    let previous = SHARED_PROGRAM.with(|slot| slot.replace(Some(Arc::clone(program))));
+    let result = f();
+    SHARED_PROGRAM.with(|slot| *slot.borrow_mut() = previous);
+    result
+}
+
+/// This thread's shared `Arc` for `program`, or a new one holding a copy
+/// when the caller never registered it.
+fn shared_program(program: &Program) -> Arc<Program> {
+    SHARED_PROGRAM
+        .with(|slot| {
+            slot.borrow()
+                .as_ref()
+                .filter(|shared| std::ptr::eq(shared.as_ref(), program))
+                .cloned()
+        })
+        .unwrap_or_else(|| Arc::new(program.clone()))
+}
+
+/// `with_timeout(fn_name, ms, args...)`: the evaluator is synchronous, so
+/// the call runs on its own thread with the shared program and a copy of
+/// the env. A call that times out keeps running in the background until it
+/// returns, holding one of the `MAX_TIMEOUT_THREADS` slots. That thread is
+/// outside the request's transaction (and may outlive it), so ORM builtins
+/// fail there instead of writing around it.
+fn with_timeout(vals: Vec<ValueRuntime>, program: &Program, env: &Env) -> EvalResult<ValueRuntime> {
+    let (func, counts, args) = wrapper_args(
+        "with_timeout",
+        "with_timeout(fn_name, ms, args...)",
+        &[("ms", 0)],
+        vals,
+        program,
+    )?;
+    let ms = counts[0];
+    let fn_name = func.name.clone();
+    let slot = try_timeout_slot().ok_or_else(|| {
+        format!(
+            "with_timeout: {} earlier calls are still running; try again later",
+            MAX_TIMEOUT_THREADS
+        )
+    })?;
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let program = shared_program(program);
+    let env = Env::with_parent(env);
+    let thread_name = fn_name.clone();
+    std::thread::spawn(move || {
+        orm::deny_on_this_thread(format!(
+            "not available inside with_timeout('{}'), which runs outside the request transaction",
+            thread_name
+        ));
+        with_shared_program(&program, || {
+            if let Some(func) = program.functions.get(&thread_name) {
+                let result = eval_function(func, args, &program, &env);
+                // Free the slot when the call returns, even if the caller
+                // gave up on it, and before the caller can see the result.
+                drop(slot);
+                let _ = tx.send(result);
+            }
+        });
+    });
+
+    match rx.recv_timeout(Duration::from_millis(ms)) {
+        Ok(result) => result,
+        Err(std::sync::mpsc::RecvTimeoutError::Timeout) => Err(format!(
+            "with_timeout: '{}' did not finish within {} ms",
+            fn_name, ms
+        )),
+        Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => Err(format!(
+            "with_timeout: '{}' stopped without a result",
+            fn_name
+        )),
+    }
+}
+
+/// `circuit_breaker(fn_name, max_failures, cooldown_ms, args...)`.
+fn circuit_breaker(
+    vals: Vec<ValueRuntime>,
+    program: &Program,
+    env: &Env,
+) -> EvalResult<ValueRuntime> {
+    let (func, counts, args) = wrapper_args(
+        "circuit_breaker",
+        "circuit_breaker(fn_name, max_failures, cooldown_ms, args...)",
+        &[("max_failures", 1), ("cooldown_ms", 0)],
+        vals,
+        program,
+    )?;
+    let (max_failures, cooldown) = (counts[0], Duration::from_millis(counts[1]));
+    let breakers = CIRCUIT_BREAKERS.get_or_init(|| Mutex::new(HashMap::new()));
+
+    // Check (and release the lock) before running user code.
+    let now = Instant::now();
+    if let Some(until) = breakers
+        .lock()
+        .get(&func.name)
+        .and_then(|state| state.open_until)
+    {
+        if now < until {
+            let wait_ms = until.duration_since(now).as_millis() + 1;
+            return Err(format!(
+                "circuit_breaker: '{}' is open after {} failures in a row; try again in {} ms",
+                func.name, max_failures, wait_ms
+            ));
+        }
+    }
+
+    let result = eval_function(func, args, program, env);
+
+    let mut breakers = breakers.lock();
+    let state = breakers.entry(func.name.clone()).or_default();
+    match &result {
+        Ok(_) => *state = CircuitState::default(),
+        Err(_) => {
+            state.failures += 1;
+            // Past the limit (including a failed trial call after the
+            // cooldown) the circuit opens again.
+            if state.failures >= max_failures {
+                state.open_until = Some(Instant::now() + cooldown);
+            }
+        }
+    }
+    result
+}
+
+fn apply_builtin(
+    name: &str,
+    vals: Vec<ValueRuntime>,
+    program: &Program,
+) -> EvalResult<ValueRuntime> {
     match name {
         // --- string helpers ---
         "len" => {
             if vals.len() != 1 {
                 return Err("len(x) expects exactly 1 argument".to_string());
             }
-            Ok(ValueRuntime::Number(
-                vals[0].to_string().chars().count() as f64
-            ))
+            let text = vals[0].to_string();
+
+            // Lists and maps are JSON strings: count elements / keys, not characters.
+            let trimmed = text.trim_start();
+            if trimmed.starts_with('[') || trimmed.starts_with('{') {
+                match serde_json::from_str::<Value>(&text) {
+                    Ok(Value::Array(items)) => return Ok(ValueRuntime::Number(items.len() as f64)),
+                    Ok(Value::Object(map)) => return Ok(ValueRuntime::Number(map.len() as f64)),
+                    _ => {}
+                }
+            }
+
+            Ok(ValueRuntime::Number(text.chars().count() as f64))
+        }
+
+        "type_of" => {
+            if vals.len() != 1 {
+                return Err("type_of(x) expects exactly 1 argument".to_string());
+            }
+            let ty = match &vals[0] {
+                ValueRuntime::Int(_) | ValueRuntime::Number(_) => "number",
+                ValueRuntime::Bool(_) => "bool",
+                ValueRuntime::Reply(_) => "response",
+                ValueRuntime::Str(s) => {
+                    // Lists/maps travel as JSON strings, and JSON null comes
+                    // back from config/JSON helpers as the text "null".
+                    let trimmed = s.trim();
+                    if trimmed == "null" {
+                        "null"
+                    } else if trimmed.starts_with('[') || trimmed.starts_with('{') {
+                        match serde_json::from_str::<Value>(trimmed) {
+                            Ok(Value::Array(_)) => "list",
+                            Ok(Value::Object(_)) => "map",
+                            _ => "string",
+                        }
+                    } else {
+                        "string"
+                    }
+                }
+            };
+            Ok(ValueRuntime::Str(ty.to_string()))
         }
 
         "upper" => {
@@ -445,12 +1420,73 @@ fn eval_builtin(
             Ok(ValueRuntime::Str(vals[0].to_string().to_lowercase()))
         }
 
+        "trim" => {
+            if vals.len() != 1 {
+                return Err("trim(x) expects exactly 1 argument".to_string());
+            }
+            Ok(ValueRuntime::Str(vals[0].to_string().trim().to_string()))
+        }
+
+        "split" => {
+            if vals.len() != 2 {
+                return Err("split(s, sep) expects 2 arguments".to_string());
+            }
+            let text = vals[0].to_string();
+            let sep = vals[1].to_string();
+            let parts: Vec<Value> = if sep.is_empty() {
+                text.chars().map(|c| Value::String(c.to_string())).collect()
+            } else {
+                text.split(sep.as_str())
+                    .map(|p| Value::String(p.to_string()))
+                    .collect()
+            };
+            Ok(ValueRuntime::Str(Value::Array(parts).to_string()))
+        }
+
+        "join" => {
+            if vals.len() != 2 {
+                return Err("join(list, sep) expects 2 arguments".to_string());
+            }
+            let items = match value_to_json(&vals[0]) {
+                Value::Array(items) => items,
+                _ => return Err(format!("join: expected a list, got '{}'", vals[0])),
+            };
+            let parts: Vec<String> = items
+                .iter()
+                .map(|item| match item {
+                    Value::String(s) => s.clone(),
+                    other => json_to_runtime_value(other).to_string(),
+                })
+                .collect();
+            Ok(ValueRuntime::Str(parts.join(&vals[1].to_string())))
+        }
+
+        "replace" => {
+            if vals.len() != 3 {
+                return Err("replace(s, from, to) expects 3 arguments".to_string());
+            }
+            let from = vals[1].to_string();
+            if from.is_empty() {
+                return Err("replace: the text to replace must not be empty".to_string());
+            }
+            Ok(ValueRuntime::Str(
+                vals[0].to_string().replace(&from, &vals[2].to_string()),
+            ))
+        }
+
         "number" => {
             if vals.len() != 1 {
                 return Err("number(x) expects exactly 1 argument".to_string());
             }
-            let n = as_number(&vals[0])?;
-            Ok(ValueRuntime::Number(n))
+            if let ValueRuntime::Str(s) = &vals[0] {
+                if let Ok(i) = s.trim().parse::<i64>() {
+                    return Ok(ValueRuntime::Int(i));
+                }
+            }
+            match &vals[0] {
+                ValueRuntime::Int(i) => Ok(ValueRuntime::Int(*i)),
+                other => Ok(ValueRuntime::Number(as_number(other)?)),
+            }
         }
 
         "string" => {
@@ -511,20 +1547,96 @@ fn eval_builtin(
             Ok(ValueRuntime::Number(best))
         }
 
-        // --- generic config + env + secrets helpers ---
-        "config_set" => {
-            if vals.len() != 2 {
-                return Err("config_set(key, value) expects 2 arguments".to_string());
+        // --- timing helpers ---
+        "now_ms" => {
+            if !vals.is_empty() {
+                return Err("now_ms() expects no arguments".to_string());
             }
-            let key = vals[0].to_string();
-            let value_json = value_to_json(&vals[1]);
-            config::set_value(&key, value_json);
-            Ok(ValueRuntime::Str("ok".to_string()))
+            let start = CLOCK_START.get_or_init(Instant::now);
+            Ok(ValueRuntime::Number(start.elapsed().as_secs_f64() * 1000.0))
         }
 
-        "config_get" => {
-            if vals.is_empty() || vals.len() > 2 {
-                return Err("config_get(key, [default]) expects 1 or 2 arguments".to_string());
+        "format_number" => {
+            if vals.len() != 2 {
+                return Err("format_number(x, digits) expects 2 arguments".to_string());
+            }
+            let x = as_number(&vals[0])?;
+            let digits = as_number(&vals[1])?;
+            if !(0.0..=20.0).contains(&digits) || digits.fract() != 0.0 {
+                return Err(format!(
+                    "format_number: digits must be a whole number from 0 to 20, got {}",
+                    vals[1]
+                ));
+            }
+            // Round first, with the same halves-up rule as `round`; `{:.*}`
+            // alone would round 2.5 to "2". From 2^52 up every f64 is whole.
+            let scale = 10f64.powi(digits as i32);
+            let scaled = x * scale;
+            let rounded = if scaled.abs() < 2f64.powi(52) {
+                round_half_up(scaled) / scale
+            } else {
+                x
+            };
+            Ok(ValueRuntime::Str(format!(
+                "{:.*}",
+                digits as usize, rounded
+            )))
+        }
+
+        "floor" | "ceil" | "round" | "abs" => {
+            if vals.len() != 1 {
+                return Err(format!("{}(x) expects exactly 1 argument", name));
+            }
+            let x = as_number(&vals[0])?;
+            let result = match name {
+                "floor" => x.floor(),
+                "ceil" => x.ceil(),
+                // Half-up: f64::round would send -2.5 to -3.
+                "round" => round_half_up(x),
+                _ => x.abs(),
+            };
+            Ok(ValueRuntime::Number(result))
+        }
+
+        "sqrt" => {
+            if vals.len() != 1 {
+                return Err("sqrt(x) expects exactly 1 argument".to_string());
+            }
+            let x = as_number(&vals[0])?;
+            if x < 0.0 {
+                return Err(format!("sqrt: cannot take the square root of {}", vals[0]));
+            }
+            Ok(ValueRuntime::Number(x.sqrt()))
+        }
+
+        "pow" => {
+            if vals.len() != 2 {
+                return Err("pow(base, exp) expects 2 arguments".to_string());
+            }
+            let result = as_number(&vals[0])?.powf(as_number(&vals[1])?);
+            if !result.is_finite() {
+                return Err(format!(
+                    "pow: {} to the power {} is not a finite number",
+                    vals[0], vals[1]
+                ));
+            }
+            Ok(ValueRuntime::Number(result))
+        }
+
+        // --- generic config + env + secrets helpers ---
+        "config_set" => {
+            if vals.len() != 2 {
+                return Err("config_set(key, value) expects 2 arguments".to_string());
+            }
+            let key = vals[0].to_string();
+            let value_json = value_to_json(&vals[1]);
+            config::set_value(&key, value_json);
+            Ok(ValueRuntime::Str("ok".to_string()))
+        }
+
+        "config_get" => {
+            if vals.is_empty() || vals.len() > 2 {
+                return Err("config_get(key, [default]) expects 1 or 2 arguments".to_string());
             }
 
             let key = vals[0].to_string();
@@ -546,6 +1658,15 @@ fn eval_builtin(
             Ok(ValueRuntime::Bool(exists))
         }
 
+        "feature" => {
+            if vals.len() != 1 {
+                return Err("feature(name) expects exactly 1 argument".to_string());
+            }
+            Ok(ValueRuntime::Bool(config::feature_enabled(
+                &vals[0].to_string(),
+            )))
+        }
+
         "env" => {
             if vals.len() != 1 {
                 return Err("env(name) expects exactly 1 argument".to_string());
@@ -563,28 +1684,13 @@ fn eval_builtin(
 
             let logical = vals[0].to_string();
 
-            let env_key = config::secret_env_from_file(&logical)
-                .or_else(|| {
-                    program
-                        .secrets
-                        .iter()
-                        .find(|s| s.name == logical)
-                        .map(|s| s.key.clone())
-                })
-                .unwrap_or_else(|| logical.clone());
-
-            let default = if vals.len() == 2 {
-                Some(vals[1].clone())
-            } else {
-                None
-            };
-
-            match env::var(&env_key) {
-                Ok(v) => Ok(ValueRuntime::Str(v)),
-                Err(_) => {
-                    if let Some(d) = default {
-                        Ok(d)
+            match config::secret_value(&logical, Some(&program.secrets)) {
+                Some(v) => Ok(ValueRuntime::Str(v)),
+                None => {
+                    if vals.len() == 2 {
+                        Ok(vals[1].clone())
                     } else {
+                        let env_key = config::secret_env_key(&logical, Some(&program.secrets));
                         Err(format!(
                             "Secret '{}' (env '{}') is not set",
                             logical, env_key
@@ -594,13 +1700,71 @@ fn eval_builtin(
             }
         }
 
+        "secret_set" => {
+            if vals.len() != 2 {
+                return Err("secret_set(name, value) expects 2 arguments".to_string());
+            }
+            if !config::secret_overrides_allowed() {
+                return Err(format!(
+                    "secret_set is only allowed when SHRIMPL_ENV is dev or test (current: '{}')",
+                    config::env_name()
+                ));
+            }
+            let logical = vals[0].to_string();
+            config::set_secret_override(&logical, &vals[1].to_string());
+            Ok(ValueRuntime::Str("ok".to_string()))
+        }
+
+        // --- In-memory cache helpers ---
+        "cache_set" => {
+            if vals.len() != 2 && vals.len() != 3 {
+                return Err(
+                    "cache_set(key, value[, ttl_secs]) expects 2 or 3 arguments".to_string()
+                );
+            }
+            let ttl = match vals.get(2) {
+                Some(v) => {
+                    let secs = as_number(v)?;
+                    if !(secs >= 0.0 && secs.fract() == 0.0) {
+                        return Err(format!(
+                            "cache_set: ttl_secs must be a whole number of seconds >= 0, got '{}'",
+                            v
+                        ));
+                    }
+                    Some(secs as u64)
+                }
+                None => None,
+            };
+            let key = format!("{}{}", APP_CACHE_PREFIX, vals[0]);
+            cache::cache_set(key, value_to_json(&vals[1]), ttl);
+            Ok(ValueRuntime::Str("ok".to_string()))
+        }
+
+        "cache_get" => {
+            if vals.len() != 1 {
+                return Err("cache_get(key) expects 1 argument".to_string());
+            }
+            let key = format!("{}{}", APP_CACHE_PREFIX, vals[0]);
+            Ok(cache::cache_get(&key)
+                .map(|v| json_to_runtime_value(&v))
+                .unwrap_or_else(|| ValueRuntime::Str(String::new())))
+        }
+
+        "cache_delete" => {
+            if vals.len() != 1 {
+                return Err("cache_delete(key) expects 1 argument".to_string());
+            }
+            cache::cache_delete(&format!("{}{}", APP_CACHE_PREFIX, vals[0]));
+            Ok(ValueRuntime::Str("ok".to_string()))
+        }
+
         // --- HTTP client helpers ---
         "http_get" => {
             if vals.len() != 1 {
                 return Err("http_get(url) expects exactly 1 argument".to_string());
             }
             let url = vals[0].to_string();
-            let resp = ureq::get(&url).call();
+            let resp = http_client::agent().get(&url).call();
             match resp {
                 Ok(r) => match r.into_string() {
                     Ok(body) => Ok(ValueRuntime::Str(body)),
@@ -615,7 +1779,7 @@ fn eval_builtin(
                 return Err("http_get_json(url) expects exactly 1 argument".to_string());
             }
             let url = vals[0].to_string();
-            let resp = ureq::get(&url).call();
+            let resp = http_client::agent().get(&url).call();
             match resp {
                 Ok(r) => {
                     let text = r.into_string().map_err(|e| {
@@ -632,6 +1796,113 @@ fn eval_builtin(
             }
         }
 
+        "http_post" | "http_post_json" => {
+            if vals.len() != 2 && vals.len() != 3 {
+                return Err(format!(
+                    "{}(url, body[, token]) expects 2 or 3 arguments",
+                    name
+                ));
+            }
+            let url = vals[0].to_string();
+            let (content_type, body) = if name == "http_post_json" {
+                ("application/json", value_to_json(&vals[1]).to_string())
+            } else {
+                ("text/plain; charset=utf-8", vals[1].to_string())
+            };
+
+            let mut req = http_client::agent()
+                .post(&url)
+                .set("Content-Type", content_type);
+            if let Some(token) = vals.get(2) {
+                req = req.set("Authorization", &format!("Bearer {}", token));
+            }
+
+            match req.send_string(&body) {
+                Ok(r) => r
+                    .into_string()
+                    .map(ValueRuntime::Str)
+                    .map_err(|e| format!("{}({}): failed to read body: {}", name, url, e)),
+                Err(ureq::Error::Status(code, r)) => {
+                    // Include the start of the error body; APIs usually
+                    // explain the failure there.
+                    let detail: String = r
+                        .into_string()
+                        .unwrap_or_default()
+                        .trim()
+                        .chars()
+                        .take(200)
+                        .collect();
+                    let mut msg = format!("{}({}): HTTP status {}", name, url, code);
+                    if !detail.is_empty() {
+                        msg.push_str(&format!(": {}", detail));
+                    }
+                    Err(msg)
+                }
+                Err(err) => Err(format!("{}({}): {}", name, url, err)),
+            }
+        }
+
+        "http_get_many" => {
+            if vals.len() != 1 {
+                return Err("http_get_many(urls) expects exactly 1 argument".to_string());
+            }
+            let urls = match value_to_json(&vals[0]) {
+                Value::Array(items) => items
+                    .into_iter()
+                    .map(|item| match item {
+                        Value::String(url) => Ok(url),
+                        other => Err(format!(
+                            "http_get_many: expected a URL string, got {}",
+                            other
+                        )),
+                    })
+                    .collect::<EvalResult<Vec<String>>>()?,
+                _ => {
+                    return Err(format!(
+                        "http_get_many: expected a list of URLs, got '{}'",
+                        vals[0]
+                    ))
+                }
+            };
+            let bodies = concurrency::block_on(concurrency::http_get_many(urls))
+                .map_err(|e| format!("http_get_many: {}", e))?;
+            Ok(ValueRuntime::Str(Value::from(bodies).to_string()))
+        }
+
+        // --- URL helpers ---
+        "url_parse" => {
+            if vals.len() != 1 {
+                return Err("url_parse(url) expects exactly 1 argument".to_string());
+            }
+            let raw = vals[0].to_string();
+            let parsed = url::Url::parse(raw.trim())
+                .map_err(|e| format!("url_parse: invalid URL '{}': {}", raw, e))?;
+            let parts = json!({
+                "scheme": parsed.scheme(),
+                "host": parsed.host_str().unwrap_or(""),
+                "port": parsed.port_or_known_default(),
+                "path": parsed.path(),
+                "query": parsed.query().unwrap_or(""),
+            });
+            Ok(ValueRuntime::Str(parts.to_string()))
+        }
+
+        "query_parse" => {
+            if vals.len() != 1 {
+                return Err("query_parse(query_string) expects exactly 1 argument".to_string());
+            }
+            let qs = vals[0].to_string();
+            let params = parse_query_string(qs.strip_prefix('?').unwrap_or(&qs));
+            // Sorted keys so the same query always prints the same object.
+            let obj: serde_json::Map<String, Value> = params
+                .into_iter()
+                .collect::<std::collections::BTreeMap<_, _>>()
+                .into_iter()
+                .map(|(k, v)| (k, Value::String(v)))
+                .collect();
+            Ok(ValueRuntime::Str(Value::Object(obj).to_string()))
+        }
+
         // --- vector / tensor helpers ---
         "vec" => {
             if vals.is_empty() {
@@ -650,6 +1921,97 @@ fn eval_builtin(
             Ok(ValueRuntime::Str(txt))
         }
 
+        "range" => {
+            let (start, end, step) = match vals.len() {
+                1 => (0.0, as_number(&vals[0])?, 1.0),
+                2 => (as_number(&vals[0])?, as_number(&vals[1])?, 1.0),
+                3 => (
+                    as_number(&vals[0])?,
+                    as_number(&vals[1])?,
+                    as_number(&vals[2])?,
+                ),
+                _ => {
+                    return Err(
+                        "range(n) / range(start, end) / range(start, end, step) expects 1 to 3 arguments"
+                            .to_string(),
+                    )
+                }
+            };
+            if step == 0.0 {
+                return Err("range: step must not be 0".to_string());
+            }
+
+            // Same half-open semantics as Python: [start, end).
+            let count = ((end - start) / step).ceil().max(0.0);
+            if count > MAX_LOOP_ITERATIONS as f64 {
+                return Err("range: too many elements (max 10_000)".to_string());
+            }
+
+            let integral = start.fract() == 0.0 && step.fract() == 0.0;
+            let arr: Vec<Value> = (0..count as usize)
+                .map(|k| {
+                    let n = start + step * k as f64;
+                    if integral {
+                        json!(n as i64)
+                    } else {
+                        json!(n)
+                    }
+                })
+                .collect();
+            let txt =
+                serde_json::to_string(&Value::Array(arr)).unwrap_or_else(|_| "[]".to_string());
+            Ok(ValueRuntime::Str(txt))
+        }
+
+        "sort" | "sort_desc" => {
+            if vals.len() != 1 {
+                return Err(format!("{}(list_json) expects exactly 1 argument", name));
+            }
+            let mut items = parse_json_array(name, &vals[0].to_string())?;
+
+            if items.iter().all(|v| v.is_number()) {
+                items.sort_by(|a, b| {
+                    let x = a.as_f64().unwrap_or(0.0);
+                    let y = b.as_f64().unwrap_or(0.0);
+                    x.total_cmp(&y)
+                });
+            } else {
+                items.sort_by_key(sort_key_text);
+            }
+            if name == "sort_desc" {
+                items.reverse();
+            }
+
+            let txt =
+                serde_json::to_string(&Value::Array(items)).unwrap_or_else(|_| "[]".to_string());
+            Ok(ValueRuntime::Str(txt))
+        }
+
+        "unique" => {
+            if vals.len() != 1 {
+                return Err("unique(list_json) expects exactly 1 argument".to_string());
+            }
+            let items = parse_json_array("unique", &vals[0].to_string())?;
+
+            // Numbers compare by value so 1 and 1.0 count as the same element.
+            let mut seen = std::collections::HashSet::new();
+            let out: Vec<Value> = items
+                .into_iter()
+                .filter(|v| {
+                    let key = match (v.as_i64(), v.as_f64()) {
+                        (Some(i), _) => format!("n:{}", i),
+                        (None, Some(n)) => format!("n:{}", n),
+                        _ => format!("v:{}", v),
+                    };
+                    seen.insert(key)
+                })
+                .collect();
+
+            let txt =
+                serde_json::to_string(&Value::Array(out)).unwrap_or_else(|_| "[]".to_string());
+            Ok(ValueRuntime::Str(txt))
+        }
+
         "tensor_add" => {
             if vals.len() != 2 {
                 return Err("tensor_add(a, b) expects 2 arguments".to_string());
@@ -699,7 +2061,7 @@ fn eval_builtin(
                 return Err("df_from_csv(url) expects exactly 1 argument".to_string());
             }
             let url = vals[0].to_string();
-            let resp = ureq::get(&url).call();
+            let resp = http_client::agent().get(&url).call();
             let text = match resp {
                 Ok(r) => r
                     .into_string()
@@ -709,49 +2071,44 @@ fn eval_builtin(
                 }
             };
 
-            let mut rdr = csv::ReaderBuilder::new()
-                .has_headers(true)
-                .from_reader(Cursor::new(text.into_bytes()));
-
-            let headers_record = rdr
-                .headers()
-                .map_err(|e| format!("df_from_csv({}): failed to read headers: {}", url, e))?;
-            let headers: Vec<String> = headers_record.iter().map(|s| s.to_string()).collect();
+            let label = format!("df_from_csv({})", url);
+            df_from_csv_reader(&label, Cursor::new(text.into_bytes()))
+        }
 
-            let mut rows_json: Vec<Value> = Vec::new();
-            for rec in rdr.records() {
-                let record =
-                    rec.map_err(|e| format!("df_from_csv({}): failed to read record: {}", url, e))?;
-                let mut row_vals: Vec<Value> = Vec::new();
-                for field in record.iter() {
-                    if let Ok(n) = field.parse::<f64>() {
-                        row_vals.push(json!(n));
-                    } else {
-                        row_vals.push(json!(field));
-                    }
-                }
-                rows_json.push(Value::Array(row_vals));
+        "df_from_csv_text" => {
+            if vals.len() != 1 {
+                return Err("df_from_csv_text(csv_text) expects exactly 1 argument".to_string());
             }
+            let text = vals[0].to_string();
+            df_from_csv_reader("df_from_csv_text", Cursor::new(text.into_bytes()))
+        }
 
-            let table = json!({
-                "columns": headers,
-                "rows": rows_json
-            });
+        "df_from_csv_file" => {
+            if vals.len() != 1 {
+                return Err("df_from_csv_file(path) expects exactly 1 argument".to_string());
+            }
+            let path = vals[0].to_string();
+            let label = format!("df_from_csv_file({})", path);
 
-            let txt = serde_json::to_string(&table).unwrap_or_else(|_| "{}".to_string());
-            Ok(ValueRuntime::Str(txt))
+            let resolved = resolve_data_path(&path).map_err(|e| format!("{}: {}", label, e))?;
+            let file = fs::File::open(&resolved)
+                .map_err(|e| format!("{}: failed to open file: {}", label, e))?;
+            df_from_csv_reader(&label, file)
         }
 
-        "df_head" => {
+        "df_head" | "df_tail" => {
             if vals.len() != 2 {
-                return Err("df_head(df_json, n) expects 2 arguments".to_string());
+                return Err(format!("{}(df_json, n) expects 2 arguments", name));
             }
             let df_txt = vals[0].to_string();
-            let n = as_number(&vals[1])? as usize;
+            let n = df_row_count(name, &vals[1])?;
 
             let mut df = parse_df(&df_txt)?;
-            if df.rows.len() > n {
+            let n = n.min(df.rows.len());
+            if name == "df_head" {
                 df.rows.truncate(n);
+            } else {
+                df.rows.drain(..df.rows.len() - n);
             }
 
             let table = json!({
@@ -824,6 +2181,76 @@ fn eval_builtin(
             Ok(ValueRuntime::Str(txt))
         }
 
+        "df_from_json" => {
+            if vals.len() != 1 {
+                return Err("df_from_json(rows_json) expects exactly 1 argument".to_string());
+            }
+            let rows_txt = vals[0].to_string();
+
+            let rows_val: Value = serde_json::from_str(&rows_txt)
+                .map_err(|e| format!("df_from_json: rows_json is not valid JSON: {}", e))?;
+            let items = rows_val
+                .as_array()
+                .ok_or_else(|| "df_from_json: expected a JSON array of objects".to_string())?;
+
+            let mut columns: Vec<String> = Vec::new();
+            for item in items {
+                let obj = item
+                    .as_object()
+                    .ok_or_else(|| "df_from_json: every row must be a JSON object".to_string())?;
+                for key in obj.keys() {
+                    if !columns.contains(key) {
+                        columns.push(key.clone());
+                    }
+                }
+            }
+
+            let mut rows_json: Vec<Value> = Vec::new();
+            for item in items {
+                let row_vals: Vec<Value> = columns
+                    .iter()
+                    .map(|c| item.get(c).cloned().unwrap_or(Value::Null))
+                    .collect();
+                rows_json.push(Value::Array(row_vals));
+            }
+
+            let table = json!({
+                "columns": columns,
+                "rows": rows_json
+            });
+
+            let txt = serde_json::to_string(&table).unwrap_or_else(|_| "{}".to_string());
+            Ok(ValueRuntime::Str(txt))
+        }
+
+        "df_to_csv" => {
+            if vals.len() != 1 {
+                return Err("df_to_csv(df_json) expects exactly 1 argument".to_string());
+            }
+            let df_txt = vals[0].to_string();
+            let df = parse_df(&df_txt)?;
+
+            let mut wtr = csv::Writer::from_writer(Vec::new());
+            wtr.write_record(&df.columns)
+                .map_err(|e| format!("df_to_csv: failed to write header: {}", e))?;
+
+            for row in &df.rows {
+                let row_arr = row
+                    .as_array()
+                    .ok_or_else(|| "df_to_csv: row is not an array".to_string())?;
+                let fields: Vec<String> = row_arr.iter().map(csv_field_from_json).collect();
+                wtr.write_record(&fields)
+                    .map_err(|e| format!("df_to_csv: failed to write row: {}", e))?;
+            }
+
+            let bytes = wtr
+                .into_inner()
+                .map_err(|e| format!("df_to_csv: failed to flush CSV: {}", e))?;
+            let txt = String::from_utf8(bytes)
+                .map_err(|e| format!("df_to_csv: CSV is not valid UTF-8: {}", e))?;
+            Ok(ValueRuntime::Str(txt))
+        }
+
         // --- ML helpers: simple linear regression ---
         "linreg_fit" => {
             if vals.len() != 2 {
@@ -902,9 +2329,7 @@ fn eval_builtin(
             }
             let key = vals[0].to_string();
             let cfg_lock = get_openai_config();
-            let mut cfg = cfg_lock
-                .lock()
-                .map_err(|_| "OpenAI config mutex poisoned".to_string())?;
+            let mut cfg = cfg_lock.lock();
             cfg.api_key = Some(key);
             Ok(ValueRuntime::Str("ok".to_string()))
         }
@@ -915,9 +2340,7 @@ fn eval_builtin(
             }
             let prompt = vals[0].to_string();
             let cfg_lock = get_openai_config();
-            let mut cfg = cfg_lock
-                .lock()
-                .map_err(|_| "OpenAI config mutex poisoned".to_string())?;
+            let mut cfg = cfg_lock.lock();
             cfg.system_prompt = Some(prompt);
             Ok(ValueRuntime::Str("ok".to_string()))
         }
@@ -928,27 +2351,21 @@ fn eval_builtin(
             }
             let user_msg = vals[0].to_string();
 
-            let cfg_lock = get_openai_config();
-            let cfg = cfg_lock
-                .lock()
-                .map_err(|_| "OpenAI config mutex poisoned".to_string())?;
-            let model = cfg.model.clone();
-            let system_prompt = cfg.system_prompt.clone();
-            drop(cfg);
-
-            let mut messages: Vec<Value> = Vec::new();
-            if let Some(sp) = system_prompt {
-                messages.push(json!({ "role": "system", "content": sp }));
-            }
-            messages.push(json!({
-                "role": "user",
-                "content": user_msg
-            }));
-
-            let payload = json!({
-                "model": model,
-                "messages": messages,
+            let payload = openai_chat_payload(user_msg);
+
+            // The payload holds model + system prompt + message, so it is the cache key.
+            let cached = config::openai_cache_ttl().map(|ttl| {
+                let digest = Sha256::digest(payload.to_string().as_bytes());
+                (
+                    format!("{}{}", OPENAI_CACHE_PREFIX, hex::encode(digest)),
+                    ttl,
+                )
             });
+            if let Some((key, _)) = &cached {
+                if let Some(Value::String(text)) = cache::cache_get(key) {
+                    return Ok(ValueRuntime::Str(text));
+                }
+            }
 
             let json_resp = openai_post("chat/completions", &payload)?;
             let text = json_resp
@@ -961,36 +2378,28 @@ fn eval_builtin(
                 .unwrap_or("")
                 .to_string();
 
+            if let Some((key, ttl)) = cached {
+                cache::cache_set(key, Value::String(text.clone()), Some(ttl));
+            }
+
             Ok(ValueRuntime::Str(text))
         }
 
+        "openai_cache_clear" => {
+            if !vals.is_empty() {
+                return Err("openai_cache_clear() expects no arguments".to_string());
+            }
+            let removed = cache::cache_clear_prefix(OPENAI_CACHE_PREFIX);
+            Ok(ValueRuntime::Number(removed as f64))
+        }
+
         "openai_chat_json" => {
             if vals.len() != 1 {
                 return Err("openai_chat_json(user_message) expects 1 argument".to_string());
             }
             let user_msg = vals[0].to_string();
 
-            let cfg_lock = get_openai_config();
-            let cfg = cfg_lock
-                .lock()
-                .map_err(|_| "OpenAI config mutex poisoned".to_string())?;
-            let model = cfg.model.clone();
-            let system_prompt = cfg.system_prompt.clone();
-            drop(cfg);
-
-            let mut messages: Vec<Value> = Vec::new();
-            if let Some(sp) = system_prompt {
-                messages.push(json!({ "role": "system", "content": sp }));
-            }
-            messages.push(json!({
-                "role": "user",
-                "content": user_msg
-            }));
-
-            let payload = json!({
-                "model": model,
-                "messages": messages,
-            });
+            let payload = openai_chat_payload(user_msg);
 
             let json_resp = openai_post("chat/completions", &payload)?;
             let txt =
@@ -998,10 +2407,27 @@ fn eval_builtin(
             Ok(ValueRuntime::Str(txt))
         }
 
+        "openai_chat_tools" => {
+            if vals.len() != 2 {
+                return Err(
+                    "openai_chat_tools(user_message, tools_json) expects 2 arguments".to_string(),
+                );
+            }
+            let user_msg = vals[0].to_string();
+            let tools = openai_tools_from_json(&vals[1].to_string())?;
+
+            let mut payload = openai_chat_payload(user_msg);
+            payload["tools"] = Value::Array(tools);
+
+            let json_resp = openai_post("chat/completions", &payload)?;
+            let result = openai_tool_call_result(&json_resp)?;
+            Ok(ValueRuntime::Str(result.to_string()))
+        }
+
         "openai_mcp_call" => {
-            if vals.len() != 3 {
+            if vals.len() != 3 && vals.len() != 4 {
                 return Err(
-                    "openai_mcp_call(server_id, tool_name, args_json) expects 3 arguments"
+                    "openai_mcp_call(server_id, tool_name, args_json[, options_json]) expects 3 or 4 arguments"
                         .to_string(),
                 );
             }
@@ -1013,14 +2439,31 @@ fn eval_builtin(
             let args_val: Value =
                 serde_json::from_str(&args_raw).unwrap_or_else(|_| json!({ "raw": args_raw }));
 
-            let cfg_lock = get_openai_config();
-            let cfg = cfg_lock
-                .lock()
-                .map_err(|_| "OpenAI config mutex poisoned".to_string())?;
-            let model = cfg.model.clone();
-            drop(cfg);
+            let options: Value = match vals.get(3) {
+                Some(v) => {
+                    let text = v.to_string();
+                    match serde_json::from_str::<Value>(&text) {
+                        Ok(Value::Object(map)) => Value::Object(map),
+                        _ => {
+                            return Err(format!(
+                                "openai_mcp_call: options must be a JSON object, got '{}'",
+                                text
+                            ))
+                        }
+                    }
+                }
+                None => json!({}),
+            };
+            let opt_str = |key: &str| options.get(key).and_then(|v| v.as_str()).map(String::from);
+            let raw = options
+                .get("raw")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+
+            let model =
+                opt_str("model").unwrap_or_else(|| get_openai_config().lock().model.clone());
 
-            let payload = json!({
+            let mut payload = json!({
                 "model": model,
                 "input": format!(
                     "Call MCP tool '{}' on server '{}' with args: {}",
@@ -1028,10 +2471,205 @@ fn eval_builtin(
                 ),
             });
 
+            // With a server URL, let the model call the tool directly.
+            let is_url = server_id.starts_with("http://") || server_id.starts_with("https://");
+            let server_url = opt_str("server_url").or_else(|| is_url.then(|| server_id.clone()));
+            if let Some(url) = server_url {
+                let label = opt_str("server_label").unwrap_or_else(|| {
+                    if is_url {
+                        "shrimpl".to_string()
+                    } else {
+                        server_id.clone()
+                    }
+                });
+                payload["tools"] = json!([{
+                    "type": "mcp",
+                    "server_label": label,
+                    "server_url": url,
+                    "allowed_tools": [tool_name],
+                    "require_approval": "never",
+                }]);
+            }
+
             let json_resp = openai_post("responses", &payload)?;
-            let txt =
-                serde_json::to_string_pretty(&json_resp).unwrap_or_else(|_| json_resp.to_string());
-            Ok(ValueRuntime::Str(txt))
+            if raw {
+                let txt = serde_json::to_string_pretty(&json_resp)
+                    .unwrap_or_else(|_| json_resp.to_string());
+                return Ok(ValueRuntime::Str(txt));
+            }
+
+            let result = extract_mcp_result(&json_resp, &tool_name)?;
+            Ok(ValueRuntime::Str(
+                json!({ "tool": tool_name, "result": result }).to_string(),
+            ))
+        }
+
+        // --- validation helpers ---
+        // --- JSON object helpers ---
+        "json_merge" => {
+            if vals.len() != 2 {
+                return Err("json_merge(a_json, b_json) expects 2 arguments".to_string());
+            }
+            let mut merged = Value::Object(json_object_arg(name, &vals[0])?);
+            config::merge_json(&mut merged, Value::Object(json_object_arg(name, &vals[1])?));
+            Ok(ValueRuntime::Str(merged.to_string()))
+        }
+
+        "json_pick" | "json_omit" => {
+            if vals.len() != 2 {
+                return Err(format!("{}(obj_json, keys) expects 2 arguments", name));
+            }
+            let obj = json_object_arg(name, &vals[0])?;
+            let keys = key_list_arg(name, &vals[1])?;
+            let keep = name == "json_pick";
+            let filtered: serde_json::Map<String, Value> = obj
+                .into_iter()
+                .filter(|(k, _)| keys.contains(k) == keep)
+                .collect();
+            Ok(ValueRuntime::Str(Value::Object(filtered).to_string()))
+        }
+
+        "parse_json" => {
+            if vals.len() != 1 {
+                return Err("parse_json(text) expects exactly 1 argument".to_string());
+            }
+            let parsed: Value = serde_json::from_str(&vals[0].to_string())
+                .map_err(|e| format!("parse_json: invalid JSON: {}", e))?;
+            Ok(json_to_runtime_value(&parsed))
+        }
+
+        "to_json" => {
+            if vals.len() != 1 {
+                return Err("to_json(value) expects exactly 1 argument".to_string());
+            }
+            let json = match &vals[0] {
+                ValueRuntime::Str(s) => Value::String(s.clone()),
+                other => value_to_json(other),
+            };
+            Ok(ValueRuntime::Str(json.to_string()))
+        }
+
+        "validate" | "validate_errors" => {
+            if vals.len() != 2 {
+                return Err(format!(
+                    "{}(value_json, schema_json) expects 2 arguments",
+                    name
+                ));
+            }
+            let value = value_to_json(&vals[0]);
+            let schema_text = vals[1].to_string();
+            let schema: Value = serde_json::from_str(&schema_text)
+                .map_err(|e| format!("{}: schema is not valid JSON: {}", name, e))?;
+            let compiled = compile_json_schema(&schema).map_err(|e| format!("{}: {}", name, e))?;
+
+            if name == "validate" {
+                return Ok(ValueRuntime::Bool(compiled.is_valid(&value)));
+            }
+
+            let messages: Vec<String> = match compiled.validate(&value) {
+                Ok(()) => Vec::new(),
+                Err(errors) => errors.map(|e| schema_error_message(&e)).collect(),
+            };
+            Ok(ValueRuntime::Str(json!(messages).to_string()))
+        }
+
+        // --- response helpers ---
+        "download" => {
+            if vals.len() != 2 && vals.len() != 3 {
+                return Err(
+                    "download(filename, content[, content_type]) expects 2 or 3 arguments"
+                        .to_string(),
+                );
+            }
+            let filename = vals[0].to_string();
+            let content = vals[1].to_string();
+            let content_type = match vals.get(2) {
+                Some(v) => v.to_string(),
+                None => content_type_for_filename(&filename).to_string(),
+            };
+
+            Ok(ValueRuntime::Reply(Box::new(Reply::Download {
+                filename,
+                content,
+                content_type,
+            })))
+        }
+
+        "with_content_type" => {
+            if vals.len() != 2 {
+                return Err(
+                    "with_content_type(content, content_type) expects 2 arguments".to_string(),
+                );
+            }
+            Ok(ValueRuntime::Reply(Box::new(Reply::WithContentType {
+                content: vals[0].to_string(),
+                content_type: vals[1].to_string(),
+            })))
+        }
+
+        "template" => {
+            if vals.len() != 2 && vals.len() != 3 {
+                return Err(
+                    "template(source, vars_json[, strict]) expects 2 or 3 arguments".to_string(),
+                );
+            }
+            let vars = json_object_arg("template", &vals[1])?;
+            let strict = match vals.get(2) {
+                Some(v) => as_bool(v)?,
+                None => false,
+            };
+            render_template(&vals[0].to_string(), &vars, strict).map(ValueRuntime::Str)
+        }
+
+        "to_yaml" | "to_toml" => {
+            if vals.len() != 1 {
+                return Err(format!("{}(json) expects exactly 1 argument", name));
+            }
+            let json: Value = serde_json::from_str(&vals[0].to_string())
+                .map_err(|e| format!("{}: invalid JSON: {}", name, e))?;
+            let text = if name == "to_yaml" {
+                dataformats::to_yaml(&json)
+            } else {
+                dataformats::to_toml(&json).map_err(|e| format!("to_toml: {}", e))?
+            };
+            Ok(ValueRuntime::Str(text))
+        }
+
+        "from_yaml" | "from_toml" => {
+            if vals.len() != 1 {
+                return Err(format!("{}(text) expects exactly 1 argument", name));
+            }
+            let text = vals[0].to_string();
+            let parsed = if name == "from_yaml" {
+                dataformats::from_yaml(&text)
+            } else {
+                dataformats::from_toml(&text)
+            };
+            let json = parsed.map_err(|e| format!("{}: {}", name, e))?;
+            Ok(ValueRuntime::Str(json.to_string()))
+        }
+
+        "db_stream" => {
+            if vals.is_empty() || vals.len() > 2 {
+                return Err("db_stream(model_name[, format]) expects 1 or 2 arguments".to_string());
+            }
+            let model_name = vals[0].to_string();
+            let format = match vals.get(1) {
+                Some(v) => v.to_string().to_ascii_lowercase(),
+                None => "json".to_string(),
+            };
+            if !STREAM_FORMATS.contains(&format.as_str()) {
+                return Err(format!(
+                    "db_stream: unknown format '{}'; expected json, ndjson, or csv",
+                    format
+                ));
+            }
+            orm::orm_field_names(&model_name).map_err(|e| format!("db_stream: {}", e))?;
+
+            Ok(ValueRuntime::Reply(Box::new(Reply::Stream {
+                model: model_name,
+                format,
+            })))
         }
 
         // --- ORM helpers ---
@@ -1066,6 +2704,127 @@ fn eval_builtin(
             Ok(ValueRuntime::Str(out))
         }
 
+        "db_insert_many" => {
+            if vals.len() != 2 {
+                return Err(
+                    "db_insert_many(model_name, records_json) expects 2 arguments".to_string(),
+                );
+            }
+
+            let model_name = vals[0].to_string();
+            let records_json = vals[1].to_string();
+
+            let inserted = orm::orm_insert_many(&model_name, &records_json)
+                .map_err(|e| format!("db_insert_many: {}", e))?;
+
+            Ok(ValueRuntime::Int(inserted as i64))
+        }
+
+        "db_count" => {
+            if vals.len() != 1 {
+                return Err("db_count(model_name) expects exactly 1 argument".to_string());
+            }
+
+            let model_name = vals[0].to_string();
+            let count = orm::orm_count(&model_name).map_err(|e| format!("db_count: {}", e))?;
+
+            Ok(ValueRuntime::Number(count as f64))
+        }
+
+        "db_count_where" => {
+            if vals.len() != 3 {
+                return Err(
+                    "db_count_where(model_name, column, value) expects 3 arguments".to_string(),
+                );
+            }
+
+            let model_name = vals[0].to_string();
+            let column = vals[1].to_string();
+            let value_json = value_to_json(&vals[2]).to_string();
+
+            let count = orm::orm_count_where(&model_name, &column, &value_json)
+                .map_err(|e| format!("db_count_where: {}", e))?;
+
+            Ok(ValueRuntime::Number(count as f64))
+        }
+
+        "db_update" => {
+            if vals.len() != 3 {
+                return Err(
+                    "db_update(model_name, id_json, patch_json) expects 3 arguments".to_string(),
+                );
+            }
+
+            let model_name = vals[0].to_string();
+            let id_json = vals[1].to_string();
+            let patch_json = value_to_json(&vals[2]).to_string();
+
+            let changed = orm::orm_update(&model_name, &id_json, &patch_json)
+                .map_err(|e| format!("db_update: {}", e))?;
+
+            Ok(ValueRuntime::Int(changed as i64))
+        }
+
+        "db_delete" => {
+            if vals.len() != 2 {
+                return Err("db_delete(model_name, id_json) expects 2 arguments".to_string());
+            }
+
+            let model_name = vals[0].to_string();
+            let id_json = vals[1].to_string();
+
+            let deleted =
+                orm::orm_delete(&model_name, &id_json).map_err(|e| format!("db_delete: {}", e))?;
+
+            Ok(ValueRuntime::Int(deleted as i64))
+        }
+
+        "db_all" => {
+            if vals.len() != 1 {
+                return Err("db_all(model_name) expects exactly 1 argument".to_string());
+            }
+
+            let model_name = vals[0].to_string();
+            let rows = orm::orm_all(&model_name).map_err(|e| format!("db_all: {}", e))?;
+
+            Ok(ValueRuntime::Str(rows))
+        }
+
+        "db_where" => {
+            if vals.len() != 3 {
+                return Err("db_where(model_name, column, value) expects 3 arguments".to_string());
+            }
+
+            let model_name = vals[0].to_string();
+            let column = vals[1].to_string();
+            let value_json = value_to_json(&vals[2]).to_string();
+
+            let rows = orm::orm_find_where(&model_name, &column, &value_json)
+                .map_err(|e| format!("db_where: {}", e))?;
+
+            Ok(ValueRuntime::Str(rows))
+        }
+
+        "db_aggregate" => {
+            if vals.len() != 3 {
+                return Err(
+                    "db_aggregate(model_name, column, func) expects 3 arguments".to_string()
+                );
+            }
+
+            let model_name = vals[0].to_string();
+            let column = vals[1].to_string();
+            let func = vals[2].to_string();
+
+            let result = orm::orm_aggregate(&model_name, &column, &func)
+                .map_err(|e| format!("db_aggregate: {}", e))?;
+
+            match result {
+                Some(n) => Ok(ValueRuntime::Number(n)),
+                None => Ok(ValueRuntime::Str(String::new())),
+            }
+        }
+
         _ => Err(format!("Undefined function '{}'", name)),
     }
 }
@@ -1074,18 +2833,21 @@ fn eval_builtin(
 
 fn value_to_json(v: &ValueRuntime) -> Value {
     match v {
-        ValueRuntime::Number(n) => json!(n),
+        ValueRuntime::Int(i) => json!(i),
+        ValueRuntime::Number(n) => json!(tidy_float(*n)),
         ValueRuntime::Bool(b) => json!(*b),
-        ValueRuntime::Str(s) => {
-            // Try to parse as JSON; fall back to string.
-            serde_json::from_str::<Value>(s).unwrap_or_else(|_| json!(s))
-        }
+        // Lists and maps are JSON text, so nest them as JSON. Anything else
+        // stays a string: "12", "true" and "null" are not reinterpreted.
+        ValueRuntime::Str(s) => parse_json_container(s).unwrap_or_else(|| json!(s)),
+        ValueRuntime::Reply(r) => json!(r.to_string()),
     }
 }
 
 fn json_to_runtime_value(v: &Value) -> ValueRuntime {
     if let Some(b) = v.as_bool() {
         ValueRuntime::Bool(b)
+    } else if let Some(i) = v.as_i64() {
+        ValueRuntime::Int(i)
     } else if let Some(n) = v.as_f64() {
         ValueRuntime::Number(n)
     } else if let Some(s) = v.as_str() {
@@ -1095,32 +2857,212 @@ fn json_to_runtime_value(v: &Value) -> ValueRuntime {
     }
 }
 
+/// A JSON object argument (a map literal or a JSON string) for `builtin`.
+fn json_object_arg(builtin: &str, v: &ValueRuntime) -> EvalResult<serde_json::Map<String, Value>> {
+    match value_to_json(v) {
+        Value::Object(obj) => Ok(obj),
+        _ => Err(format!("{}: expected a JSON object, got '{}'", builtin, v)),
+    }
+}
+
+/// Key names given as "a,b" (like df_select) or as a list of strings.
+fn key_list_arg(builtin: &str, v: &ValueRuntime) -> EvalResult<Vec<String>> {
+    match value_to_json(v) {
+        Value::Array(items) => items
+            .into_iter()
+            .map(|item| match item {
+                Value::String(s) => Ok(s),
+                other => Err(format!("{}: keys must be strings, got {}", builtin, other)),
+            })
+            .collect(),
+        _ => Ok(v
+            .to_string()
+            .split(',')
+            .map(|k| k.trim().to_string())
+            .filter(|k| !k.is_empty())
+            .collect()),
+    }
+}
+
+/// Row count for df_head / df_tail: negatives mean 0, fractions round down.
+fn df_row_count(builtin: &str, v: &ValueRuntime) -> EvalResult<usize> {
+    let n = as_number(v).map_err(|_| format!("{}: n must be a number, got '{}'", builtin, v))?;
+    if n.is_nan() {
+        return Err(format!("{}: n must be a number, got '{}'", builtin, v));
+    }
+    // Float-to-int casts saturate, so huge n simply means "all rows".
+    Ok(n.max(0.0) as usize)
+}
+
+/// Nearest whole number, with halves rounded up: 2.5 -> 3, -2.5 -> -2.
+fn round_half_up(x: f64) -> f64 {
+    let down = x.floor();
+    if x - down >= 0.5 {
+        down + 1.0
+    } else {
+        down
+    }
+}
+
 fn as_number(v: &ValueRuntime) -> EvalResult<f64> {
     match v {
+        ValueRuntime::Int(i) => Ok(*i as f64),
         ValueRuntime::Number(n) => Ok(*n),
         ValueRuntime::Str(s) => s
             .parse::<f64>()
             .map_err(|_| format!("Value '{}' is not a number", s)),
         ValueRuntime::Bool(b) => Err(format!("Value '{}' is not a number", b)),
+        ValueRuntime::Reply(r) => Err(format!("Value '{}' is not a number", r)),
     }
 }
 
 fn as_bool(v: &ValueRuntime) -> EvalResult<bool> {
     match v {
         ValueRuntime::Bool(b) => Ok(*b),
+        ValueRuntime::Int(i) => Ok(*i != 0),
         ValueRuntime::Number(n) => Ok(*n != 0.0),
         ValueRuntime::Str(s) => Ok(!s.is_empty()),
+        ValueRuntime::Reply(_) => Ok(true),
+    }
+}
+
+/// Equality used by `==` / `!=`.
+///
+/// Lists and maps (JSON array/object strings) compare structurally, so key
+/// order and whitespace don't matter; everything else compares as before
+/// (numbers and bools by value, the rest by string form).
+fn values_equal(left: &ValueRuntime, right: &ValueRuntime) -> bool {
+    match (left, right) {
+        (ValueRuntime::Int(a), ValueRuntime::Int(b)) => a == b,
+        (ValueRuntime::Int(a), ValueRuntime::Number(b))
+        | (ValueRuntime::Number(b), ValueRuntime::Int(a)) => *a as f64 == *b,
+        (ValueRuntime::Number(a), ValueRuntime::Number(b)) => a == b,
+        (ValueRuntime::Bool(a), ValueRuntime::Bool(b)) => a == b,
+        (ValueRuntime::Str(a), ValueRuntime::Str(b)) => {
+            match (parse_json_container(a), parse_json_container(b)) {
+                (Some(x), Some(y)) => json_deep_equal(&x, &y),
+                _ => a == b,
+            }
+        }
+        _ => left.to_string() == right.to_string(),
+    }
+}
+
+/// Parse `text` only if it is a JSON array or object.
+fn parse_json_container(text: &str) -> Option<Value> {
+    let trimmed = text.trim_start();
+    if !trimmed.starts_with('[') && !trimmed.starts_with('{') {
+        return None;
+    }
+    serde_json::from_str::<Value>(text)
+        .ok()
+        .filter(|v| v.is_array() || v.is_object())
+}
+
+/// Structural JSON equality where numbers compare by value (1 == 1.0).
+fn json_deep_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x.as_f64() == y.as_f64(),
+        (Value::Array(xs), Value::Array(ys)) => {
+            xs.len() == ys.len() && xs.iter().zip(ys).all(|(x, y)| json_deep_equal(x, y))
+        }
+        (Value::Object(xm), Value::Object(ym)) => {
+            xm.len() == ym.len()
+                && xm
+                    .iter()
+                    .all(|(k, x)| ym.get(k).is_some_and(|y| json_deep_equal(x, y)))
+        }
+        _ => a == b,
+    }
+}
+
+fn eval_neg(v: &ValueRuntime) -> EvalResult<ValueRuntime> {
+    let not_a_number = || format!("unary '-' requires a number, got '{}'", v);
+    match v {
+        // Integers stay exact; only -i64::MIN has to widen to a float.
+        ValueRuntime::Int(i) => Ok(i
+            .checked_neg()
+            .map(ValueRuntime::Int)
+            .unwrap_or(ValueRuntime::Number(-(*i as f64)))),
+        ValueRuntime::Number(n) => Ok(ValueRuntime::Number(-n)),
+        // Request vars are strings, so `-id` works like `0 - id`.
+        ValueRuntime::Str(s) => match s.trim().parse::<i64>() {
+            Ok(i) => eval_neg(&ValueRuntime::Int(i)),
+            Err(_) => s
+                .trim()
+                .parse::<f64>()
+                .map(|n| ValueRuntime::Number(-n))
+                .map_err(|_| not_a_number()),
+        },
+        ValueRuntime::Bool(_) | ValueRuntime::Reply(_) => Err(not_a_number()),
+    }
+}
+
+/// `list[i]` (zero-based) or `map[key]`. Nested lists and maps come back as
+/// JSON strings, like every other list/map value.
+fn eval_index(target: &ValueRuntime, index: &ValueRuntime) -> EvalResult<ValueRuntime> {
+    match value_to_json(target) {
+        Value::Array(items) => {
+            let n = as_number(index)?;
+            if n < 0.0 || n.fract() != 0.0 {
+                return Err(format!(
+                    "list index must be a whole number >= 0, got '{}'",
+                    index
+                ));
+            }
+            items
+                .get(n as usize)
+                .map(json_to_runtime_value)
+                .ok_or_else(|| {
+                    format!(
+                        "index {} out of range for a list of {} items",
+                        index,
+                        items.len()
+                    )
+                })
+        }
+        Value::Object(obj) => {
+            let key = index.to_string();
+            obj.get(&key)
+                .map(json_to_runtime_value)
+                .ok_or_else(|| format!("key '{}' not found in map", key))
+        }
+        _ => Err(format!("cannot index '{}': not a list or map", target)),
     }
 }
 
 fn eval_binary(left: &ValueRuntime, op: &BinOp, right: &ValueRuntime) -> EvalResult<ValueRuntime> {
+    // Integer arithmetic stays exact unless it overflows.
+    if let (ValueRuntime::Int(a), ValueRuntime::Int(b)) = (left, right) {
+        let exact = match op {
+            BinOp::Add => a.checked_add(*b),
+            BinOp::Sub => a.checked_sub(*b),
+            BinOp::Mul => a.checked_mul(*b),
+            BinOp::Mod if *b != 0 => a.checked_rem(*b),
+            _ => None,
+        };
+        if let Some(i) = exact {
+            return Ok(ValueRuntime::Int(i));
+        }
+        match op {
+            BinOp::Lt => return Ok(ValueRuntime::Bool(a < b)),
+            BinOp::Le => return Ok(ValueRuntime::Bool(a <= b)),
+            BinOp::Gt => return Ok(ValueRuntime::Bool(a > b)),
+            BinOp::Ge => return Ok(ValueRuntime::Bool(a >= b)),
+            _ => {}
+        }
+    }
+
     match op {
         BinOp::Add => match (left, right) {
-            (ValueRuntime::Number(a), ValueRuntime::Number(b)) => Ok(ValueRuntime::Number(a + b)),
+            (
+                ValueRuntime::Int(_) | ValueRuntime::Number(_),
+                ValueRuntime::Int(_) | ValueRuntime::Number(_),
+            ) => Ok(ValueRuntime::Number(as_number(left)? + as_number(right)?)),
             _ => Ok(ValueRuntime::Str(format!("{}{}", left, right))),
         },
 
-        BinOp::Sub | BinOp::Mul | BinOp::Div => {
+        BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Mod => {
             let a = as_number(left)?;
             let b = as_number(right)?;
 
@@ -1133,29 +3075,21 @@ fn eval_binary(left: &ValueRuntime, op: &BinOp, right: &ValueRuntime) -> EvalRes
                     }
                     a / b
                 }
+                BinOp::Mod => {
+                    if b == 0.0 {
+                        return Err("Modulo by zero".to_string());
+                    }
+                    a % b
+                }
                 _ => unreachable!(),
             };
 
             Ok(ValueRuntime::Number(res))
         }
 
-        BinOp::Eq => {
-            let result = match (left, right) {
-                (ValueRuntime::Number(a), ValueRuntime::Number(b)) => a == b,
-                (ValueRuntime::Bool(a), ValueRuntime::Bool(b)) => a == b,
-                _ => left.to_string() == right.to_string(),
-            };
-            Ok(ValueRuntime::Bool(result))
-        }
+        BinOp::Eq => Ok(ValueRuntime::Bool(values_equal(left, right))),
 
-        BinOp::Ne => {
-            let result = match (left, right) {
-                (ValueRuntime::Number(a), ValueRuntime::Number(b)) => a != b,
-                (ValueRuntime::Bool(a), ValueRuntime::Bool(b)) => a != b,
-                _ => left.to_string() != right.to_string(),
-            };
-            Ok(ValueRuntime::Bool(result))
-        }
+        BinOp::Ne => Ok(ValueRuntime::Bool(!values_equal(left, right))),
 
         BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => {
             let a = as_number(left)?;
@@ -1184,6 +3118,138 @@ fn eval_binary(left: &ValueRuntime, op: &BinOp, right: &ValueRuntime) -> EvalRes
     }
 }
 
+/// Parse a JSON array (of any element type) passed as a Shrimpl string.
+/// Compile a JSON Schema the way request-body validation does (Draft 7).
+pub fn compile_json_schema(schema: &Value) -> Result<JSONSchema, String> {
+    JSONSchema::options()
+        .with_draft(Draft::Draft7)
+        .compile(schema)
+        .map_err(|e| format!("invalid schema: {}", e))
+}
+
+/// Split a raw query string into key -> value. Shared by endpoint query
+/// parameters and `query_parse`: values are kept as sent (no percent
+/// decoding), a later duplicate key wins and empty keys are dropped.
+pub fn parse_query_string(qs: &str) -> HashMap<String, String> {
+    let mut map = HashMap::new();
+    for part in qs.split('&') {
+        if part.is_empty() {
+            continue;
+        }
+        let mut split = part.splitn(2, '=');
+        let key = split.next().unwrap_or("").to_string();
+        let value = split.next().unwrap_or("").to_string();
+        if !key.is_empty() {
+            map.insert(key, value);
+        }
+    }
+    map
+}
+
+/// "<error> at <path>", with "/" for the document root.
+pub fn schema_error_message(err: &jsonschema::ValidationError) -> String {
+    let path = err.instance_path.to_string();
+    let path = if path.is_empty() { "/" } else { path.as_str() };
+    format!("{} at {}", err, path)
+}
+
+/// Default content type for `download(...)` based on the file extension.
+fn content_type_for_filename(filename: &str) -> &'static str {
+    let ext = Path::new(filename)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    match ext.as_str() {
+        "csv" => "text/csv; charset=utf-8",
+        "json" => "application/json; charset=utf-8",
+        "txt" | "log" => "text/plain; charset=utf-8",
+        "md" => "text/markdown; charset=utf-8",
+        "html" | "htm" => "text/html; charset=utf-8",
+        "xml" => "application/xml; charset=utf-8",
+        "tsv" => "text/tab-separated-values; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Fill `{{ key }}` (HTML-escaped) and `{{{ key }}}` (raw) placeholders for
+/// `template(...)`. Strings are inserted as-is, other JSON values as JSON
+/// text and null as "". An unclosed `{{` is left in the output.
+fn render_template(
+    source: &str,
+    vars: &serde_json::Map<String, Value>,
+    strict: bool,
+) -> EvalResult<String> {
+    let mut out = String::with_capacity(source.len());
+    let mut rest = source;
+
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let tag = &rest[start..];
+        let (open, close, raw) = if tag.starts_with("{{{") {
+            ("{{{", "}}}", true)
+        } else {
+            ("{{", "}}", false)
+        };
+        let Some(end) = tag[open.len()..].find(close) else {
+            rest = tag;
+            break;
+        };
+
+        let key = tag[open.len()..open.len() + end].trim();
+        let text = match vars.get(key) {
+            Some(Value::String(s)) => s.clone(),
+            Some(Value::Null) => String::new(),
+            Some(v) => v.to_string(),
+            None if strict => {
+                return Err(format!("template: no value for placeholder '{}'", key));
+            }
+            None => String::new(),
+        };
+        if raw {
+            out.push_str(&text);
+        } else {
+            out.push_str(&html_escape(&text));
+        }
+        rest = &tag[open.len() + end + close.len()..];
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn html_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn parse_json_array(label: &str, text: &str) -> EvalResult<Vec<Value>> {
+    match serde_json::from_str::<Value>(text) {
+        Ok(Value::Array(items)) => Ok(items),
+        Ok(_) => Err(format!("{}: expected a JSON array", label)),
+        Err(e) => Err(format!("{}: invalid JSON array: {}", label, e)),
+    }
+}
+
+/// Text used for lexicographic sorting: strings sort by their raw content,
+/// everything else by its JSON representation.
+fn sort_key_text(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
 fn parse_json_array_numbers(label: &str, text: &str) -> EvalResult<Vec<f64>> {
     let val: Value = serde_json::from_str(text).unwrap_or_else(|_| json!(text));
 
@@ -1210,6 +3276,225 @@ fn parse_json_array_numbers(label: &str, text: &str) -> EvalResult<Vec<f64>> {
     Ok(out)
 }
 
+/// Scalar type a CSV cell reads as.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CsvCell {
+    Int,
+    Float,
+    Bool,
+    Text,
+}
+
+/// Classify one CSV cell. Codes with leading zeros or signs ("007", "+44")
+/// and non-finite spellings ("NaN", "inf") are text so they survive
+/// unchanged.
+fn csv_cell_kind(field: &str) -> CsvCell {
+    if field == "true" || field == "false" {
+        return CsvCell::Bool;
+    }
+    if field.starts_with('+') {
+        return CsvCell::Text;
+    }
+    let digits = field.strip_prefix('-').unwrap_or(field);
+    let int_part = digits.split(['.', 'e', 'E']).next().unwrap_or("");
+    if int_part.len() > 1 && int_part.starts_with('0') {
+        return CsvCell::Text;
+    }
+    if field.parse::<i64>().is_ok() {
+        CsvCell::Int
+    } else if field.parse::<f64>().is_ok_and(f64::is_finite) {
+        CsvCell::Float
+    } else {
+        CsvCell::Text
+    }
+}
+
+fn csv_cell_value(field: &str) -> Value {
+    match csv_cell_kind(field) {
+        CsvCell::Int => field
+            .parse::<i64>()
+            .map(Value::from)
+            .unwrap_or(json!(field)),
+        CsvCell::Float => field
+            .parse::<f64>()
+            .map(Value::from)
+            .unwrap_or(json!(field)),
+        CsvCell::Bool => Value::Bool(field == "true"),
+        CsvCell::Text => json!(field),
+    }
+}
+
+/// Parse CSV (with a header row) from any reader into a dataframe JSON table.
+///
+/// `label` prefixes error messages, e.g. `df_from_csv(https://...)`. Cell
+/// types follow config `csv.inference`: "loose" (default) converts each
+/// cell on its own, "strict" converts a column only when all of it agrees.
+fn df_from_csv_reader<R: Read>(label: &str, reader: R) -> EvalResult<ValueRuntime> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .from_reader(reader);
+
+    let headers_record = rdr
+        .headers()
+        .map_err(|e| format!("{}: failed to read headers: {}", label, e))?;
+    let headers: Vec<String> = headers_record.iter().map(|s| s.to_string()).collect();
+
+    let strict = match config::csv_inference().as_deref() {
+        None | Some("loose") => false,
+        Some("strict") => true,
+        Some(other) => {
+            return Err(format!(
+                "{}: config csv.inference must be \"loose\" or \"strict\", got \"{}\"",
+                label, other
+            ))
+        }
+    };
+
+    let mut records: Vec<csv::StringRecord> = Vec::new();
+    for rec in rdr.records() {
+        records.push(rec.map_err(|e| format!("{}: failed to read record: {}", label, e))?);
+    }
+
+    // Strict mode converts a column only when every non-empty cell in it
+    // reads as a number, or every one as a boolean; otherwise the whole
+    // column stays text.
+    let convert_column: Vec<bool> = (0..headers.len())
+        .map(|col| {
+            if !strict {
+                return true;
+            }
+            let mut kinds = records
+                .iter()
+                .filter_map(|r| r.get(col))
+                .filter(|f| !f.is_empty())
+                .map(csv_cell_kind)
+                .peekable();
+            match kinds.peek().copied() {
+                Some(CsvCell::Bool) => kinds.all(|k| k == CsvCell::Bool),
+                Some(CsvCell::Int | CsvCell::Float) => {
+                    kinds.all(|k| matches!(k, CsvCell::Int | CsvCell::Float))
+                }
+                Some(CsvCell::Text) | None => false,
+            }
+        })
+        .collect();
+
+    let mut rows_json: Vec<Value> = Vec::new();
+    for record in &records {
+        let mut row_vals: Vec<Value> = Vec::new();
+        for (col, field) in record.iter().enumerate() {
+            row_vals.push(if convert_column.get(col).copied().unwrap_or(true) {
+                csv_cell_value(field)
+            } else {
+                json!(field)
+            });
+        }
+        rows_json.push(Value::Array(row_vals));
+    }
+
+    let table = json!({
+        "columns": headers,
+        "rows": rows_json
+    });
+
+    let txt = serde_json::to_string(&table).unwrap_or_else(|_| "{}".to_string());
+    Ok(ValueRuntime::Str(txt))
+}
+
+/// Load the rows of a data file as JSON objects (used by `shrimpl seed`).
+///
+/// `.csv` files are read like `df_from_csv_file` (header row = keys, empty
+/// fields become null). Any other file must be JSON: an array of objects, or a dataframe table
+/// `{ "columns": [...], "rows": [[...], ...] }`.
+pub fn records_from_data_file(path: &str) -> Result<Vec<Value>, String> {
+    let is_csv = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("csv"));
+
+    let df = if is_csv {
+        let file = fs::File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+        let table = df_from_csv_reader(path, file)?;
+        parse_df(&table.to_string())?
+    } else {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let val: Value =
+            serde_json::from_str(&text).map_err(|e| format!("{}: invalid JSON: {}", path, e))?;
+        if let Value::Array(items) = val {
+            return Ok(items);
+        }
+        parse_df(&text).map_err(|e| {
+            format!(
+                "{}: expected an array of objects or a dataframe table ({})",
+                path, e
+            )
+        })?
+    };
+
+    let records = df
+        .rows
+        .iter()
+        .map(|row| {
+            let cells = row.as_array().cloned().unwrap_or_default();
+            // An empty CSV field means "no value", not an empty string.
+            let cells = cells.into_iter().map(|c| match c {
+                Value::String(t) if is_csv && t.is_empty() => Value::Null,
+                other => other,
+            });
+            let obj: serde_json::Map<String, Value> =
+                df.columns.iter().cloned().zip(cells).collect();
+            Value::Object(obj)
+        })
+        .collect();
+    Ok(records)
+}
+
+/// Resolve a user-supplied data file path.
+///
+/// Without `data_dir` in config the path is used as-is. When `data_dir` is
+/// set, the path is resolved relative to it and must not escape it
+/// (e.g. via `..` or an absolute path elsewhere).
+fn resolve_data_path(path: &str) -> EvalResult<PathBuf> {
+    let data_dir = match config::data_dir() {
+        Some(d) => d,
+        None => return Ok(PathBuf::from(path)),
+    };
+
+    let root = Path::new(&data_dir)
+        .canonicalize()
+        .map_err(|e| format!("data_dir '{}' is not accessible: {}", data_dir, e))?;
+    let candidate = root
+        .join(path)
+        .canonicalize()
+        .map_err(|e| format!("failed to open file: {}", e))?;
+
+    if !candidate.starts_with(&root) {
+        return Err(format!(
+            "path '{}' is outside data_dir '{}'",
+            path, data_dir
+        ));
+    }
+
+    Ok(candidate)
+}
+
+/// Render a single dataframe cell for CSV output.
+///
+/// Numbers use the same integer-friendly formatting as runtime values,
+/// nulls become empty fields, and nested arrays/objects are written as JSON.
+pub fn csv_field_from_json(v: &Value) -> String {
+    match v {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Number(n) => match (n.as_i64(), n.as_f64()) {
+            (Some(i), _) => i.to_string(),
+            (None, Some(f)) => ValueRuntime::Number(f).to_string(),
+            (None, None) => n.to_string(),
+        },
+        other => other.to_string(),
+    }
+}
+
 struct DataFrame {
     columns: Vec<String>,
     rows: Vec<Value>,
@@ -1241,3 +3526,33 @@ fn parse_df(text: &str) -> EvalResult<DataFrame> {
 
     Ok(DataFrame { columns, rows })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::expr::parse_expr;
+    use crate::parser::parse_program;
+
+    #[test]
+    fn with_timeout_fails_fast_when_its_threads_are_used_up() {
+        let program = parse_program("server 3000\n\nfunc one(): 1\n").unwrap();
+        let call = parse_expr(r#"with_timeout("one", 1000)"#).unwrap();
+        assert_eq!(
+            eval_body_expr(&call, &program, &HashMap::new()).unwrap(),
+            "1"
+        );
+
+        let slots: Vec<TimeoutSlot> = std::iter::from_fn(try_timeout_slot).collect();
+        assert_eq!(slots.len(), MAX_TIMEOUT_THREADS);
+        assert_eq!(
+            eval_body_expr(&call, &program, &HashMap::new()).unwrap_err(),
+            "with_timeout: 16 earlier calls are still running; try again later"
+        );
+
+        drop(slots);
+        assert_eq!(
+            eval_body_expr(&call, &program, &HashMap::new()).unwrap(),
+            "1"
+        );
+    }
+}
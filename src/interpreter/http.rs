@@ -1,36 +1,73 @@
 // src/interpreter/http.rs
 //
 // Actix-Web HTTP server for Shrimpl.
-// - Serves Shrimpl endpoints.
+// - Serves Shrimpl endpoints (plus automatic HEAD/OPTIONS and 405 + Allow).
+// - Optional not-found fallback via `endpoint GET "/*"`.
 // - Exposes /__shrimpl/schema and /__shrimpl/ui (API Studio).
-// - Exposes /__shrimpl/diagnostics (static analysis).
+// - Exposes /__shrimpl/diagnostics (static analysis + config.types checks).
 // - Exposes /__shrimpl/source (raw app.shr).
+// - Exposes /__shrimpl/ast (parsed Program as JSON; dev environment only).
+// - Exposes /__shrimpl/metrics (Prometheus latency histograms).
 // - Exposes /health (built-in health check).
 // - Supports optional TLS via `server <port> tls` and env certs.
-// - Built-in JWT auth based on config.auth.*
+// - Adds config.server.default_headers to every response (e.g. security headers).
+// - Optional global per-IP rate limit via config.server.rate_limit (429 + Retry-After).
+// - Built-in JWT or HTTP Basic auth based on config.auth.*
 // - Input validation + sanitization via config.validation.schemas (JSON Schema).
-// - Structured JSON logging per HTTP request.
+// - multipart/form-data uploads exposed to Shrimpl as a JSON `body`.
+// - application/x-www-form-urlencoded fields exposed as variables.
+// - `with_content_type(...)` bodies sent with their own Content-Type.
+// - `db_stream(...)` bodies streamed as chunked JSON / NDJSON / CSV.
+// - `@cache(ttl_secs)` GET endpoints reuse their response per path + query
+//   (and caller), with an ETag and 304 for a matching If-None-Match.
+// - Write endpoints run in one ORM transaction per request: committed on a
+//   < 400 response, rolled back otherwise (config.database.auto_transactions).
+// - Structured request logging via `tracing` (SHRIMPL_LOG_FORMAT=json for JSON lines).
+// - A `tracing` span per endpoint evaluation (method, route, status, request id).
 
+use crate::cache;
 use crate::config;
 use crate::docs;
+use crate::metrics;
+use crate::orm;
 use crate::parser::ast::{Body, EndpointDecl, Method, Program};
+use crate::typecheck;
+use actix_multipart::Multipart;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::PayloadError;
+use actix_web::http::header::{
+    Charset, ContentDisposition, DispositionParam, DispositionType, ExtendedValue,
+};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::http::{header, Method as HttpMethod, StatusCode};
+use actix_web::middleware::{from_fn, DefaultHeaders, Next};
 use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
-use chrono::Utc;
-use jsonschema::{Draft, JSONSchema};
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use futures::channel::mpsc;
+use futures::{SinkExt, StreamExt};
+use ipnet::IpNet;
+use jsonschema::JSONSchema;
 use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+use once_cell::sync::Lazy;
 use serde::Deserialize;
-use serde_json::Value;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::env;
 use std::fs;
-use std::time::Instant;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
 use rustls::ServerConfig as TlsServerConfig;
 use rustls_pemfile::{certs, pkcs8_private_keys};
 use std::io::BufReader;
 
-use super::eval;
+use super::eval::{self, BodyValue, Reply};
+use super::ratelimit::WindowLimiter;
 
 // --- JWT claims ---
 
@@ -43,23 +80,14 @@ struct JwtClaims {
     pub exp: Option<u64>,
 }
 
-// --- helpers: auth, validation, logging ---
-
-fn path_requires_auth(path: &str) -> bool {
-    let auth = match config::auth_section() {
-        Some(a) => a,
-        None => return false,
-    };
-
-    let allow_on = auth.allow_missing_on.unwrap_or_default();
-    if allow_on.iter().any(|p| path.starts_with(p)) {
-        return false;
-    }
-
-    let protected = auth.protected_paths.unwrap_or_default();
-    protected.iter().any(|p| path.starts_with(p))
+/// Who a request on a protected path authenticated as.
+enum AuthPrincipal {
+    Jwt(JwtClaims),
+    Basic { user: String },
 }
 
+// --- helpers: auth, validation, logging ---
+
 fn extract_bearer_token(req: &HttpRequest) -> Option<String> {
     let auth_header = req
         .headers()
@@ -74,8 +102,9 @@ fn extract_bearer_token(req: &HttpRequest) -> Option<String> {
 }
 
 fn verify_jwt(token: &str) -> Result<JwtClaims, String> {
-    let secret = config::jwt_secret_from_env()
-        .ok_or_else(|| "JWT secret not configured (auth.jwt_secret_env)".to_string())?;
+    let secret = config::jwt_secret_from_env().ok_or_else(|| {
+        "JWT secret not configured (auth.jwt_secret_ref / auth.jwt_secret_env)".to_string()
+    })?;
     let mut validation = Validation::new(Algorithm::HS256);
     validation.validate_exp = true;
 
@@ -88,15 +117,23 @@ fn verify_jwt(token: &str) -> Result<JwtClaims, String> {
     .map_err(|e| format!("invalid token: {}", e))
 }
 
-/// Return Ok(claims) if valid token or auth not required, Err(HttpResponse) on failure.
-/// If auth is not required for this path, Ok(None).
-fn verify_jwt_if_required(
+/// Return Ok(principal) if the request authenticated or auth is not
+/// required for this path (Ok(None)), Err(HttpResponse) on failure.
+/// `config.auth.mode` picks JWT bearer tokens (default) or HTTP Basic.
+///
+/// With `config.auth.verify_if_present`, unprotected paths also verify
+/// credentials the client sends: absent means anonymous, invalid is a 401.
+fn verify_auth_if_required(
     path: &str,
     req: &HttpRequest,
-) -> Result<Option<JwtClaims>, HttpResponse> {
-    if !path_requires_auth(path) {
+) -> Result<Option<AuthPrincipal>, HttpResponse> {
+    let optional = config::auth_verify_if_present() && presents_credentials(req);
+    if !config::path_requires_auth(path) && !optional {
         return Ok(None);
     }
+    if config::auth_uses_basic() {
+        return verify_basic(req).map(|user| Some(AuthPrincipal::Basic { user }));
+    }
 
     let token = match extract_bearer_token(req) {
         Some(t) => t,
@@ -109,26 +146,94 @@ fn verify_jwt_if_required(
     };
 
     match verify_jwt(&token) {
-        Ok(claims) => Ok(Some(claims)),
+        Ok(claims) => Ok(Some(AuthPrincipal::Jwt(claims))),
         Err(msg) => Err(HttpResponse::Unauthorized()
             .content_type("application/json; charset=utf-8")
             .body(format!(r#"{{"error":"unauthorized","detail":"{}"}}"#, msg))),
     }
 }
 
-/// Validate and sanitize JSON request body for a Shrimpl path.
+/// Whether the request carries an `Authorization` header for the configured
+/// auth mode (`Bearer` for JWT, `Basic` for basic auth).
+fn presents_credentials(req: &HttpRequest) -> bool {
+    let scheme = if config::auth_uses_basic() {
+        "Basic "
+    } else {
+        "Bearer "
+    };
+    req.headers()
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.starts_with(scheme))
+        .unwrap_or(false)
+}
+
+/// Decode `Authorization: Basic base64(user:password)`.
+fn extract_basic_credentials(req: &HttpRequest) -> Option<(String, String)> {
+    let auth_header = req
+        .headers()
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())?;
+    let encoded = auth_header.strip_prefix("Basic ")?.trim();
+    let decoded = BASE64_STANDARD.decode(encoded).ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (user, password) = decoded.split_once(':')?;
+    Some((user.to_string(), password.to_string()))
+}
+
+/// Check basic credentials against `config.auth.basic.users`; Ok(username)
+/// on success. Failures carry `WWW-Authenticate` so browsers prompt.
+fn verify_basic(req: &HttpRequest) -> Result<String, HttpResponse> {
+    let unauthorized = |body: &str| {
+        HttpResponse::Unauthorized()
+            .insert_header((header::WWW_AUTHENTICATE, r#"Basic realm="shrimpl""#))
+            .content_type("application/json; charset=utf-8")
+            .body(body.to_string())
+    };
+
+    let (user, password) = match extract_basic_credentials(req) {
+        Some(c) => c,
+        None => return Err(unauthorized(r#"{"error":"missing basic credentials"}"#)),
+    };
+
+    match config::basic_password_from_env(&user) {
+        Some(expected) if constant_time_eq(expected.as_bytes(), password.as_bytes()) => Ok(user),
+        _ => Err(unauthorized(
+            r#"{"error":"unauthorized","detail":"invalid username or password"}"#,
+        )),
+    }
+}
+
+/// Compare secrets without returning early on the first differing byte.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Validate and sanitize JSON request body against an endpoint's precompiled schema.
 /// - If no schema is configured, returns Ok(body_string) unchanged.
 /// - If schema exists, validates using jsonschema.
 /// - On success, returns sanitized JSON serialized back to a string.
 /// - On failure, returns Err(HttpResponse) with 400 status.
-async fn validate_and_sanitize_body(
-    path: &str,
+fn validate_and_sanitize_body(
+    schema: Option<&Result<JSONSchema, String>>,
     raw_body: web::Bytes,
 ) -> Result<String, HttpResponse> {
     let body_text = String::from_utf8_lossy(&raw_body).to_string();
 
-    let schema_val = match config::validation_schema_for_path(path) {
-        Some(s) => s,
+    let compiled = match schema {
+        Some(Ok(c)) => c,
+        Some(Err(e)) => {
+            // Misconfigured schema -> 500, not user's fault.
+            return Err(HttpResponse::InternalServerError()
+                .content_type("application/json; charset=utf-8")
+                .body(format!(
+                    r#"{{"error":"schema_compile_error","detail":"{}"}}"#,
+                    e
+                )));
+        }
         None => return Ok(body_text),
     };
 
@@ -141,26 +246,10 @@ async fn validate_and_sanitize_body(
         }
     };
 
-    let compiled = match JSONSchema::options()
-        .with_draft(Draft::Draft7)
-        .compile(&schema_val)
-    {
-        Ok(c) => c,
-        Err(e) => {
-            // Misconfigured schema -> 500, not user's fault.
-            return Err(HttpResponse::InternalServerError()
-                .content_type("application/json; charset=utf-8")
-                .body(format!(
-                    r#"{{"error":"schema_compile_error","detail":"{}"}}"#,
-                    e
-                )));
-        }
-    };
-
     if let Err(errors) = compiled.validate(&json_val) {
         let first = errors.into_iter().next();
         let msg = match first {
-            Some(e) => format!("{} at {}", e, e.instance_path),
+            Some(e) => eval::schema_error_message(&e),
             None => "validation failed".to_string(),
         };
         return Err(HttpResponse::BadRequest()
@@ -196,259 +285,282 @@ fn sanitize_json(val: &mut Value) {
     }
 }
 
+/// Request id for tracing: the client's `x-request-id` header if present,
+/// otherwise a process-local counter.
+fn request_id(req: &HttpRequest) -> String {
+    static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+    req.headers()
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| format!("{:016x}", NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed)))
+}
+
 fn log_request(
     path: &str,
     method: &str,
     status: u16,
     client: &str,
-    elapsed_ms: u128,
+    elapsed: Duration,
     auth_ok: bool,
 ) {
-    let payload = serde_json::json!({
-        "ts": Utc::now().to_rfc3339(),
-        "level": "info",
-        "kind": "http-request",
-        "method": method,
-        "path": path,
-        "status": status,
-        "client": client,
-        "elapsed_ms": elapsed_ms,
-        "auth_ok": auth_ok
-    });
-    println!("{}", payload);
+    metrics::observe_endpoint_latency(path, status, elapsed.as_secs_f64());
+
+    tracing::info!(
+        kind = "http-request",
+        method,
+        path,
+        status,
+        client,
+        elapsed_ms = elapsed.as_millis() as u64,
+        auth_ok,
+        "request"
+    );
 }
 
-pub async fn run(program: Program) -> std::io::Result<()> {
-    // Take server configuration from the original Program
-    let addr = ("0.0.0.0", program.server.port);
-    let tls_enabled = program.server.tls;
+// --- multipart/form-data ---
 
-    // Clone once for moving into the Actix factory closure
-    let program_for_server = program.clone();
+/// Request bodies (JSON or multipart) larger than this are rejected with 413.
+const MAX_BODY_BYTES: usize = 256 * 1024;
 
-    let factory = move || {
-        let mut app = App::new();
-        let program_cloned = program_for_server.clone();
-
-        // Built-in health check endpoint
-        app = app.route(
-            "/health",
-            web::get().to(|| async {
-                HttpResponse::Ok()
-                    .content_type("application/json; charset=utf-8")
-                    .body(r#"{"status":"ok"}"#)
-            }),
-        );
+/// Maximum number of parts (fields + files) accepted in one multipart body.
+const MAX_MULTIPART_PARTS: usize = 32;
 
-        // User-defined endpoints from Shrimpl program
-        let endpoints_snapshot = program_cloned.endpoints.clone();
-        for ep in endpoints_snapshot {
-            let actix_path = convert_path_for_actix(&ep.path);
-            let endpoint_clone = ep.clone();
-            let program_for_route = program_cloned.clone();
+fn is_multipart(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.to_ascii_lowercase().starts_with("multipart/form-data"))
+}
 
-            match ep.method {
-                Method::Get => {
-                    app = app.route(
-                        &actix_path,
-                        web::get().to(move |req: HttpRequest| {
-                            let ep_here = endpoint_clone.clone();
-                            let program_here = program_for_route.clone();
-                            async move {
-                                let started = Instant::now();
-                                let path = ep_here.path.clone();
-                                let method = "GET";
-                                let client = req
-                                    .connection_info()
-                                    .realip_remote_addr()
-                                    .unwrap_or("unknown")
-                                    .to_string();
-
-                                let jwt_result = verify_jwt_if_required(&path, &req);
-                                let claims_opt = match jwt_result {
-                                    Ok(c) => c,
-                                    Err(resp) => {
-                                        log_request(
-                                            &path,
-                                            method,
-                                            resp.status().as_u16(),
-                                            &client,
-                                            started.elapsed().as_millis(),
-                                            false,
-                                        );
-                                        return resp;
-                                    }
-                                };
-
-                                // Collect vars from path + query
-                                let mut vars = collect_all_vars(&req);
-
-                                // Inject claims when present
-                                if let Some(claims) = claims_opt.as_ref() {
-                                    if let Some(sub) = &claims.sub {
-                                        vars.insert("jwt_sub".to_string(), sub.clone());
-                                    }
-                                    if let Some(scope) = &claims.scope {
-                                        vars.insert("jwt_scope".to_string(), scope.clone());
-                                    }
-                                    if let Some(role) = &claims.role {
-                                        vars.insert("jwt_role".to_string(), role.clone());
-                                    }
-                                }
-
-                                let resp = respond(ep_here, program_here, vars);
-                                let status = resp.status().as_u16();
-                                log_request(
-                                    &path,
-                                    method,
-                                    status,
-                                    &client,
-                                    started.elapsed().as_millis(),
-                                    claims_opt.is_some(),
-                                );
-                                resp
-                            }
-                        }),
-                    );
-                }
-                Method::Post => {
-                    app = app.route(
-                        &actix_path,
-                        web::post().to(move |req: HttpRequest, body: web::Bytes| {
-                            let ep_here = endpoint_clone.clone();
-                            let program_here = program_for_route.clone();
-                            async move {
-                                let started = Instant::now();
-                                let path = ep_here.path.clone();
-                                let method = "POST";
-                                let client = req
-                                    .connection_info()
-                                    .realip_remote_addr()
-                                    .unwrap_or("unknown")
-                                    .to_string();
-
-                                let jwt_result = verify_jwt_if_required(&path, &req);
-                                let claims_opt = match jwt_result {
-                                    Ok(c) => c,
-                                    Err(resp) => {
-                                        log_request(
-                                            &path,
-                                            method,
-                                            resp.status().as_u16(),
-                                            &client,
-                                            started.elapsed().as_millis(),
-                                            false,
-                                        );
-                                        return resp;
-                                    }
-                                };
-
-                                // Validate + sanitize JSON body (if schema exists)
-                                let body_text_res = validate_and_sanitize_body(&path, body).await;
-                                let body_text = match body_text_res {
-                                    Ok(t) => t,
-                                    Err(resp) => {
-                                        log_request(
-                                            &path,
-                                            method,
-                                            resp.status().as_u16(),
-                                            &client,
-                                            started.elapsed().as_millis(),
-                                            claims_opt.is_some(),
-                                        );
-                                        return resp;
-                                    }
-                                };
-
-                                // Collect vars from path + query
-                                let mut vars = collect_all_vars(&req);
-
-                                // Insert request body under "body" for Shrimpl code
-                                vars.insert("body".to_string(), body_text);
-
-                                // Inject claims when present
-                                if let Some(claims) = claims_opt.as_ref() {
-                                    if let Some(sub) = &claims.sub {
-                                        vars.insert("jwt_sub".to_string(), sub.clone());
-                                    }
-                                    if let Some(scope) = &claims.scope {
-                                        vars.insert("jwt_scope".to_string(), scope.clone());
-                                    }
-                                    if let Some(role) = &claims.role {
-                                        vars.insert("jwt_role".to_string(), role.clone());
-                                    }
-                                }
-
-                                let resp = respond(ep_here, program_here, vars);
-                                let status = resp.status().as_u16();
-                                log_request(
-                                    &path,
-                                    method,
-                                    status,
-                                    &client,
-                                    started.elapsed().as_millis(),
-                                    claims_opt.is_some(),
-                                );
-                                resp
-                            }
-                        }),
-                    );
-                }
+fn is_form_urlencoded(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| {
+            ct.to_ascii_lowercase()
+                .starts_with("application/x-www-form-urlencoded")
+        })
+}
+
+/// `curl -d '{...}'` labels JSON as a form; such bodies keep the JSON path.
+fn looks_like_json(body: &[u8]) -> bool {
+    matches!(
+        body.iter().find(|b| !b.is_ascii_whitespace()),
+        Some(b'{' | b'[')
+    )
+}
+
+/// Decode an `application/x-www-form-urlencoded` body into its fields. A
+/// repeated key keeps its last value, like query parameters.
+///
+/// As with multipart, endpoints with a validation schema only accept JSON.
+fn form_urlencoded_fields(
+    ep: &CompiledEndpoint,
+    body: &[u8],
+) -> Result<HashMap<String, String>, HttpResponse> {
+    if ep.schema.is_some() {
+        return Err(HttpResponse::UnsupportedMediaType()
+            .content_type("application/json; charset=utf-8")
+            .body(
+                serde_json::json!({
+                    "error": "unsupported_media_type",
+                    "detail": "this endpoint has a validation schema and only accepts JSON bodies",
+                })
+                .to_string(),
+            ));
+    }
+
+    Ok(form_urlencoded::parse(body)
+        .filter(|(k, _)| !k.is_empty())
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect())
+}
+
+/// Parse an already-buffered multipart body into the JSON handed to Shrimpl
+/// as `body`:
+///
+///   { "fields": { "title": "..." },
+///     "files": [ { "field", "filename", "content_type", "size", "base64" } ] }
+///
+/// Endpoints with a validation schema only accept JSON, so multipart can't
+/// be used to skip validation.
+async fn multipart_body(
+    ep: &CompiledEndpoint,
+    req: &HttpRequest,
+    body: web::Bytes,
+) -> Result<String, HttpResponse> {
+    let error = |status: StatusCode, kind: &str, detail: String| {
+        HttpResponse::build(status)
+            .content_type("application/json; charset=utf-8")
+            .body(serde_json::json!({ "error": kind, "detail": detail }).to_string())
+    };
+
+    if ep.schema.is_some() {
+        return Err(error(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "unsupported_media_type",
+            "this endpoint has a validation schema and only accepts JSON bodies".to_string(),
+        ));
+    }
+
+    let stream = futures::stream::once(async move { Ok::<_, PayloadError>(body) });
+    let mut multipart = Multipart::new(req.headers(), stream);
+
+    let mut fields = serde_json::Map::new();
+    let mut files = Vec::new();
+    let mut parts = 0;
+
+    while let Some(part) = multipart.next().await {
+        let mut part =
+            part.map_err(|e| error(StatusCode::BAD_REQUEST, "invalid_multipart", e.to_string()))?;
+
+        parts += 1;
+        if parts > MAX_MULTIPART_PARTS {
+            return Err(error(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "too_many_parts",
+                format!(
+                    "multipart bodies may contain at most {} parts",
+                    MAX_MULTIPART_PARTS
+                ),
+            ));
+        }
+
+        let name = part.name().unwrap_or("").to_string();
+        let filename = part
+            .content_disposition()
+            .and_then(|cd| cd.get_filename())
+            .map(|f| f.to_string());
+        let content_type = part.content_type().map(|m| m.to_string());
+
+        let mut data = Vec::new();
+        while let Some(chunk) = part.next().await {
+            let chunk = chunk
+                .map_err(|e| error(StatusCode::BAD_REQUEST, "invalid_multipart", e.to_string()))?;
+            data.extend_from_slice(&chunk);
+        }
+
+        match filename {
+            Some(filename) => files.push(serde_json::json!({
+                "field": name,
+                "filename": filename,
+                "content_type": content_type.unwrap_or_else(|| "application/octet-stream".to_string()),
+                "size": data.len(),
+                "base64": BASE64_STANDARD.encode(&data),
+            })),
+            None => {
+                fields.insert(name, Value::String(String::from_utf8_lossy(&data).into_owned()));
             }
         }
+    }
+
+    Ok(serde_json::json!({ "fields": fields, "files": files }).to_string())
+}
 
-        // Docs + schema + diagnostics + source
-        let program_schema = program_cloned.clone();
-        let program_ui = program_cloned.clone();
-        let program_diag = program_cloned.clone();
+// --- compiled endpoints ---
 
-        app = app
-            .route(
-                "/__shrimpl/schema",
-                web::get().to(move || {
-                    let program_here = program_schema.clone();
-                    async move {
-                        let json: Value = docs::build_schema(&program_here);
-                        HttpResponse::Ok().json(json)
-                    }
-                }),
-            )
-            .route(
-                "/__shrimpl/diagnostics",
-                web::get().to(move || {
-                    let program_here = program_diag.clone();
-                    async move {
-                        let json: Value = docs::build_diagnostics(&program_here);
-                        HttpResponse::Ok().json(json)
-                    }
-                }),
-            )
-            .route(
-                "/__shrimpl/ui",
-                web::get().to(move || {
-                    let _program_here = program_ui.clone(); // reserved for future customization
-                    async move {
-                        HttpResponse::Ok()
-                            .content_type("text/html; charset=utf-8")
-                            .body(docs::docs_html())
-                    }
-                }),
-            )
-            .route(
-                "/__shrimpl/source",
-                web::get().to(|| async {
-                    match fs::read_to_string("app.shr") {
-                        Ok(text) => HttpResponse::Ok()
-                            .content_type("text/plain; charset=utf-8")
-                            .body(text),
-                        Err(_) => {
-                            HttpResponse::InternalServerError().body("Could not read app.shr")
-                        }
-                    }
-                }),
-            );
+/// Path that marks an endpoint as the not-found fallback.
+const FALLBACK_PATH: &str = "/*";
+
+/// An endpoint prepared once at startup. Every Actix worker shares the same
+/// `Arc<Vec<CompiledEndpoint>>`, and route handlers only carry an index into it.
+pub struct CompiledEndpoint {
+    pub decl: EndpointDecl,
+    /// Request body schema from config.validation.schemas, compiled once.
+    /// A schema that fails to compile is kept as the error and reported as 500.
+    schema: Option<Result<JSONSchema, String>>,
+    /// Per-client-IP budget from `@rate_limit(max, window_secs)`.
+    limiter: Option<WindowLimiter>,
+}
 
-        app
+impl CompiledEndpoint {
+    fn compile(decl: EndpointDecl) -> Self {
+        let schema = config::validation_schema_for_path(&decl.path)
+            .map(|schema_val| eval::compile_json_schema(&schema_val));
+        let limiter = decl
+            .rate_limit
+            .as_ref()
+            .map(|rl| WindowLimiter::new(rl.max_requests, rl.window_secs));
+        CompiledEndpoint {
+            decl,
+            schema,
+            limiter,
+        }
+    }
+
+    /// `endpoint GET "/*"` is not a route: it answers every request that no
+    /// other route matches, with a 404 status.
+    fn is_fallback(&self) -> bool {
+        self.decl.path == FALLBACK_PATH
+    }
+}
+
+/// Immutable server state built once in `run` and shared by all workers.
+#[derive(Clone)]
+pub struct ServerState {
+    pub program: Arc<Program>,
+    pub endpoints: Arc<Vec<CompiledEndpoint>>,
+}
+
+impl ServerState {
+    pub fn new(program: Program) -> Self {
+        let endpoints = program
+            .endpoints
+            .iter()
+            .cloned()
+            .map(CompiledEndpoint::compile)
+            .collect();
+        ServerState {
+            program: Arc::new(program),
+            endpoints: Arc::new(endpoints),
+        }
+    }
+}
+
+pub async fn run(mut program: Program) -> std::io::Result<()> {
+    // Take server configuration from the original Program
+    let addr = ("0.0.0.0", program.server.port);
+    let tls_enabled = program.server.tls;
+
+    // Let config references (auth.jwt_secret_ref) resolve `secret` declarations.
+    config::register_secret_decls(&program.secrets);
+
+    // Endpoints gated by `@env(...)` / `@only ...` are simply not registered
+    // outside their environments.
+    let env = config::env_name();
+    let total = program.endpoints.len();
+    program.endpoints.retain(|ep| ep.enabled_in(&env));
+    if program.endpoints.len() < total {
+        tracing::info!(
+            env = %env,
+            skipped = total - program.endpoints.len(),
+            "endpoints not registered in this environment"
+        );
+    }
+
+    // Compile endpoints once; the factory below runs per worker and only
+    // clones the Arcs.
+    let state = ServerState::new(program);
+    let headers = default_headers_from_config();
+    // One limiter for the whole server, so the budget is per client IP rather
+    // than per client IP per worker.
+    let limiter = config::server_rate_limit()
+        .map(|(max, window)| web::Data::new(WindowLimiter::new(max, window)));
+    let factory = move || {
+        let mut defaults = DefaultHeaders::new();
+        for (name, value) in &headers {
+            defaults = defaults.add((name.clone(), value.clone()));
+        }
+        let mut app = App::new();
+        if let Some(limiter) = &limiter {
+            app = app.app_data(limiter.clone());
+        }
+        // Registered before DefaultHeaders so 429s get the default headers too.
+        app.wrap(from_fn(global_rate_limit))
+            .wrap(defaults)
+            .configure(|cfg| configure(cfg, &state))
     };
 
     if tls_enabled {
@@ -462,6 +574,515 @@ pub async fn run(program: Program) -> std::io::Result<()> {
     }
 }
 
+/// `config.server.trusted_proxies`, parsed once. Invalid entries are skipped
+/// with a warning, so a typo trusts less rather than more.
+static TRUSTED_PROXIES: Lazy<Vec<IpNet>> = Lazy::new(|| {
+    config::server_trusted_proxies()
+        .iter()
+        .filter_map(|entry| {
+            let entry = entry.trim();
+            let net = entry
+                .parse::<IpNet>()
+                .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from));
+            if net.is_err() {
+                tracing::warn!(entry = %entry, "ignoring invalid server.trusted_proxies entry");
+            }
+            net.ok()
+        })
+        .collect()
+});
+
+/// The client address used for rate limits and request logs: the connecting
+/// peer, unless that peer is a trusted proxy.
+fn client_ip(req: &HttpRequest) -> String {
+    let forwarded_for = req
+        .headers()
+        .get("x-forwarded-for")
+        .and_then(|v| v.to_str().ok());
+    resolve_client_ip(
+        req.peer_addr().map(|addr| addr.ip()),
+        forwarded_for,
+        &TRUSTED_PROXIES,
+    )
+}
+
+/// Headers are client input, so `X-Forwarded-For` only counts when `peer` is
+/// a trusted proxy. Its entries are read right to left (nearest hop first),
+/// and the first one that isn't a trusted proxy is the client.
+fn resolve_client_ip(
+    peer: Option<IpAddr>,
+    forwarded_for: Option<&str>,
+    trusted: &[IpNet],
+) -> String {
+    let Some(peer) = peer else {
+        return "unknown".to_string();
+    };
+    let is_trusted = |ip: &IpAddr| trusted.iter().any(|net| net.contains(ip));
+    if !is_trusted(&peer) {
+        return peer.to_string();
+    }
+
+    let mut client = peer;
+    for hop in forwarded_for.unwrap_or("").rsplit(',') {
+        // An unparsable hop ends the chain; what the proxies vouched for so
+        // far is all that can be believed.
+        let Ok(ip) = hop.trim().parse::<IpAddr>() else {
+            break;
+        };
+        client = ip;
+        if !is_trusted(&ip) {
+            break;
+        }
+    }
+    client.to_string()
+}
+
+/// Middleware enforcing `config.server.rate_limit` per client IP across every
+/// route. Per-endpoint `@rate_limit` budgets apply on top of this one.
+async fn global_rate_limit(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let limiter = match req.app_data::<web::Data<WindowLimiter>>() {
+        Some(l) => l.clone(),
+        None => return next.call(req).await.map(|res| res.map_into_left_body()),
+    };
+
+    let client = client_ip(req.request());
+
+    match limiter.check(&client) {
+        Ok(()) => next.call(req).await.map(|res| res.map_into_left_body()),
+        Err(retry_after) => {
+            metrics::metrics_incr("shrimpl_rate_limited_total", 1.0);
+            tracing::warn!(
+                kind = "rate-limited",
+                client = %client,
+                path = %req.path(),
+                retry_after_secs = retry_after,
+                "global rate limit exceeded"
+            );
+            let resp = rate_limited_response(retry_after);
+            Ok(req.into_response(resp).map_into_right_body())
+        }
+    }
+}
+
+/// 429 with `Retry-After`, shared by the global and `@rate_limit` limiters.
+fn rate_limited_response(retry_after: u64) -> HttpResponse {
+    HttpResponse::TooManyRequests()
+        .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+        .content_type("application/json; charset=utf-8")
+        .body(
+            serde_json::json!({
+                "error": "rate_limited",
+                "retry_after_secs": retry_after
+            })
+            .to_string(),
+        )
+}
+
+/// `config.server.default_headers`, parsed once at startup. Invalid names or
+/// values are reported and skipped rather than failing the server.
+fn default_headers_from_config() -> Vec<(HeaderName, HeaderValue)> {
+    let map = config::server_section()
+        .and_then(|s| s.default_headers)
+        .unwrap_or_default();
+
+    let mut headers = Vec::new();
+    for (name, value) in map {
+        match (
+            HeaderName::try_from(name.as_str()),
+            HeaderValue::try_from(value.as_str()),
+        ) {
+            (Ok(n), Ok(v)) => headers.push((n, v)),
+            _ => tracing::warn!(
+                "[shrimpl-config] ignoring invalid default header '{}: {}'",
+                name,
+                value
+            ),
+        }
+    }
+    headers
+}
+
+/// Register all routes (built-ins, user endpoints, API Studio) for `state`.
+pub fn configure(cfg: &mut web::ServiceConfig, state: &ServerState) {
+    cfg.app_data(web::PayloadConfig::new(MAX_BODY_BYTES));
+
+    // Built-in health check endpoint
+    cfg.route(
+        "/health",
+        web::get().to(|| async {
+            HttpResponse::Ok()
+                .content_type("application/json; charset=utf-8")
+                .body(r#"{"status":"ok"}"#)
+        }),
+    );
+
+    // User-defined endpoints from Shrimpl program, one Actix resource per
+    // path so HEAD/OPTIONS can be answered for every declared path.
+    for (actix_path, indices) in group_endpoints_by_path(&state.endpoints) {
+        let mut resource = web::resource(actix_path.as_str());
+
+        for &idx in &indices {
+            let st = state.clone();
+            let method = state.endpoints[idx].decl.method;
+            match method {
+                Method::Get => {
+                    // HEAD runs the same handler; Actix drops the body for HEAD.
+                    let st_head = st.clone();
+                    resource = resource
+                        .route(web::get().to(move |req: HttpRequest| {
+                            handle_endpoint(st.clone(), idx, req, None)
+                        }))
+                        .route(web::head().to(move |req: HttpRequest| {
+                            handle_endpoint(st_head.clone(), idx, req, None)
+                        }));
+                }
+                Method::Post | Method::Put | Method::Patch => {
+                    let route = match method {
+                        Method::Put => web::put(),
+                        Method::Patch => web::patch(),
+                        _ => web::post(),
+                    };
+                    resource =
+                        resource.route(route.to(move |req: HttpRequest, body: web::Bytes| {
+                            handle_endpoint(st.clone(), idx, req, Some(body))
+                        }));
+                }
+                Method::Delete => {
+                    resource =
+                        resource.route(web::delete().to(move |req: HttpRequest| {
+                            handle_endpoint(st.clone(), idx, req, None)
+                        }));
+                }
+            }
+        }
+
+        let allow = allowed_methods(&state.endpoints, &indices);
+        let allow_405 = allow.clone();
+        resource = resource
+            .route(web::method(HttpMethod::OPTIONS).to(move || {
+                let allow = allow.clone();
+                async move {
+                    HttpResponse::NoContent()
+                        .insert_header((header::ALLOW, allow))
+                        .finish()
+                }
+            }))
+            // Known path, wrong method: 405 with the methods that do exist.
+            .default_service(web::to(move |req: HttpRequest| {
+                let allow = allow_405.clone();
+                async move {
+                    HttpResponse::MethodNotAllowed()
+                        .insert_header((header::ALLOW, allow.clone()))
+                        .content_type("application/json; charset=utf-8")
+                        .body(format!(
+                            r#"{{"error":"method_not_allowed","detail":"{} is not allowed; use one of: {}"}}"#,
+                            req.method(),
+                            allow
+                        ))
+                }
+            }));
+
+        cfg.service(resource);
+    }
+
+    // Docs + schema + diagnostics + source
+    let program_schema = state.program.clone();
+    let program_diag = state.program.clone();
+
+    cfg.route(
+        "/__shrimpl/schema",
+        web::get().to(move || {
+            let program_here = program_schema.clone();
+            async move {
+                let json: Value = docs::build_schema(&program_here);
+                HttpResponse::Ok().json(json)
+            }
+        }),
+    )
+    .route(
+        "/__shrimpl/diagnostics",
+        web::get().to(move || {
+            let program_here = program_diag.clone();
+            async move {
+                let mut json: Value = docs::build_diagnostics(&program_here);
+                typecheck::merge_type_diagnostics(&mut json, &program_here);
+                HttpResponse::Ok().json(json)
+            }
+        }),
+    )
+    .route(
+        "/__shrimpl/ui",
+        web::get().to(|| async {
+            HttpResponse::Ok()
+                .content_type("text/html; charset=utf-8")
+                .body(docs::docs_html())
+        }),
+    )
+    .route(
+        "/__shrimpl/metrics",
+        web::get().to(|| async {
+            HttpResponse::Ok()
+                .content_type("text/plain; version=0.0.4; charset=utf-8")
+                .body(metrics::render_prometheus())
+        }),
+    )
+    .route(
+        "/__shrimpl/source",
+        web::get().to(|| async {
+            match fs::read_to_string("app.shr") {
+                Ok(text) => HttpResponse::Ok()
+                    .content_type("text/plain; charset=utf-8")
+                    .body(text),
+                Err(_) => HttpResponse::InternalServerError().body("Could not read app.shr"),
+            }
+        }),
+    );
+
+    // The full AST includes every function body, so only serve it in dev.
+    if config::env_name() == "dev" {
+        let program_ast = state.program.clone();
+        cfg.route(
+            "/__shrimpl/ast",
+            web::get().to(move || {
+                let program_here = program_ast.clone();
+                async move { HttpResponse::Ok().json(&*program_here) }
+            }),
+        );
+    }
+
+    // Not-found fallback: `endpoint GET "/*"` handles any unmatched request.
+    if let Some(idx) = state.endpoints.iter().position(|ep| ep.is_fallback()) {
+        let st = state.clone();
+        cfg.default_service(web::to(move |req: HttpRequest| {
+            handle_endpoint(st.clone(), idx, req, None)
+        }));
+    }
+}
+
+// Group endpoint indices by Actix path, keeping first-seen order.
+fn group_endpoints_by_path(endpoints: &[CompiledEndpoint]) -> Vec<(String, Vec<usize>)> {
+    let mut groups: Vec<(String, Vec<usize>)> = Vec::new();
+    for (idx, ep) in endpoints.iter().enumerate() {
+        if ep.is_fallback() {
+            continue;
+        }
+        let actix_path = convert_path_for_actix(&ep.decl.path);
+        match groups.iter_mut().find(|(p, _)| *p == actix_path) {
+            Some((_, indices)) => indices.push(idx),
+            None => groups.push((actix_path, vec![idx])),
+        }
+    }
+    groups
+}
+
+// Value for the `Allow` header of a path, e.g. "GET, HEAD, POST, OPTIONS".
+fn allowed_methods(endpoints: &[CompiledEndpoint], indices: &[usize]) -> String {
+    let has = |m: Method| indices.iter().any(|&i| endpoints[i].decl.method == m);
+
+    let mut methods = Vec::new();
+    if has(Method::Get) {
+        methods.push("GET");
+        methods.push("HEAD");
+    }
+    for method in [Method::Post, Method::Put, Method::Patch, Method::Delete] {
+        if has(method) {
+            methods.push(method.as_str());
+        }
+    }
+    methods.push("OPTIONS");
+    methods.join(", ")
+}
+
+/// Shared request pipeline for user endpoints:
+/// rate limit -> auth -> body validation (when a body is present) -> vars ->
+/// eval -> log.
+async fn handle_endpoint(
+    state: ServerState,
+    idx: usize,
+    req: HttpRequest,
+    body: Option<web::Bytes>,
+) -> HttpResponse {
+    let ep = &state.endpoints[idx];
+    let started = Instant::now();
+    let path = ep.decl.path.as_str();
+    let method = req.method().as_str().to_string();
+    let method = method.as_str();
+    let client = client_ip(&req);
+
+    // @rate_limit(max, window_secs): each client IP gets its own window.
+    if let Some(Err(retry_after)) = ep.limiter.as_ref().map(|l| l.check(&client)) {
+        metrics::metrics_incr("shrimpl_rate_limited_total", 1.0);
+        let resp = rate_limited_response(retry_after);
+        log_request(
+            path,
+            method,
+            resp.status().as_u16(),
+            &client,
+            started.elapsed(),
+            false,
+        );
+        return resp;
+    }
+
+    let auth_opt = match verify_auth_if_required(path, &req) {
+        Ok(c) => c,
+        Err(resp) => {
+            log_request(
+                path,
+                method,
+                resp.status().as_u16(),
+                &client,
+                started.elapsed(),
+                false,
+            );
+            return resp;
+        }
+    };
+
+    // @cache endpoints answer from the response cache while it's fresh.
+    let cache_key = ep
+        .decl
+        .cache_ttl
+        .and_then(|_| response_cache_key(&req, auth_opt.as_ref()));
+    if let Some(resp) = cache_key
+        .as_deref()
+        .and_then(cache::cache_get)
+        .and_then(|entry| cached_response(&req, &entry))
+    {
+        log_request(
+            path,
+            method,
+            resp.status().as_u16(),
+            &client,
+            started.elapsed(),
+            auth_opt.is_some(),
+        );
+        return resp;
+    }
+
+    // Collect vars from path + query, plus request metadata
+    let mut vars = collect_all_vars(&req);
+    insert_request_vars(&mut vars, method, &req);
+
+    if let Some(body) = body {
+        // Multipart forms become a JSON object; url-encoded forms become
+        // variables instead of `body`; other bodies are validated + sanitized
+        // against the schema (if one exists).
+        let body_text = if is_multipart(&req) {
+            multipart_body(ep, &req, body).await.map(Some)
+        } else if is_form_urlencoded(&req) && !looks_like_json(&body) {
+            form_urlencoded_fields(ep, &body).map(|fields| {
+                // Path, query and request metadata vars win over form fields.
+                for (k, v) in fields {
+                    vars.entry(k).or_insert(v);
+                }
+                None
+            })
+        } else {
+            validate_and_sanitize_body(ep.schema.as_ref(), body).map(Some)
+        };
+        let body_text = match body_text {
+            Ok(t) => t,
+            Err(resp) => {
+                log_request(
+                    path,
+                    method,
+                    resp.status().as_u16(),
+                    &client,
+                    started.elapsed(),
+                    auth_opt.is_some(),
+                );
+                return resp;
+            }
+        };
+
+        // Insert request body under "body" for Shrimpl code
+        if let Some(body_text) = body_text {
+            vars.insert("body".to_string(), body_text);
+        }
+    }
+
+    // Identity vars always exist (empty when unauthenticated) and can't be
+    // supplied through path or query parameters.
+    for key in ["jwt_sub", "jwt_scope", "jwt_role", "auth_user"] {
+        vars.insert(key.to_string(), String::new());
+    }
+
+    // Inject the authenticated identity when present
+    match auth_opt.as_ref() {
+        Some(AuthPrincipal::Jwt(claims)) => {
+            if let Some(sub) = &claims.sub {
+                vars.insert("jwt_sub".to_string(), sub.clone());
+                vars.insert("auth_user".to_string(), sub.clone());
+            }
+            if let Some(scope) = &claims.scope {
+                vars.insert("jwt_scope".to_string(), scope.clone());
+            }
+            if let Some(role) = &claims.role {
+                vars.insert("jwt_role".to_string(), role.clone());
+            }
+        }
+        Some(AuthPrincipal::Basic { user }) => {
+            vars.insert("auth_user".to_string(), user.clone());
+        }
+        None => {}
+    }
+
+    // Per-request span around evaluation; exported when OTLP is enabled.
+    let span = tracing::info_span!(
+        "shrimpl.endpoint",
+        http.method = method,
+        http.route = path,
+        http.status_code = tracing::field::Empty,
+        request_id = tracing::field::Empty,
+    );
+    if !span.is_disabled() {
+        span.record("request_id", request_id(&req).as_str());
+    }
+
+    let authenticated = auth_opt.is_some();
+    let mut resp = span.in_scope(|| {
+        // Everything the body writes commits or rolls back together. No
+        // `.await` may run inside this scope: it is tied to the worker thread.
+        let transactional = matches!(
+            *req.method(),
+            HttpMethod::POST | HttpMethod::PUT | HttpMethod::PATCH | HttpMethod::DELETE
+        ) && config::auto_transactions_enabled();
+        if transactional {
+            orm::begin_request_tx();
+        }
+        let resp = eval::with_shared_program(&state.program, || {
+            respond(&ep.decl, &state.program, &vars, authenticated)
+        });
+        if !transactional {
+            return resp;
+        }
+        match orm::finish_request_tx(resp.status().as_u16() < 400) {
+            Ok(()) => resp,
+            Err(e) => HttpResponse::InternalServerError().body(e),
+        }
+    });
+    if ep.is_fallback() && resp.status().is_success() {
+        *resp.status_mut() = StatusCode::NOT_FOUND;
+    }
+    if let (Some(key), Some(ttl)) = (cache_key, ep.decl.cache_ttl) {
+        resp = cache_response(&req, key, ttl, resp);
+    }
+    span.record("http.status_code", resp.status().as_u16());
+    log_request(
+        path,
+        method,
+        resp.status().as_u16(),
+        &client,
+        started.elapsed(),
+        auth_opt.is_some(),
+    );
+    resp
+}
+
 fn load_tls_config() -> std::io::Result<TlsServerConfig> {
     let cert_path = env::var("SHRIMPL_TLS_CERT").unwrap_or_else(|_| "cert.pem".to_string());
     let key_path = env::var("SHRIMPL_TLS_KEY").unwrap_or_else(|_| "key.pem".to_string());
@@ -523,23 +1144,7 @@ fn collect_path_params(req: &HttpRequest) -> HashMap<String, String> {
 
 // Collect query params (?k=v&x=y)
 fn collect_query_params(req: &HttpRequest) -> HashMap<String, String> {
-    let mut map = HashMap::new();
-    let qs = req.query_string();
-    if qs.is_empty() {
-        return map;
-    }
-    for part in qs.split('&') {
-        if part.is_empty() {
-            continue;
-        }
-        let mut split = part.splitn(2, '=');
-        let key = split.next().unwrap_or("").to_string();
-        let value = split.next().unwrap_or("").to_string();
-        if !key.is_empty() {
-            map.insert(key, value);
-        }
-    }
-    map
+    eval::parse_query_string(req.query_string())
 }
 
 // Merge both (path overrides query on conflict)
@@ -551,22 +1156,383 @@ fn collect_all_vars(req: &HttpRequest) -> HashMap<String, String> {
     vars
 }
 
+// Request metadata for endpoint bodies; set after path/query params so a
+// query string can't spoof them.
+fn insert_request_vars(vars: &mut HashMap<String, String>, method: &str, req: &HttpRequest) {
+    vars.insert("request_method".to_string(), method.to_string());
+    vars.insert("request_path".to_string(), req.path().to_string());
+    vars.insert("request_query".to_string(), req.query_string().to_string());
+}
+
+// --- @cache response cache ---
+
+/// Response cache key: path + query, per caller so one user's response is
+/// never served to another. HEAD shares the GET entry. None (don't cache)
+/// for a JWT without `sub`, since nothing identifies whose response it is.
+fn response_cache_key(req: &HttpRequest, auth: Option<&AuthPrincipal>) -> Option<String> {
+    let caller = match auth {
+        Some(AuthPrincipal::Jwt(claims)) => format!("jwt:{}", claims.sub.as_deref()?),
+        Some(AuthPrincipal::Basic { user }) => format!("basic:{}", user),
+        None => String::new(),
+    };
+    Some(format!(
+        "http:{}|{}?{}",
+        caller,
+        req.path(),
+        req.query_string()
+    ))
+}
+
+/// Strong ETag for a response body.
+fn etag_for(body: &[u8]) -> String {
+    format!("\"{}\"", hex::encode(&Sha256::digest(body)[..16]))
+}
+
+/// Whether the request's If-None-Match already names `etag`.
+fn if_none_match(req: &HttpRequest, etag: &str) -> bool {
+    req.headers()
+        .get_all(header::IF_NONE_MATCH)
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+fn not_modified(etag: &str) -> HttpResponse {
+    HttpResponse::NotModified()
+        .insert_header((header::ETAG, etag))
+        .finish()
+}
+
+/// Rebuild a cached response, or a 304 when the client already has it.
+fn cached_response(req: &HttpRequest, entry: &Value) -> Option<HttpResponse> {
+    let etag = entry.get("etag")?.as_str()?;
+    if if_none_match(req, etag) {
+        return Some(not_modified(etag));
+    }
+    let body = BASE64_STANDARD.decode(entry.get("body")?.as_str()?).ok()?;
+
+    let mut resp = HttpResponse::Ok();
+    for pair in entry.get("headers")?.as_array()? {
+        if let (Some(name), Some(value)) = (
+            pair.get(0).and_then(Value::as_str),
+            pair.get(1).and_then(Value::as_str),
+        ) {
+            resp.append_header((name, value));
+        }
+    }
+    Some(resp.insert_header((header::ETAG, etag)).body(body))
+}
+
+/// Store a 200 response for `ttl` seconds and tag it with its ETag. Other
+/// statuses and streamed bodies pass through uncached.
+fn cache_response(req: &HttpRequest, key: String, ttl: u64, resp: HttpResponse) -> HttpResponse {
+    if resp.status() != StatusCode::OK {
+        return resp;
+    }
+    let (head, body) = resp.into_parts();
+    let bytes = match body.try_into_bytes() {
+        Ok(bytes) => bytes,
+        Err(body) => return head.set_body(body),
+    };
+
+    let etag = etag_for(&bytes);
+    let headers: Vec<Value> = head
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| Some(json!([name.as_str(), value.to_str().ok()?])))
+        .collect();
+    cache::cache_set(
+        key,
+        json!({ "etag": etag, "headers": headers, "body": BASE64_STANDARD.encode(&bytes) }),
+        Some(ttl),
+    );
+
+    if if_none_match(req, &etag) {
+        return not_modified(&etag);
+    }
+    let mut resp = head.set_body(bytes).map_into_boxed_body();
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        resp.headers_mut().insert(header::ETAG, value);
+    }
+    resp
+}
+
+/// The response for a body that evaluated to a response helper's `Reply`.
+fn reply_response(reply: Reply) -> HttpResponse {
+    match reply {
+        Reply::Download {
+            filename,
+            content,
+            content_type,
+        } => download_response(&filename, content, &content_type),
+        Reply::WithContentType {
+            content,
+            content_type,
+        } => {
+            let content_type = match content_type.as_str() {
+                "" => "text/plain; charset=utf-8",
+                ct => ct,
+            };
+            HttpResponse::Ok().content_type(content_type).body(content)
+        }
+        Reply::Stream { model, format } => stream_response(model, format),
+    }
+}
+
+/// The attachment response for `download(...)`.
+fn download_response(filename: &str, content: String, content_type: &str) -> HttpResponse {
+    let content_type = match content_type {
+        "" => "application/octet-stream",
+        ct => ct,
+    };
+
+    // Keep only the last path segment and drop characters that could break
+    // the header; non-ASCII names also get an RFC 5987 `filename*`.
+    let base = filename
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or("")
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"')
+        .collect::<String>();
+    let filename = if base.is_empty() {
+        "download".to_string()
+    } else {
+        base
+    };
+
+    let mut parameters = Vec::new();
+    if filename.is_ascii() {
+        parameters.push(DispositionParam::Filename(filename));
+    } else {
+        let ascii: String = filename
+            .chars()
+            .map(|c| if c.is_ascii() { c } else { '_' })
+            .collect();
+        parameters.push(DispositionParam::Filename(ascii));
+        parameters.push(DispositionParam::FilenameExt(ExtendedValue {
+            charset: Charset::Ext("UTF-8".to_string()),
+            language_tag: None,
+            value: filename.into_bytes(),
+        }));
+    }
+
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters,
+        })
+        .body(content)
+}
+
+/// Rows are buffered up to this many bytes before being sent as one chunk.
+const STREAM_CHUNK_BYTES: usize = 16 * 1024;
+
+/// Chunks queued between the row reader thread and the client.
+const STREAM_QUEUE_CHUNKS: usize = 8;
+
+/// The `db_stream(...)` response: chunked, filled row by row from the ORM
+/// by a background thread. The reader stops as soon as the client
+/// disconnects.
+fn stream_response(model: String, format: String) -> HttpResponse {
+    let columns = match orm::orm_field_names(&model) {
+        Ok(columns) => columns,
+        Err(e) => return HttpResponse::InternalServerError().body(e),
+    };
+
+    let content_type = match format.as_str() {
+        "ndjson" => "application/x-ndjson",
+        "csv" => "text/csv; charset=utf-8",
+        _ => "application/json",
+    };
+
+    // Each stream keeps a pooled connection until the client has read
+    // everything, so cap them instead of letting slow readers drain the pool.
+    let Some(slot) = orm::try_stream_slot() else {
+        tracing::warn!(model = %model, "db_stream refused: too many concurrent streams");
+        return HttpResponse::ServiceUnavailable()
+            .insert_header((header::RETRY_AFTER, "1"))
+            .body(format!(
+                "too many concurrent db_stream responses (limit {}); try again shortly",
+                orm::MAX_STREAMS
+            ));
+    };
+
+    let (mut tx, rx) = mpsc::channel::<Result<web::Bytes, std::io::Error>>(STREAM_QUEUE_CHUNKS);
+
+    std::thread::spawn(move || {
+        // Released when the reader finishes or the client goes away.
+        let _slot = slot;
+        let mut buf = String::new();
+        let mut rows = 0usize;
+        let mut client_gone = false;
+
+        match format.as_str() {
+            "json" => buf.push('['),
+            "csv" => buf.push_str(&csv_line(&columns)),
+            _ => {}
+        }
+
+        let result = orm::orm_for_each_row(&model, |row| {
+            match format.as_str() {
+                "csv" => {
+                    let fields: Vec<String> = columns
+                        .iter()
+                        .map(|c| eval::csv_field_from_json(row.get(c).unwrap_or(&Value::Null)))
+                        .collect();
+                    buf.push_str(&csv_line(&fields));
+                }
+                "ndjson" => {
+                    buf.push_str(&Value::Object(row).to_string());
+                    buf.push('\n');
+                }
+                _ => {
+                    if rows > 0 {
+                        buf.push(',');
+                    }
+                    buf.push_str(&Value::Object(row).to_string());
+                }
+            }
+            rows += 1;
+
+            if buf.len() >= STREAM_CHUNK_BYTES {
+                let chunk = web::Bytes::from(std::mem::take(&mut buf));
+                client_gone = futures::executor::block_on(tx.send(Ok(chunk))).is_err();
+            }
+            !client_gone
+        });
+
+        if client_gone {
+            return;
+        }
+        if let Err(e) = result {
+            // Headers are already out, so the best we can do is cut the
+            // body short; the client sees an incomplete response.
+            tracing::warn!(model = %model, error = %e, "db_stream aborted");
+            let err = std::io::Error::other(e);
+            let _ = futures::executor::block_on(tx.send(Err(err)));
+            return;
+        }
+
+        if format == "json" {
+            buf.push(']');
+        }
+        if !buf.is_empty() {
+            let _ = futures::executor::block_on(tx.send(Ok(web::Bytes::from(buf))));
+        }
+    });
+
+    HttpResponse::Ok().content_type(content_type).streaming(rx)
+}
+
+/// One CSV record (with trailing newline) for a streamed response.
+fn csv_line(fields: &[String]) -> String {
+    let mut wtr = csv::Writer::from_writer(Vec::new());
+    let _ = wtr.write_record(fields);
+    let bytes = wtr.into_inner().unwrap_or_default();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
 fn respond(
-    endpoint: EndpointDecl,
-    program: Program,
-    vars: HashMap<String, String>,
+    endpoint: &EndpointDecl,
+    program: &Program,
+    vars: &HashMap<String, String>,
+    authenticated: bool,
 ) -> HttpResponse {
-    match endpoint.body {
-        Body::JsonRaw(json_str) => match serde_json::from_str::<Value>(&json_str) {
+    let flags = [("authenticated", authenticated)];
+    match &endpoint.body {
+        Body::JsonRaw(json_str) => match serde_json::from_str::<Value>(json_str) {
             Ok(json) => HttpResponse::Ok().json(json),
             Err(err) => HttpResponse::InternalServerError().body(format!(
                 "Invalid JSON in Shrimpl endpoint '{}': {}",
                 endpoint.path, err
             )),
         },
-        Body::TextExpr(expr) => match eval::eval_body_expr(&expr, &program, &vars) {
-            Ok(text) => HttpResponse::Ok().body(text),
+        Body::TextExpr(expr) => match eval::eval_endpoint_body(expr, program, vars, &flags) {
+            Ok(BodyValue::Reply(reply)) => reply_response(reply),
+            Ok(BodyValue::Text(text)) => HttpResponse::Ok().body(text),
             Err(err) => HttpResponse::InternalServerError().body(err),
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn jwt(sub: Option<&str>) -> AuthPrincipal {
+        AuthPrincipal::Jwt(JwtClaims {
+            sub: sub.map(str::to_string),
+            scope: None,
+            role: None,
+            exp: None,
+        })
+    }
+
+    #[test]
+    fn response_cache_key_is_per_caller() {
+        let req = TestRequest::get().uri("/reports?team=a").to_http_request();
+
+        assert_eq!(
+            response_cache_key(&req, None).as_deref(),
+            Some("http:|/reports?team=a")
+        );
+        assert_eq!(
+            response_cache_key(&req, Some(&jwt(Some("ann")))).as_deref(),
+            Some("http:jwt:ann|/reports?team=a")
+        );
+        assert_eq!(
+            response_cache_key(
+                &req,
+                Some(&AuthPrincipal::Basic {
+                    user: "bo".to_string()
+                })
+            )
+            .as_deref(),
+            Some("http:basic:bo|/reports?team=a")
+        );
+        // Tokens without `sub` can't be told apart, so they aren't cached.
+        assert_eq!(response_cache_key(&req, Some(&jwt(None))), None);
+    }
+
+    #[test]
+    fn client_ip_trusts_forwarded_for_only_from_trusted_proxies() {
+        let trusted: Vec<IpNet> = vec!["10.0.0.0/8".parse().unwrap()];
+        let ip = |s: &str| Some(s.parse::<IpAddr>().unwrap());
+
+        let cases = [
+            // Direct clients can't pick their own address.
+            (ip("203.0.113.9"), Some("198.51.100.1"), "203.0.113.9"),
+            (ip("203.0.113.9"), None, "203.0.113.9"),
+            // Behind a trusted proxy, the nearest untrusted hop is the client,
+            // even if the client prepended a fake entry.
+            (ip("10.0.0.2"), Some("198.51.100.1"), "198.51.100.1"),
+            (
+                ip("10.0.0.2"),
+                Some("1.2.3.4, 198.51.100.1"),
+                "198.51.100.1",
+            ),
+            (
+                ip("10.0.0.2"),
+                Some("198.51.100.1, 10.0.0.7"),
+                "198.51.100.1",
+            ),
+            // Nothing usable forwarded: the proxy itself.
+            (ip("10.0.0.2"), None, "10.0.0.2"),
+            (ip("10.0.0.2"), Some("garbage"), "10.0.0.2"),
+            (None, Some("198.51.100.1"), "unknown"),
+        ];
+        for (peer, forwarded_for, expected) in cases {
+            assert_eq!(
+                resolve_client_ip(peer, forwarded_for, &trusted),
+                expected,
+                "{:?} {:?}",
+                peer,
+                forwarded_for
+            );
+        }
+    }
+}
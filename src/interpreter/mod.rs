@@ -6,5 +6,6 @@
 pub mod eval;
 pub mod http;
 pub mod openai;
+pub mod ratelimit;
 
 // pub use http::run;
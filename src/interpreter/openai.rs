@@ -23,9 +23,10 @@
 
 #![allow(dead_code)]
 
+use parking_lot::Mutex;
 use serde_json::{json, Value};
 use std::env;
-use std::sync::{Mutex, OnceLock};
+use std::sync::OnceLock;
 
 use reqwest::blocking::Client;
 use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
@@ -71,7 +72,7 @@ pub enum OpenAIError {
 }
 
 fn get_api_key() -> Result<String, OpenAIError> {
-    let cfg = config().lock().unwrap();
+    let cfg = config().lock();
     if let Some(k) = &cfg.api_key {
         Ok(k.clone())
     } else {
@@ -80,17 +81,17 @@ fn get_api_key() -> Result<String, OpenAIError> {
 }
 
 pub fn set_api_key(key: &str) {
-    let mut cfg = config().lock().unwrap();
+    let mut cfg = config().lock();
     cfg.api_key = Some(key.to_string());
 }
 
 pub fn set_system_prompt(prompt: &str) {
-    let mut cfg = config().lock().unwrap();
+    let mut cfg = config().lock();
     cfg.system_prompt = prompt.to_string();
 }
 
 fn build_messages(user_message: &str) -> Vec<Value> {
-    let cfg = config().lock().unwrap();
+    let cfg = config().lock();
     let mut msgs = Vec::new();
 
     if !cfg.system_prompt.is_empty() {
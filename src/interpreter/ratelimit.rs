@@ -0,0 +1,109 @@
+// src/interpreter/ratelimit.rs
+//
+// Fixed-window request counters keyed by an arbitrary string (client IP,
+// endpoint + client, ...).
+//
+// Used by the global `config.server.rate_limit` middleware and by each
+// `@rate_limit` endpoint, so both limits count requests the same way.
+
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Once this many keys are tracked, expired windows are dropped on the next
+/// check so idle clients don't accumulate forever.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Allows `max_requests` per key in each `window`. The window for a key
+/// starts at its first request and resets once it has elapsed.
+pub struct WindowLimiter {
+    max_requests: u32,
+    window: Duration,
+    windows: Mutex<HashMap<String, (Instant, u32)>>,
+}
+
+impl WindowLimiter {
+    pub fn new(max_requests: u32, window_secs: u32) -> Self {
+        Self::with_window(max_requests, Duration::from_secs(u64::from(window_secs)))
+    }
+
+    fn with_window(max_requests: u32, window: Duration) -> Self {
+        WindowLimiter {
+            max_requests,
+            window,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Count one request for `key`. Returns `Err(retry_after_secs)` when the
+    /// key has used up its budget for the current window.
+    pub fn check(&self, key: &str) -> Result<(), u64> {
+        let now = Instant::now();
+        let mut windows = self.windows.lock();
+
+        if windows.len() >= PRUNE_THRESHOLD {
+            let window = self.window;
+            windows.retain(|_, (started, _)| now.duration_since(*started) < window);
+        }
+
+        let slot = windows.entry(key.to_string()).or_insert((now, 0));
+        if now.duration_since(slot.0) >= self.window {
+            *slot = (now, 0);
+        }
+
+        if slot.1 >= self.max_requests {
+            let remaining = self.window.saturating_sub(now.duration_since(slot.0));
+            // Round up so clients never retry a moment too early.
+            let secs = remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+            return Err(secs.max(1));
+        }
+
+        slot.1 += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allows_up_to_the_limit_then_rejects() {
+        let limiter = WindowLimiter::new(3, 60);
+        for _ in 0..3 {
+            assert_eq!(limiter.check("1.2.3.4"), Ok(()));
+        }
+        let retry_after = limiter.check("1.2.3.4").unwrap_err();
+        assert!(
+            (1..=60).contains(&retry_after),
+            "retry_after = {retry_after}"
+        );
+        assert!(limiter.check("1.2.3.4").is_err(), "still limited");
+    }
+
+    #[test]
+    fn keys_have_separate_budgets() {
+        let limiter = WindowLimiter::new(1, 60);
+        assert_eq!(limiter.check("GET /a|1.2.3.4"), Ok(()));
+        assert!(limiter.check("GET /a|1.2.3.4").is_err());
+        assert_eq!(limiter.check("GET /a|5.6.7.8"), Ok(()));
+        assert_eq!(limiter.check("GET /b|1.2.3.4"), Ok(()));
+    }
+
+    #[test]
+    fn budget_resets_after_the_window() {
+        let limiter = WindowLimiter::with_window(2, Duration::from_millis(50));
+        assert_eq!(limiter.check("client"), Ok(()));
+        assert_eq!(limiter.check("client"), Ok(()));
+        assert_eq!(
+            limiter.check("client"),
+            Err(1),
+            "rounded up to a whole second"
+        );
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(limiter.check("client"), Ok(()));
+        assert_eq!(limiter.check("client"), Ok(()));
+        assert!(limiter.check("client").is_err());
+    }
+}
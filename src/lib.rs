@@ -8,8 +8,10 @@ pub mod ast;
 pub mod cache;
 pub mod concurrency;
 pub mod config;
+pub mod dataformats;
 pub mod docs;
 pub mod format;
+pub mod http_client;
 pub mod interpreter;
 pub mod lint;
 pub mod loader;
@@ -1,14 +1,24 @@
 use clap::{Parser, Subcommand};
-use std::process::{Command, Stdio};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::time::Duration;
 use std::{env, error::Error, fs};
 
 mod ast;
+mod cache;
+mod concurrency;
 mod config;
+mod dataformats;
 mod docs;
+mod http_client;
 mod interpreter;
 mod lockfile;
+mod metrics;
 mod orm;
 mod parser;
+mod tests;
+mod typecheck;
 
 use config as shrimpl_config;
 use interpreter::http::run as run_server;
@@ -43,6 +53,9 @@ fn print_welcome_screen() {
     println!("  shrimpl --file app.shr run");
     println!("      Run the Shrimpl HTTP server defined in app.shr");
     println!();
+    println!("  shrimpl --file app.shr run --watch");
+    println!("      Run the server and restart it whenever .shr files change.");
+    println!();
     println!("  shrimpl --file app.shr check");
     println!("      Parse and check the Shrimpl program (no server start).");
     println!();
@@ -52,10 +65,27 @@ fn print_welcome_screen() {
     println!("  shrimpl --file app.shr diagnostics");
     println!("      Print static diagnostics JSON for endpoints/functions.");
     println!();
+    println!("  shrimpl --file app.shr doc --out API.md");
+    println!("      Generate Markdown API docs (stdout without --out).");
+    println!();
     println!("  shrimpl --file app.shr lint");
     println!("      Run lints and print human-readable diagnostics.");
     println!("      Exits with status 1 if there are errors.");
     println!();
+    println!("  shrimpl --file app.shr typecheck");
+    println!("      Check function bodies and calls against config.types annotations.");
+    println!("      Exits with status 1 if there are type errors.");
+    println!();
+    println!("  shrimpl --file app.shr test");
+    println!("      Run the program's `test` blocks (--format json|junit --out FILE for CI).");
+    println!("      Exits with status 1 if any test fails.");
+    println!();
+    println!("  shrimpl --file app.shr seed User users.csv");
+    println!("      Insert the rows of a CSV or JSON file into a model's table.");
+    println!();
+    println!("  shrimpl --file app.shr bench \"GET /\"");
+    println!("      Start the server and load-test one endpoint (p50/p95, req/s).");
+    println!();
     println!("  shrimpl --file app.shr format");
     println!("      Format Shrimpl source in-place (whitespace cleanup).");
     println!();
@@ -77,9 +107,10 @@ fn print_welcome_screen() {
 #[command(name = "shrimpl")]
 #[command(about = "Shrimpl language CLI", long_about = None)]
 struct Cli {
-    /// Path to the main Shrimpl file (default: app.shr)
-    #[arg(global = true, short, long, default_value = "app.shr")]
-    file: String,
+    /// Path to the main Shrimpl file (default: `[project] entry` from
+    /// shrimpl.toml, else app.shr)
+    #[arg(global = true, short, long)]
+    file: Option<String>,
 
     #[command(subcommand)]
     command: Option<Commands>,
@@ -88,7 +119,12 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Run the Shrimpl server
-    Run,
+    Run {
+        /// Restart the server when .shr files change (keeps the last good
+        /// server running if the new source fails to parse)
+        #[arg(short, long)]
+        watch: bool,
+    },
 
     /// Check syntax only
     Check,
@@ -99,12 +135,58 @@ enum Commands {
     /// Print raw diagnostics JSON
     Diagnostics,
 
+    /// Print the parsed program (AST) as JSON
+    Ast,
+
+    /// Generate Markdown API docs (endpoints, models, functions)
+    Doc {
+        /// Write Markdown to this file instead of stdout
+        #[arg(short, long)]
+        out: Option<String>,
+    },
+
     /// Human-readable lints (errors/warnings) with CI-friendly exit code
     Lint,
 
+    /// Type-check functions against `config.types` annotations only
+    Typecheck,
+
+    /// Run the `test` blocks in the program; exits 1 if any test fails
+    Test {
+        /// Report format: text, json, or junit (JUnit XML)
+        #[arg(long, value_enum, default_value = "text")]
+        format: TestFormat,
+        /// Same as --format json
+        #[arg(long, conflicts_with = "format")]
+        json: bool,
+        /// Write the report to this file instead of stdout
+        #[arg(short, long)]
+        out: Option<String>,
+    },
+
     /// Format Shrimpl source in-place
     Format,
 
+    /// Insert the rows of a CSV or JSON file into a model's table
+    Seed {
+        /// Model to insert into (e.g. User)
+        model: String,
+        /// CSV file with a header row, or JSON array of objects
+        data: String,
+    },
+
+    /// Start the server and load-test one endpoint, e.g. `bench "GET /"`
+    Bench {
+        /// Method and path to request, e.g. "GET /users/1" (method defaults to GET)
+        target: String,
+        /// Number of concurrent connections
+        #[arg(short, long, default_value_t = 10)]
+        concurrency: usize,
+        /// How long to send requests, in seconds
+        #[arg(short, long, default_value_t = 10)]
+        duration: u64,
+    },
+
     /// Start the Shrimpl language server (LSP) binary
     ///
     /// By default this runs the `shrimpl-lsp` executable found in PATH.
@@ -131,19 +213,60 @@ fn main() -> Result<(), Box<dyn Error>> {
 
 /// Actual CLI implementation.
 fn run_cli() -> Result<(), Box<dyn Error>> {
-    // Initialize environment-specific configuration (config/config.<env>.json).
+    // Initialize configuration (shrimpl.toml, then config/config.<env>.json).
     shrimpl_config::init();
 
     let cli = Cli::parse();
-    let command = cli.command.unwrap_or(Commands::Run);
+    let command = cli.command.unwrap_or(Commands::Run { watch: false });
+    let file = cli
+        .file
+        .or_else(shrimpl_config::manifest_entry)
+        .unwrap_or_else(|| "app.shr".to_string());
+
+    // A log line per request would flood the terminal and skew a benchmark.
+    if matches!(command, Commands::Bench { .. }) && env::var_os("RUST_LOG").is_none() {
+        env::set_var("RUST_LOG", "warn");
+    }
+
+    // Logging / tracing (RUST_LOG, SHRIMPL_LOG_FORMAT, SHRIMPL_OTEL).
+    metrics::init_from_env();
 
     match command {
         Commands::Lsp { exe } => {
             start_lsp_subprocess(&exe)?;
         }
 
-        Commands::Run => {
-            let (source, mut program) = load_and_parse(&cli.file)?;
+        Commands::Seed { model, data } => {
+            let (_source, program) = load_and_parse(&file)?;
+            if !program.models.contains_key(&model) {
+                return Err(format!("seed: unknown model '{}' in {}", model, file).into());
+            }
+
+            let records = interpreter::eval::records_from_data_file(&data)
+                .map_err(|e| format!("seed: {}", e))?;
+            orm::init_global_orm(&program)
+                .map_err(|e| format!("seed: failed to initialize ORM: {}", e))?;
+
+            let records_json = serde_json::to_string(&records)?;
+            let inserted =
+                orm::orm_insert_many(&model, &records_json).map_err(|e| format!("seed: {}", e))?;
+            println!("Inserted {inserted} {model} row(s) from {data}");
+        }
+
+        Commands::Bench {
+            target,
+            concurrency,
+            duration,
+        } => {
+            run_bench(&file, &target, concurrency, duration)?;
+        }
+
+        Commands::Run { watch: true } => {
+            run_watch(&file)?;
+        }
+
+        Commands::Run { watch: false } => {
+            let (source, mut program) = load_and_parse(&file)?;
             let _ = source;
 
             // Apply server overrides from config file (port / tls).
@@ -171,75 +294,86 @@ fn run_cli() -> Result<(), Box<dyn Error>> {
             println!("----------------------------------------");
             println!();
 
-            actix_web::rt::System::new().block_on(run_server(program))?;
+            let result = actix_web::rt::System::new().block_on(run_server(program));
+            metrics::shutdown();
+            result?;
         }
 
         Commands::Check => {
-            let (_source, _program) = load_and_parse(&cli.file)?;
-            println!("OK: {}", &cli.file);
+            let (_source, _program) = load_and_parse(&file)?;
+            println!("OK: {}", &file);
         }
 
         Commands::Schema => {
-            let (_source, program) = load_and_parse(&cli.file)?;
+            let (_source, program) = load_and_parse(&file)?;
             let schema = docs::build_schema(&program);
             println!("{}", serde_json::to_string_pretty(&schema)?);
         }
 
         Commands::Diagnostics => {
-            let (_source, program) = load_and_parse(&cli.file)?;
-            let diags = docs::build_diagnostics(&program);
+            let (_source, program) = load_and_parse(&file)?;
+            let mut diags = docs::build_diagnostics(&program);
+            typecheck::merge_type_diagnostics(&mut diags, &program);
             println!("{}", serde_json::to_string_pretty(&diags)?);
         }
 
-        Commands::Lint => {
-            let (_source, program) = load_and_parse(&cli.file)?;
-            let diags_json: serde_json::Value = docs::build_diagnostics(&program);
-
-            let errors = diags_json
-                .get("errors")
-                .and_then(|v| v.as_array())
-                .cloned()
-                .unwrap_or_default();
-            let warnings = diags_json
-                .get("warnings")
-                .and_then(|v| v.as_array())
-                .cloned()
-                .unwrap_or_default();
-
-            if errors.is_empty() && warnings.is_empty() {
-                println!("No lints: {}", &cli.file);
-            } else {
-                for item in &errors {
-                    let msg = item
-                        .get("message")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("Shrimpl error");
-                    println!("error: {msg}");
-                }
-                for item in &warnings {
-                    let msg = item
-                        .get("message")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("Shrimpl warning");
-                    println!("warning: {msg}");
+        Commands::Ast => {
+            let (_source, program) = load_and_parse(&file)?;
+            println!("{}", serde_json::to_string_pretty(&program)?);
+        }
+
+        Commands::Doc { out } => {
+            let (_source, program) = load_and_parse(&file)?;
+            let markdown = docs::build_markdown(&program);
+            match out {
+                Some(path) => {
+                    fs::write(&path, markdown)?;
+                    println!("Wrote API docs to {path}");
                 }
+                None => print!("{markdown}"),
             }
+        }
+
+        Commands::Lint => {
+            let (source, program) = load_and_parse(&file)?;
+            let mut diags_json: serde_json::Value = docs::build_diagnostics(&program);
+            typecheck::merge_type_diagnostics(&mut diags_json, &program);
+            report_lints(&file, &source, &diags_json, "No lints");
+        }
+
+        Commands::Typecheck => {
+            let (source, program) = load_and_parse(&file)?;
+            let mut diags_json = serde_json::json!({ "errors": [], "warnings": [] });
+            typecheck::merge_type_diagnostics(&mut diags_json, &program);
+            report_lints(&file, &source, &diags_json, "No type errors");
+        }
 
-            if !errors.is_empty() {
-                // Non-zero exit so CI can fail on errors.
-                std::process::exit(1);
+        Commands::Test { format, json, out } => {
+            let format = if json { TestFormat::Json } else { format };
+            let (_source, program) = load_and_parse(&file)?;
+
+            // Tests may call db_* / orm_* builtins. They get fresh tables in
+            // an in-memory database, never the project's database file.
+            if let Err(e) = orm::init_global_orm_in_memory(&program) {
+                eprintln!("[shrimpl-orm] failed to initialize ORM: {e}");
             }
+
+            let program = std::sync::Arc::new(program);
+            let results = interpreter::eval::with_shared_program(&program, || {
+                tests::run_program_tests(&program)
+            });
+            report_tests(&file, &results, format, out.as_deref())?;
         }
 
         Commands::Format => {
-            let (source, _program) = load_and_parse(&cli.file)?;
+            let (source, _program) = load_and_parse(&file)?;
             let formatted = format_source(&source);
 
             if formatted == source {
-                println!("Already formatted: {}", &cli.file);
+                println!("Already formatted: {}", &file);
             } else {
-                fs::write(&cli.file, formatted)?;
-                println!("Reformatted {}", &cli.file);
+                fs::write(&file, formatted)?;
+                println!("Reformatted {}", &file);
             }
         }
     }
@@ -247,6 +381,98 @@ fn run_cli() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+/// `shrimpl bench`: start the server in the background, hammer one endpoint
+/// for `duration_secs`, and print throughput and latency percentiles.
+fn run_bench(
+    path: &str,
+    target: &str,
+    concurrency: usize,
+    duration_secs: u64,
+) -> Result<(), Box<dyn Error>> {
+    let (method, req_path) = match target.trim().split_once(char::is_whitespace) {
+        Some((m, p)) => (m.to_ascii_uppercase(), p.trim().to_string()),
+        None => ("GET".to_string(), target.trim().to_string()),
+    };
+    if !req_path.starts_with('/') {
+        return Err(format!(
+            "bench target must look like \"GET /path\", got \"{}\"",
+            target
+        )
+        .into());
+    }
+    let method = reqwest::Method::from_bytes(method.as_bytes())
+        .map_err(|_| format!("bench: invalid HTTP method '{}'", method))?;
+
+    let (_source, mut program) = load_and_parse(path)?;
+    shrimpl_config::apply_server_to_program(&mut program);
+    if let Err(e) = orm::init_global_orm(&program) {
+        eprintln!("[shrimpl-orm] failed to initialize ORM: {e}");
+    }
+
+    let port = program.server.port;
+    let scheme = if program.server.tls { "https" } else { "http" };
+    let url = format!("{scheme}://127.0.0.1:{port}{req_path}");
+
+    std::thread::spawn(move || {
+        if let Err(e) = actix_web::rt::System::new().block_on(run_server(program)) {
+            eprintln!("bench: server failed: {e}");
+        }
+    });
+
+    // Wait for the listener before sending traffic.
+    let addr = std::net::SocketAddr::from(([127, 0, 0, 1], port));
+    let ready = (0..100).any(|_| {
+        if std::net::TcpStream::connect_timeout(&addr, Duration::from_millis(100)).is_ok() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+        false
+    });
+    if !ready {
+        return Err(format!("bench: server did not start listening on port {port}").into());
+    }
+
+    println!("shrimpl bench {method} {req_path} ({concurrency} connections, {duration_secs}s)");
+
+    let report = tokio::runtime::Runtime::new()?.block_on(concurrency::run_load(
+        method,
+        url,
+        concurrency,
+        Duration::from_secs(duration_secs),
+    ))?;
+
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    let completed = report.latencies.len();
+    let secs = report.elapsed.as_secs_f64().max(f64::EPSILON);
+
+    println!(
+        "  requests:   {} ({:.1} req/s)",
+        completed,
+        completed as f64 / secs
+    );
+    println!(
+        "  latency:    p50 {:.2} ms, p95 {:.2} ms, p99 {:.2} ms, max {:.2} ms",
+        ms(report.percentile(50.0)),
+        ms(report.percentile(95.0)),
+        ms(report.percentile(99.0)),
+        ms(report.percentile(100.0))
+    );
+    let statuses: Vec<String> = report
+        .statuses
+        .iter()
+        .map(|(status, count)| format!("{status} x {count}"))
+        .collect();
+    if statuses.is_empty() {
+        println!("  statuses:   none");
+    } else {
+        println!("  statuses:   {}", statuses.join(", "));
+    }
+    println!("  errors:     {}", report.errors);
+
+    metrics::shutdown();
+    Ok(())
+}
+
 /// Read the Shrimpl source file and parse it into a Program.
 /// Also writes shrimpl.lock using the current Shrimpl version and environment.
 fn load_and_parse(path: &str) -> Result<(String, ast::Program), Box<dyn Error>> {
@@ -260,6 +486,227 @@ fn load_and_parse(path: &str) -> Result<(String, ast::Program), Box<dyn Error>>
     Ok((source, program))
 }
 
+/// Whether lint output should use ANSI colors.
+/// Honors NO_COLOR (https://no-color.org) and skips color when stdout is not a TTY.
+fn use_color() -> bool {
+    use std::io::IsTerminal;
+    env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal()
+}
+
+/// Best-effort 1-based source line for a diagnostic.
+/// Uses the diagnostic's own `line` when present; otherwise looks up the
+/// declaration of the named endpoint or function in the source.
+fn diagnostic_line(source: &str, item: &serde_json::Value) -> Option<usize> {
+    if let Some(line) = item.get("line").and_then(|v| v.as_u64()) {
+        return Some(line as usize);
+    }
+
+    let scope = item.get("scope").and_then(|v| v.as_str())?;
+    let name = item.get("name").and_then(|v| v.as_str())?;
+
+    let matches = |line: &str| -> bool {
+        let t = line.trim_start();
+        match scope {
+            "endpoint" => t.starts_with("endpoint ") && t.contains(&format!("\"{}\"", name)),
+            "function" => t
+                .strip_prefix("func ")
+                .map(|rest| rest.trim_start().starts_with(&format!("{}(", name)))
+                .unwrap_or(false),
+            "test" => t.starts_with("test ") && t.contains(&format!("\"{}\"", name)),
+            _ => false,
+        }
+    };
+
+    source.lines().position(matches).map(|idx| idx + 1)
+}
+
+/// Print `{ "errors", "warnings" }` diagnostics (or `clean_msg` when there
+/// are none) and exit with status 1 if there are errors.
+fn report_lints(path: &str, source: &str, diags_json: &serde_json::Value, clean_msg: &str) {
+    let errors = diags_json
+        .get("errors")
+        .and_then(|v| v.as_array())
+        .cloned()
+        .unwrap_or_default();
+    let warnings = diags_json
+        .get("warnings")
+        .and_then(|v| v.as_array())
+        .cloned()
+        .unwrap_or_default();
+
+    if errors.is_empty() && warnings.is_empty() {
+        println!("{clean_msg}: {path}");
+    } else {
+        print_lints(path, source, &errors, &warnings);
+    }
+
+    if !errors.is_empty() {
+        // Non-zero exit so CI can fail on errors.
+        std::process::exit(1);
+    }
+}
+
+/// Output format for `shrimpl test`.
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum TestFormat {
+    /// PASS/FAIL per test and a summary line
+    Text,
+    /// One JSON object with every result
+    Json,
+    /// JUnit XML for CI test panels
+    Junit,
+}
+
+/// Print (or write to `out`) the test results in `format`, then exit with
+/// status 1 if any test failed, like `report_lints` does for errors.
+fn report_tests(
+    path: &str,
+    results: &[tests::TestResult],
+    format: TestFormat,
+    out: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    let failed = results.iter().filter(|r| !r.passed).count();
+    let passed = results.len() - failed;
+    let summary = format!("{path}: {passed} passed, {failed} failed");
+
+    let report = match format {
+        TestFormat::Json => {
+            let tests_json: Vec<serde_json::Value> = results
+                .iter()
+                .map(|r| {
+                    serde_json::json!({
+                        "name": r.name,
+                        "passed": r.passed,
+                        "failures": r.failures,
+                        "elapsed_ms": r.elapsed.as_micros() as f64 / 1000.0,
+                    })
+                })
+                .collect();
+            let report = serde_json::json!({
+                "file": path,
+                "passed": passed,
+                "failed": failed,
+                "tests": tests_json,
+            });
+            format!("{}\n", serde_json::to_string_pretty(&report)?)
+        }
+        TestFormat::Junit => tests::to_junit_xml(path, results),
+        TestFormat::Text if results.is_empty() => format!("No tests: {path}\n"),
+        TestFormat::Text => {
+            // Only color what goes to a terminal.
+            let color = out.is_none() && use_color();
+            let paint = |text: &str, code: &str| {
+                if color {
+                    format!("\x1b[{code}m{text}\x1b[0m")
+                } else {
+                    text.to_string()
+                }
+            };
+
+            let mut text = String::new();
+            for r in results {
+                let ms = r.elapsed.as_secs_f64() * 1000.0;
+                if r.passed {
+                    text.push_str(&format!(
+                        "{} {} ({ms:.1} ms)\n",
+                        paint("PASS", "32"),
+                        r.name
+                    ));
+                } else {
+                    text.push_str(&format!(
+                        "{} {} ({ms:.1} ms)\n",
+                        paint("FAIL", "31"),
+                        r.name
+                    ));
+                    for failure in &r.failures {
+                        text.push_str(&format!("  - {failure}\n"));
+                    }
+                }
+            }
+            text.push_str(&format!("\n{summary}\n"));
+            text
+        }
+    };
+
+    match out {
+        Some(out_path) => {
+            fs::write(out_path, report)?;
+            println!("{summary} (report written to {out_path})");
+        }
+        None => print!("{report}"),
+    }
+
+    if failed > 0 {
+        // Non-zero exit so CI can fail on test failures.
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Print lints as `path:line: severity[code]: message`, sorted by line,
+/// followed by a summary count.
+fn print_lints(
+    path: &str,
+    source: &str,
+    errors: &[serde_json::Value],
+    warnings: &[serde_json::Value],
+) {
+    let color = use_color();
+
+    let mut items: Vec<(Option<usize>, &str, &serde_json::Value)> = errors
+        .iter()
+        .map(|e| (diagnostic_line(source, e), "error", e))
+        .chain(
+            warnings
+                .iter()
+                .map(|w| (diagnostic_line(source, w), "warning", w)),
+        )
+        .collect();
+
+    // Diagnostics with a known line first (in order), then the rest.
+    items.sort_by_key(|(line, severity, _)| (line.is_none(), *line, *severity != "error"));
+
+    for (line, severity, item) in items {
+        let msg = item
+            .get("message")
+            .and_then(|v| v.as_str())
+            .unwrap_or("Shrimpl diagnostic");
+        let code = item
+            .get("code")
+            .or_else(|| item.get("scope"))
+            .and_then(|v| v.as_str())
+            .unwrap_or("general");
+
+        let location = match line {
+            Some(n) => format!("{path}:{n}"),
+            None => path.to_string(),
+        };
+        let label = format!("{severity}[{code}]");
+
+        if color {
+            let paint = if severity == "error" { "31" } else { "33" };
+            println!("\x1b[1m{location}:\x1b[0m \x1b[1;{paint}m{label}\x1b[0m: {msg}");
+        } else {
+            println!("{location}: {label}: {msg}");
+        }
+    }
+
+    let plural = |n: usize, word: &str| {
+        if n == 1 {
+            format!("{n} {word}")
+        } else {
+            format!("{n} {word}s")
+        }
+    };
+    println!();
+    println!(
+        "{}: {}, {}",
+        path,
+        plural(errors.len(), "error"),
+        plural(warnings.len(), "warning")
+    );
+}
+
 /// Simple, safe formatter:
 /// - converts tabs to two spaces
 /// - strips trailing whitespace
@@ -289,6 +736,91 @@ fn format_source(source: &str) -> String {
     result
 }
 
+/// Watch mode for `shrimpl run --watch`.
+///
+/// The server runs as a child `shrimpl run` process. Whenever a `.shr` file
+/// under the entry file's directory changes (which covers imports), the
+/// source is re-parsed; on success the child is restarted, on a parse error
+/// the last good server keeps running and the error is printed.
+fn run_watch(path: &str) -> Result<(), Box<dyn Error>> {
+    use notify::{RecursiveMode, Watcher};
+
+    let exe = env::current_exe()?;
+    let spawn_server = || -> Result<Child, Box<dyn Error>> {
+        let child = Command::new(&exe)
+            .args(["--file", path, "run"])
+            .stdin(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("Failed to start Shrimpl server: {}", e))?;
+        Ok(child)
+    };
+
+    let mut server: Option<Child> = match load_and_parse(path) {
+        Ok(_) => Some(spawn_server()?),
+        Err(e) => {
+            eprintln!("[shrimpl watch] {e}");
+            eprintln!("[shrimpl watch] waiting for changes...");
+            None
+        }
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    let entry = Path::new(path);
+    let watch_dir = match entry.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    watcher.watch(watch_dir, RecursiveMode::Recursive)?;
+    println!(
+        "[shrimpl watch] watching {} for .shr changes",
+        watch_dir.display()
+    );
+
+    let is_shr_change = |event: &notify::Event| {
+        !event.kind.is_access()
+            && event
+                .paths
+                .iter()
+                .any(|p| p.extension().map(|ext| ext == "shr").unwrap_or(false))
+    };
+
+    while let Ok(res) = rx.recv() {
+        let changed = matches!(&res, Ok(event) if is_shr_change(event));
+        if !changed {
+            continue;
+        }
+
+        // Debounce: editors often emit several events per save.
+        std::thread::sleep(Duration::from_millis(200));
+        while rx.try_recv().is_ok() {}
+
+        match load_and_parse(path) {
+            Ok(_) => {
+                println!("[shrimpl watch] change detected, restarting server");
+                if let Some(mut child) = server.take() {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                }
+                server = Some(spawn_server()?);
+            }
+            Err(e) => {
+                eprintln!("[shrimpl watch] {e}");
+                if server.is_some() {
+                    eprintln!("[shrimpl watch] keeping the last good server running");
+                }
+            }
+        }
+    }
+
+    if let Some(mut child) = server.take() {
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+
+    Ok(())
+}
+
 /// Start the external shrimpl-lsp process and wait for it to exit.
 fn start_lsp_subprocess(exe: &str) -> Result<(), Box<dyn Error>> {
     let mut child = Command::new(exe)
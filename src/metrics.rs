@@ -3,25 +3,49 @@
 // Basic metrics + tracing helpers for Shrimpl.
 //
 // This file is self-contained and uses `tracing` as the primary API.
-// If OpenTelemetry is configured, traces can be exported; otherwise,
-// everything still works as logs.
+// If OpenTelemetry is enabled, spans are exported over OTLP (gRPC);
+// otherwise, everything still works as logs.
 //
 // Environment-driven initialization:
 //
-//   SHRIMPL_OTEL=1                   # enable otel pipeline
-//   SHRIMPL_SERVICE_NAME=shrimpl-app # optional service name
+//   RUST_LOG=info                                      # EnvFilter directives
+//   SHRIMPL_LOG_FORMAT=text|json                       # fmt layer output (default text)
+//   SHRIMPL_OTEL=1                                     # enable otel pipeline
+//   SHRIMPL_SERVICE_NAME=shrimpl-app                   # optional service name
+//   OTEL_EXPORTER_OTLP_ENDPOINT=http://localhost:4317  # optional collector
+//
+// Logs go to stderr so CLI commands that print to stdout stay pipeable.
+// The OTLP exporter runs on a small dedicated Tokio runtime, so
+// `init_from_env` can be called before the Actix system starts.
+// If the exporter can't be built, we fall back to plain fmt logging.
+//
+// Counters and latency histograms (endpoints and expensive builtins) are
+// kept in-process and rendered in the Prometheus text format by `render_prometheus`, which
+// the HTTP server exposes at /__shrimpl/metrics.
 //
 // You can also ignore OpenTelemetry entirely and just rely on tracing.
 
+use std::collections::BTreeMap;
 use std::env;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use once_cell::sync::OnceCell;
-use tracing::{info, instrument};
+use once_cell::sync::{Lazy, OnceCell};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace as sdktrace, Resource};
+use parking_lot::Mutex;
+use tracing::{debug, info, instrument, warn};
 use tracing_subscriber::prelude::*;
 use tracing_subscriber::{fmt, EnvFilter};
 
 static INIT_ONCE: OnceCell<()> = OnceCell::new();
 
+/// Runtime that owns the OTLP batch exporter, independent of Actix.
+static OTEL_RUNTIME: OnceCell<tokio::runtime::Runtime> = OnceCell::new();
+
+/// Install the global tracing subscriber (idempotent).
 pub fn init_from_env() {
     INIT_ONCE.get_or_init(|| {
         let enable_otel = env::var("SHRIMPL_OTEL")
@@ -30,41 +54,125 @@ pub fn init_from_env() {
 
         let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
 
-        if enable_otel {
-            // If you add opentelemetry crates, you can wire them here
-            // (left as a hook to keep core crate light).
-            let fmt_layer = fmt::layer().with_target(false);
-            tracing_subscriber::registry()
-                .with(filter)
-                .with(fmt_layer)
-                .init();
+        let json = env::var("SHRIMPL_LOG_FORMAT")
+            .map(|v| v.eq_ignore_ascii_case("json"))
+            .unwrap_or(false);
+        let (json_layer, text_layer) = if json {
+            let layer = fmt::layer()
+                .json()
+                .flatten_event(true)
+                .with_target(false)
+                .with_writer(std::io::stderr);
+            (Some(layer), None)
+        } else {
+            let layer = fmt::layer().with_target(false).with_writer(std::io::stderr);
+            (None, Some(layer))
+        };
+        let mut otel_error = None;
+
+        let otel_layer = if enable_otel {
+            match build_otlp_tracer() {
+                Ok(tracer) => Some(tracing_opentelemetry::layer().with_tracer(tracer)),
+                Err(err) => {
+                    otel_error = Some(err);
+                    None
+                }
+            }
         } else {
-            let fmt_layer = fmt::layer().with_target(false);
-            tracing_subscriber::registry()
-                .with(filter)
-                .with(fmt_layer)
-                .init();
+            None
+        };
+        let otel_active = otel_layer.is_some();
+
+        tracing_subscriber::registry()
+            .with(filter)
+            .with(json_layer)
+            .with(text_layer)
+            .with(otel_layer)
+            .init();
+
+        if let Some(err) = otel_error {
+            warn!(
+                "[shrimpl-metrics] OTLP export disabled, using fmt logging only: {}",
+                err
+            );
         }
 
-        info!(
+        debug!(
             "[shrimpl-metrics] tracing initialized (otel={})",
-            enable_otel
+            otel_active
         );
     });
 }
 
-/// Increment a named counter by `value`.
+/// Build the OTLP span exporter pipeline and register it as the global
+/// tracer provider. Returns an error message instead of panicking so the
+/// caller can fall back to fmt-only logging.
+fn build_otlp_tracer() -> Result<sdktrace::Tracer, String> {
+    let rt = OTEL_RUNTIME.get_or_try_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("shrimpl-otel")
+            .enable_all()
+            .build()
+            .map_err(|e| format!("could not start OTLP runtime: {}", e))
+    })?;
+    // The tonic channel and the batch processor task are spawned on `rt`.
+    let _guard = rt.enter();
+
+    let endpoint = env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+        .unwrap_or_else(|_| "http://localhost:4317".to_string());
+    let service_name = env::var("SHRIMPL_SERVICE_NAME").unwrap_or_else(|_| "shrimpl".to_string());
+
+    // A collector that is down shows up as export errors on every batch;
+    // report the first one and stay quiet afterwards.
+    static EXPORT_ERROR_SEEN: AtomicBool = AtomicBool::new(false);
+    let _ = opentelemetry::global::set_error_handler(|err| {
+        if !EXPORT_ERROR_SEEN.swap(true, Ordering::Relaxed) {
+            eprintln!("[shrimpl-metrics] OpenTelemetry error (further errors suppressed): {err}");
+        }
+    });
+
+    let provider = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint.clone()),
+        )
+        .with_trace_config(
+            sdktrace::Config::default().with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                service_name,
+            )])),
+        )
+        .install_batch(runtime::Tokio)
+        .map_err(|e| format!("could not build OTLP exporter for {}: {}", endpoint, e))?;
+
+    opentelemetry::global::set_tracer_provider(provider.clone());
+    Ok(provider.tracer("shrimpl"))
+}
+
+/// Flush and shut down the OTLP exporter, if one was installed.
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+/// Increment a named counter by `value`. Counters are cumulative for the
+/// life of the process and appear in `render_prometheus`.
 pub fn metrics_incr(name: &str, value: f64) {
+    *COUNTERS.lock().entry(name.to_string()).or_insert(0.0) += value;
     info!(counter = name, value, "[metric] incr");
 }
 
 /// Record a gauge (point-in-time) value.
+#[allow(dead_code)]
 pub fn metrics_gauge(name: &str, value: f64) {
     info!(gauge = name, value, "[metric] gauge");
 }
 
 /// Trace a named span around a closure. Intended to be used by
 /// interpreter / HTTP handlers.
+#[allow(dead_code)]
 #[instrument(name = "shrimpl_span", skip(f))]
 pub fn trace_span<T, F>(span_name: &str, f: F) -> T
 where
@@ -75,3 +183,122 @@ where
     info!(span = span_name, "[trace] span end");
     out
 }
+
+// ---------- counters ----------
+
+static COUNTERS: Lazy<Mutex<BTreeMap<String, f64>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+// ---------- latency histograms ----------
+
+/// Upper bounds (seconds) of the latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Default)]
+struct Histogram {
+    /// Non-cumulative counts per bucket; the last slot is +Inf.
+    buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        let slot = LATENCY_BUCKETS
+            .iter()
+            .position(|le| value <= *le)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[slot] += 1;
+        self.count += 1;
+        self.sum += value;
+    }
+}
+
+/// Metric name -> label pairs -> histogram. BTreeMaps keep the rendered
+/// output in a stable order.
+type HistogramRegistry = BTreeMap<&'static str, BTreeMap<Vec<(&'static str, String)>, Histogram>>;
+
+static HISTOGRAMS: Lazy<Mutex<HistogramRegistry>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// Record one observation (in seconds) in a labelled histogram.
+pub fn metrics_histogram(name: &'static str, labels: &[(&'static str, &str)], value: f64) {
+    let labels: Vec<(&'static str, String)> =
+        labels.iter().map(|(k, v)| (*k, v.to_string())).collect();
+    HISTOGRAMS
+        .lock()
+        .entry(name)
+        .or_default()
+        .entry(labels)
+        .or_default()
+        .observe(value);
+}
+
+/// Latency of one endpoint evaluation, keyed by Shrimpl path and status.
+pub fn observe_endpoint_latency(path: &str, status: u16, seconds: f64) {
+    metrics_histogram(
+        "shrimpl_endpoint_duration_seconds",
+        &[("path", path), ("status", &status.to_string())],
+        seconds,
+    );
+}
+
+/// Latency of one call to an expensive builtin (http_get, openai_chat, ...).
+pub fn observe_builtin_latency(builtin: &str, seconds: f64) {
+    metrics_histogram(
+        "shrimpl_builtin_duration_seconds",
+        &[("builtin", builtin)],
+        seconds,
+    );
+}
+
+/// Render all counters and histograms in the Prometheus text exposition format.
+pub fn render_prometheus() -> String {
+    let mut out = String::new();
+
+    for (name, value) in COUNTERS.lock().iter() {
+        let _ = writeln!(out, "# TYPE {} counter", name);
+        let _ = writeln!(out, "{} {}", name, value);
+    }
+
+    let histograms = HISTOGRAMS.lock();
+
+    for (name, series) in histograms.iter() {
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for (labels, hist) in series {
+            let base: Vec<String> = labels
+                .iter()
+                .map(|(k, v)| format!("{}=\"{}\"", k, escape_label(v)))
+                .collect();
+            let with_le = |le: &str| {
+                let mut all = base.clone();
+                all.push(format!("le=\"{}\"", le));
+                all.join(",")
+            };
+
+            let mut cumulative = 0;
+            for (i, le) in LATENCY_BUCKETS.iter().enumerate() {
+                cumulative += hist.buckets[i];
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{{{}}} {}",
+                    name,
+                    with_le(&le.to_string()),
+                    cumulative
+                );
+            }
+            let _ = writeln!(out, "{}_bucket{{{}}} {}", name, with_le("+Inf"), hist.count);
+            let _ = writeln!(out, "{}_sum{{{}}} {}", name, base.join(","), hist.sum);
+            let _ = writeln!(out, "{}_count{{{}}} {}", name, base.join(","), hist.count);
+        }
+    }
+
+    out
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
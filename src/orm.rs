@@ -2,57 +2,351 @@
 //
 // Very small ORM layer for Shrimpl models.
 //
-// - Uses rusqlite with a single file database `shrimpl.db` in the CWD.
+// - Uses rusqlite with a single file database (`shrimpl.db` in the CWD, or
+//   config `database.path`), opened as a small pool of connections (WAL
+//   mode) so concurrent requests don't serialize on one shared connection.
 // - At startup, `init_global_orm` walks all Program.models and issues
 //   CREATE TABLE IF NOT EXISTS statements.
 // - Exposes helpers that operate on JSON strings so the interpreter can
 //   wire them into builtins without depending on internal Value types.
+// - Supports request-scoped transactions: between `begin_request_tx` and
+//   `finish_request_tx`, every ORM call on that thread shares one pinned
+//   connection and one transaction.
 
+use std::cell::RefCell;
 use std::collections::HashMap;
-use std::sync::Mutex;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use indexmap::IndexMap;
+use parking_lot::{Condvar, Mutex, RwLock};
 
 use base64::engine::general_purpose::STANDARD;
 use base64::Engine;
 use once_cell::sync::Lazy;
+use regex::Regex;
 use rusqlite::{params, Connection};
 use serde_json::{Map as JsonMap, Value as JsonValue};
 
+use crate::config;
 use crate::parser::ast::{ModelDef, ModelField, Program};
 
 /// Global ORM handle. Initialized once at startup via `init_global_orm`.
-static GLOBAL_ORM: Lazy<Mutex<Option<Orm>>> = Lazy::new(|| Mutex::new(None));
+///
+/// The lock is only held long enough to clone the `Arc`; queries run on
+/// pooled connections without it. `parking_lot` locks do not poison, so a
+/// panic in one request can't break every later request.
+static GLOBAL_ORM: Lazy<RwLock<Option<Arc<Orm>>>> = Lazy::new(|| RwLock::new(None));
+
+/// Connections opened for the global ORM.
+const POOL_SIZE: usize = 8;
+
+/// How long a connection waits on a locked database before failing.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long an ORM call waits for a free pooled connection before failing.
+const CHECKOUT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// `db_stream` responses allowed at once. Each one keeps a pooled connection
+/// until its client has read every row, so this leaves the rest of the pool
+/// for ordinary requests however slow the stream readers are.
+pub const MAX_STREAMS: usize = POOL_SIZE / 2;
+
+static ACTIVE_STREAMS: AtomicUsize = AtomicUsize::new(0);
+
+/// One of the `MAX_STREAMS` stream slots; released when dropped.
+pub struct StreamSlot(());
+
+/// Claim a stream slot, or None when `MAX_STREAMS` streams are running.
+pub fn try_stream_slot() -> Option<StreamSlot> {
+    ACTIVE_STREAMS
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+            (n < MAX_STREAMS).then_some(n + 1)
+        })
+        .ok()
+        .map(|_| StreamSlot(()))
+}
+
+impl Drop for StreamSlot {
+    fn drop(&mut self) {
+        ACTIVE_STREAMS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Fixed-size set of SQLite connections handed out one operation at a time.
+struct ConnPool {
+    idle: Mutex<Vec<Connection>>,
+    returned: Condvar,
+}
+
+impl ConnPool {
+    fn new(conns: Vec<Connection>) -> Self {
+        ConnPool {
+            idle: Mutex::new(conns),
+            returned: Condvar::new(),
+        }
+    }
+
+    /// Take a connection, waiting up to `CHECKOUT_TIMEOUT` for one to be
+    /// returned if all are busy.
+    fn get(&self) -> Result<PooledConn<'_>, String> {
+        self.get_within(CHECKOUT_TIMEOUT)
+    }
+
+    fn get_within(&self, timeout: Duration) -> Result<PooledConn<'_>, String> {
+        let deadline = Instant::now() + timeout;
+        let mut idle = self.idle.lock();
+        loop {
+            if let Some(conn) = idle.pop() {
+                return Ok(PooledConn {
+                    pool: self,
+                    conn: Some(conn),
+                });
+            }
+            if self.returned.wait_until(&mut idle, deadline).timed_out() && idle.is_empty() {
+                return Err(format!(
+                    "database pool exhausted: no connection was free within {} ms",
+                    timeout.as_millis()
+                ));
+            }
+        }
+    }
+
+    /// Return a connection detached with `PooledConn::into_inner`.
+    fn put(&self, conn: Connection) {
+        self.idle.lock().push(conn);
+        self.returned.notify_one();
+    }
+}
+
+/// A checked-out connection; goes back to the pool when dropped.
+struct PooledConn<'a> {
+    pool: &'a ConnPool,
+    conn: Option<Connection>,
+}
+
+impl Deref for PooledConn<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn
+            .as_ref()
+            .expect("pooled connection present until drop")
+    }
+}
+
+impl PooledConn<'_> {
+    /// Keep the connection past this guard; hand it back with `ConnPool::put`.
+    fn into_inner(mut self) -> Connection {
+        self.conn
+            .take()
+            .expect("pooled connection present until drop")
+    }
+}
+
+impl Drop for PooledConn<'_> {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            self.pool.put(conn);
+        }
+    }
+}
+
+thread_local! {
+    /// The request transaction scope open on this thread, if any.
+    static REQUEST_TX: RefCell<Option<RequestTx>> = const { RefCell::new(None) };
+
+    /// Why ORM calls are refused on this thread, if they are (see
+    /// `deny_on_this_thread`).
+    static DENIED: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// A request-scoped transaction. The connection is taken from the pool
+/// (and `BEGIN IMMEDIATE` run on it) by the first ORM call in the scope, so
+/// requests that never touch the database never lock it.
+struct RequestTx {
+    orm: Arc<Orm>,
+    /// The pinned connection while idle; `None` before the first ORM call
+    /// or while an operation has it checked out.
+    conn: Option<Connection>,
+    began: bool,
+}
+
+impl RequestTx {
+    fn finish(mut self, commit: bool) -> Result<(), String> {
+        let Some(conn) = self.conn.take() else {
+            return Ok(());
+        };
+        let result = if commit {
+            conn.execute_batch("COMMIT")
+                .map_err(|e| format!("commit transaction failed: {e}"))
+        } else {
+            Ok(())
+        };
+        if !commit || result.is_err() {
+            let _ = conn.execute_batch("ROLLBACK");
+        }
+        self.orm.pool.put(conn);
+        result
+    }
+}
+
+/// The connection one ORM operation runs on: a pooled one, or the current
+/// request transaction's, which goes back to the scope when dropped.
+enum OrmConn<'a> {
+    Pooled(PooledConn<'a>),
+    Pinned {
+        pool: &'a ConnPool,
+        conn: Option<Connection>,
+    },
+}
+
+impl Deref for OrmConn<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        match self {
+            OrmConn::Pooled(conn) => conn,
+            OrmConn::Pinned { conn, .. } => {
+                conn.as_ref().expect("pinned connection present until drop")
+            }
+        }
+    }
+}
+
+impl Drop for OrmConn<'_> {
+    fn drop(&mut self) {
+        let OrmConn::Pinned { pool, conn } = self else {
+            return;
+        };
+        let Some(conn) = conn.take() else {
+            return;
+        };
+        let orphan = REQUEST_TX.with(|slot| match slot.borrow_mut().as_mut() {
+            Some(tx) => {
+                tx.conn = Some(conn);
+                None
+            }
+            None => Some(conn),
+        });
+        // The scope ended while the connection was out; don't leak it.
+        if let Some(conn) = orphan {
+            let _ = conn.execute_batch("ROLLBACK");
+            pool.put(conn);
+        }
+    }
+}
 
 /// SQLite-backed ORM for Shrimpl `model` declarations.
 pub struct Orm {
-    /// SQLite connection (currently a single file `shrimpl.db` in CWD).
-    conn: Connection,
+    /// Pooled SQLite connections (to `config::database_path()` for the global ORM).
+    pool: ConnPool,
     /// All models keyed by model name (e.g. "User").
-    models: HashMap<String, ModelDef>,
+    models: IndexMap<String, ModelDef>,
+    /// Compiled `match("...")` rules keyed by their source pattern.
+    patterns: HashMap<String, Regex>,
 }
 
 impl Orm {
-    /// Construct a new ORM instance and run migrations for all models.
-    pub fn new(conn: Connection, models: HashMap<String, ModelDef>) -> rusqlite::Result<Self> {
-        let mut orm = Orm { conn, models };
+    /// Construct an ORM over a single connection and run migrations for all
+    /// models. Operations are serialized on that connection.
+    pub fn new(conn: Connection, models: IndexMap<String, ModelDef>) -> rusqlite::Result<Self> {
+        Self::with_connections(vec![conn], models)
+    }
+
+    /// Open `pool_size` connections to the SQLite file at `path` (WAL mode,
+    /// so readers don't block on a writer) and run migrations.
+    pub fn open(
+        path: &str,
+        pool_size: usize,
+        models: IndexMap<String, ModelDef>,
+    ) -> rusqlite::Result<Self> {
+        let mut conns = Vec::new();
+        for _ in 0..pool_size.max(1) {
+            let conn = Connection::open(path)?;
+            conn.busy_timeout(BUSY_TIMEOUT)?;
+            // `journal_mode` reports the resulting mode as a row.
+            conn.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
+            conns.push(conn);
+        }
+        Self::with_connections(conns, models)
+    }
+
+    /// A connection for one operation: this thread's request transaction
+    /// connection when a scope is open for this ORM, else a pooled one.
+    fn conn(&self) -> Result<OrmConn<'_>, String> {
+        REQUEST_TX.with(|slot| {
+            let mut slot = slot.borrow_mut();
+            let Some(tx) = slot
+                .as_mut()
+                .filter(|tx| std::ptr::eq(Arc::as_ptr(&tx.orm), self))
+            else {
+                return Ok(OrmConn::Pooled(self.pool.get()?));
+            };
+
+            let conn = match tx.conn.take() {
+                Some(conn) => conn,
+                None if tx.began => {
+                    return Err("request transaction connection is already in use".to_string())
+                }
+                None => {
+                    let conn = self.pool.get()?.into_inner();
+                    if let Err(e) = conn.execute_batch("BEGIN IMMEDIATE") {
+                        self.pool.put(conn);
+                        return Err(format!("begin transaction failed: {e}"));
+                    }
+                    tx.began = true;
+                    conn
+                }
+            };
+            Ok(OrmConn::Pinned {
+                pool: &self.pool,
+                conn: Some(conn),
+            })
+        })
+    }
+
+    fn with_connections(
+        conns: Vec<Connection>,
+        models: IndexMap<String, ModelDef>,
+    ) -> rusqlite::Result<Self> {
+        // The parser already rejected invalid regexes, so any that fail to
+        // compile here are simply skipped.
+        let patterns = models
+            .values()
+            .flat_map(|m| m.fields.iter())
+            .filter_map(|f| f.pattern.as_ref())
+            .filter_map(|p| Regex::new(p).ok().map(|re| (p.clone(), re)))
+            .collect();
+        let mut orm = Orm {
+            pool: ConnPool::new(conns),
+            models,
+            patterns,
+        };
         orm.migrate_all()?;
         Ok(orm)
     }
 
     /// Run `CREATE TABLE IF NOT EXISTS` for every model in `self.models`.
     fn migrate_all(&mut self) -> rusqlite::Result<()> {
-        // Avoid borrowing `self.models` for the whole loop while also
-        // mutably borrowing `self` in `migrate_model` by cloning the
-        // model definitions into a local Vec first.
-        let models: Vec<ModelDef> = self.models.values().cloned().collect();
-        for model in &models {
-            self.migrate_model(model)?;
-        }
-        Ok(())
+        // Nothing else can hold a connection yet, so take one straight from
+        // the idle list instead of going through a timed checkout.
+        let Some(conn) = self.pool.idle.get_mut().pop() else {
+            return Ok(());
+        };
+        let result = self
+            .models
+            .values()
+            .try_for_each(|model| self.migrate_model(&conn, model));
+        self.pool.idle.get_mut().push(conn);
+        result
     }
 
     /// Build and execute a `CREATE TABLE IF NOT EXISTS` statement for a
     /// single model.
-    fn migrate_model(&mut self, model: &ModelDef) -> rusqlite::Result<()> {
+    fn migrate_model(&self, conn: &Connection, model: &ModelDef) -> rusqlite::Result<()> {
         let mut cols = Vec::new();
         for field in &model.fields {
             cols.push(self.column_sql(field));
@@ -63,7 +357,7 @@ impl Orm {
             model.table_name,
             cols.join(", ")
         );
-        self.conn.execute(&sql, [])?;
+        conn.execute(&sql, [])?;
         Ok(())
     }
 
@@ -108,51 +402,54 @@ impl Orm {
             .models
             .get(model_name)
             .ok_or_else(|| format!("unknown model '{}'", model_name))?;
+        self.check_record(model, record)?;
+        let conn = self.conn()?;
 
-        let obj = record
-            .as_object()
-            .ok_or_else(|| "record must be a JSON object".to_string())?;
+        insert_row(&conn, model, record)
+    }
 
-        let mut cols = Vec::new();
-        let mut placeholders = Vec::new();
-        let mut values: Vec<JsonValue> = Vec::new();
+    /// Insert many JSON objects into `model_name` in one transaction.
+    ///
+    /// - Every key of every record must be a model field; unknown columns
+    ///   are rejected before anything is written.
+    /// - If any insert fails, none of the rows are kept.
+    /// - Returns the number of rows inserted.
+    pub fn insert_many(&self, model_name: &str, records: &[JsonValue]) -> Result<usize, String> {
+        let model = self
+            .models
+            .get(model_name)
+            .ok_or_else(|| format!("unknown model '{}'", model_name))?;
 
-        for field in &model.fields {
-            if let Some(value) = obj.get(&field.name) {
-                cols.push(field.name.clone());
-                placeholders.push("?".to_string());
-                values.push(value.clone());
+        for (idx, record) in records.iter().enumerate() {
+            let obj = record
+                .as_object()
+                .ok_or_else(|| format!("row {}: record must be a JSON object", idx + 1))?;
+            for key in obj.keys() {
+                find_field(model, key).map_err(|e| format!("row {}: {}", idx + 1, e))?;
             }
+            self.check_record(model, record)
+                .map_err(|e| format!("row {}: {}", idx + 1, e))?;
         }
 
-        if cols.is_empty() {
-            return Err("record has no matching fields".to_string());
+        // A savepoint acts as a transaction of its own, or nests inside the
+        // request transaction when one is open.
+        let conn = self.conn()?;
+        conn.execute_batch("SAVEPOINT shrimpl_insert_many")
+            .map_err(|e| format!("begin transaction failed: {e}"))?;
+        let inserted = records.iter().enumerate().try_for_each(|(idx, record)| {
+            insert_row(&conn, model, record)
+                .map(|_| ())
+                .map_err(|e| format!("row {}: {}", idx + 1, e))
+        });
+        if let Err(e) = inserted {
+            let _ =
+                conn.execute_batch("ROLLBACK TO shrimpl_insert_many; RELEASE shrimpl_insert_many");
+            return Err(e);
         }
+        conn.execute_batch("RELEASE shrimpl_insert_many")
+            .map_err(|e| format!("commit transaction failed: {e}"))?;
 
-        let sql = format!(
-            "INSERT INTO {} ({}) VALUES ({})",
-            model.table_name,
-            cols.join(", "),
-            placeholders.join(", ")
-        );
-
-        let mut stmt = self
-            .conn
-            .prepare(&sql)
-            .map_err(|e| format!("insert prepare failed: {e}"))?;
-
-        let params_vec: Vec<rusqlite::types::Value> =
-            values.into_iter().map(json_to_sql_value).collect();
-
-        let rows_changed = stmt
-            .execute(rusqlite::params_from_iter(params_vec.iter()))
-            .map_err(|e| format!("insert execute failed: {e}"))?;
-
-        if rows_changed == 0 {
-            return Err("insert affected 0 rows".to_string());
-        }
-
-        Ok(self.conn.last_insert_rowid())
+        Ok(records.len())
     }
 
     /// Look up a row by primary key in `model_name`.
@@ -168,6 +465,7 @@ impl Orm {
             .models
             .get(model_name)
             .ok_or_else(|| format!("unknown model '{}'", model_name))?;
+        let conn = self.conn()?;
 
         let pk_field = model
             .fields
@@ -180,8 +478,7 @@ impl Orm {
             model.table_name, pk_field.name
         );
 
-        let mut stmt = self
-            .conn
+        let mut stmt = conn
             .prepare(&sql)
             .map_err(|e| format!("find prepare failed: {e}"))?;
 
@@ -191,18 +488,440 @@ impl Orm {
             .map_err(|e| format!("find query failed: {e}"))?;
 
         if let Some(row) = rows.next().map_err(|e| format!("find next failed: {e}"))? {
-            let mut obj = JsonMap::new();
-            for field in &model.fields {
-                let val: rusqlite::types::Value = row
-                    .get(field.name.as_str())
-                    .map_err(|e| format!("column get failed: {e}"))?;
-                obj.insert(field.name.clone(), sql_value_to_json(val));
-            }
-            Ok(Some(JsonValue::Object(obj)))
+            Ok(Some(JsonValue::Object(row_to_json(model, row)?)))
         } else {
             Ok(None)
         }
     }
+
+    /// Apply a partial update to the row with primary key `id`.
+    ///
+    /// - `patch` must be a JSON object; only the keys it contains are
+    ///   written, so fields missing from the patch keep their values.
+    /// - Keys that are not model fields, or that name the primary key, are
+    ///   rejected.
+    /// - Returns the number of rows changed (0 when `id` doesn't exist).
+    pub fn update_json(
+        &self,
+        model_name: &str,
+        id: &JsonValue,
+        patch: &JsonValue,
+    ) -> Result<usize, String> {
+        let model = self
+            .models
+            .get(model_name)
+            .ok_or_else(|| format!("unknown model '{}'", model_name))?;
+        let conn = self.conn()?;
+
+        let pk_field = model
+            .fields
+            .iter()
+            .find(|f| f.is_primary_key)
+            .ok_or_else(|| format!("model '{}' has no primary key field", model_name))?;
+
+        let obj = patch
+            .as_object()
+            .ok_or_else(|| "patch must be a JSON object".to_string())?;
+
+        let mut assignments = Vec::new();
+        let mut values: Vec<rusqlite::types::Value> = Vec::new();
+
+        for (key, value) in obj {
+            let field = find_field(model, key)?;
+            if field.is_primary_key {
+                return Err(format!(
+                    "cannot update primary key '{}' of model '{}'",
+                    field.name, model_name
+                ));
+            }
+            self.check_rules(model, field, value)?;
+            assignments.push(format!("{} = ?", field.name));
+            values.push(json_to_sql_value(value.clone()));
+        }
+
+        if assignments.is_empty() {
+            return Err("patch has no fields to update".to_string());
+        }
+
+        values.push(json_to_sql_value(id.clone()));
+
+        let sql = format!(
+            "UPDATE {} SET {} WHERE {} = ?",
+            model.table_name,
+            assignments.join(", "),
+            pk_field.name
+        );
+
+        conn.execute(&sql, rusqlite::params_from_iter(values.iter()))
+            .map_err(|e| format!("update execute failed: {e}"))
+    }
+
+    /// Delete the row with primary key `id` from `model_name`.
+    ///
+    /// - Returns the number of rows deleted (0 when `id` doesn't exist).
+    pub fn delete_by_id(&self, model_name: &str, id: &JsonValue) -> Result<usize, String> {
+        let model = self
+            .models
+            .get(model_name)
+            .ok_or_else(|| format!("unknown model '{}'", model_name))?;
+        let conn = self.conn()?;
+
+        let pk_field = model
+            .fields
+            .iter()
+            .find(|f| f.is_primary_key)
+            .ok_or_else(|| format!("model '{}' has no primary key field", model_name))?;
+
+        let sql = format!(
+            "DELETE FROM {} WHERE {} = ?",
+            model.table_name, pk_field.name
+        );
+
+        conn.execute(&sql, params![json_to_sql_value(id.clone())])
+            .map_err(|e| format!("delete execute failed: {e}"))
+    }
+
+    /// Count all rows in the table backing `model_name`.
+    pub fn count(&self, model_name: &str) -> Result<i64, String> {
+        let model = self
+            .models
+            .get(model_name)
+            .ok_or_else(|| format!("unknown model '{}'", model_name))?;
+        let conn = self.conn()?;
+
+        let sql = format!("SELECT COUNT(*) FROM {}", model.table_name);
+
+        conn.query_row(&sql, [], |row| row.get(0))
+            .map_err(|e| format!("count query failed: {e}"))
+    }
+
+    /// Visit every row of `model_name` in primary-key order, one at a time,
+    /// so large tables are never loaded into memory at once.
+    ///
+    /// - `visit` receives each row as a JSON object and returns `false` to
+    ///   stop early (e.g. the HTTP client went away).
+    /// - A pooled connection is held until the walk finishes.
+    pub fn for_each_row<F>(&self, model_name: &str, mut visit: F) -> Result<(), String>
+    where
+        F: FnMut(JsonMap<String, JsonValue>) -> bool,
+    {
+        let model = self
+            .models
+            .get(model_name)
+            .ok_or_else(|| format!("unknown model '{}'", model_name))?;
+        let conn = self.conn()?;
+
+        let order_by = model
+            .fields
+            .iter()
+            .find(|f| f.is_primary_key)
+            .map(|f| f.name.as_str())
+            .unwrap_or("rowid");
+        let sql = format!("SELECT * FROM {} ORDER BY {}", model.table_name, order_by);
+
+        let mut stmt = conn
+            .prepare(&sql)
+            .map_err(|e| format!("select prepare failed: {e}"))?;
+        let mut rows = stmt
+            .query([])
+            .map_err(|e| format!("select query failed: {e}"))?;
+
+        while let Some(row) = rows
+            .next()
+            .map_err(|e| format!("select next failed: {e}"))?
+        {
+            if !visit(row_to_json(model, row)?) {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Every row of `model_name` in primary-key order, as JSON objects.
+    pub fn all(&self, model_name: &str) -> Result<Vec<JsonValue>, String> {
+        let mut out = Vec::new();
+        self.for_each_row(model_name, |obj| {
+            out.push(JsonValue::Object(obj));
+            true
+        })?;
+        Ok(out)
+    }
+
+    /// Rows of `model_name` where `column = value`, in primary-key order.
+    ///
+    /// - `column` must be a field declared on the model.
+    /// - `value` is a JSON scalar compared with `=`.
+    pub fn find_where(
+        &self,
+        model_name: &str,
+        column: &str,
+        value: &JsonValue,
+    ) -> Result<Vec<JsonValue>, String> {
+        let model = self
+            .models
+            .get(model_name)
+            .ok_or_else(|| format!("unknown model '{}'", model_name))?;
+        let conn = self.conn()?;
+
+        let field = find_field(model, column)?;
+        let order_by = model
+            .fields
+            .iter()
+            .find(|f| f.is_primary_key)
+            .map(|f| f.name.as_str())
+            .unwrap_or("rowid");
+        let sql = format!(
+            "SELECT * FROM {} WHERE {} = ? ORDER BY {}",
+            model.table_name, field.name, order_by
+        );
+
+        let mut stmt = conn
+            .prepare(&sql)
+            .map_err(|e| format!("select prepare failed: {e}"))?;
+        let value = json_to_sql_value(value.clone());
+        let mut rows = stmt
+            .query(params![value])
+            .map_err(|e| format!("select query failed: {e}"))?;
+
+        let mut out = Vec::new();
+        while let Some(row) = rows
+            .next()
+            .map_err(|e| format!("select next failed: {e}"))?
+        {
+            out.push(JsonValue::Object(row_to_json(model, row)?));
+        }
+        Ok(out)
+    }
+
+    /// Count rows in `model_name` where `column = value`.
+    ///
+    /// - `column` must be a field declared on the model.
+    /// - `value` is a JSON scalar compared with `=`.
+    pub fn count_where(
+        &self,
+        model_name: &str,
+        column: &str,
+        value: &JsonValue,
+    ) -> Result<i64, String> {
+        let model = self
+            .models
+            .get(model_name)
+            .ok_or_else(|| format!("unknown model '{}'", model_name))?;
+        let conn = self.conn()?;
+
+        let field = find_field(model, column)?;
+
+        let sql = format!(
+            "SELECT COUNT(*) FROM {} WHERE {} = ?",
+            model.table_name, field.name
+        );
+
+        let value = json_to_sql_value(value.clone());
+        conn.query_row(&sql, params![value], |row| row.get(0))
+            .map_err(|e| format!("count query failed: {e}"))
+    }
+
+    /// Run a SQL aggregate (`sum`, `avg`, `min`, `max`) over `column`.
+    ///
+    /// - `column` must be a field declared on the model.
+    /// - Returns `Ok(None)` when the aggregate is NULL (e.g. empty table).
+    pub fn aggregate(
+        &self,
+        model_name: &str,
+        column: &str,
+        func: &str,
+    ) -> Result<Option<f64>, String> {
+        let model = self
+            .models
+            .get(model_name)
+            .ok_or_else(|| format!("unknown model '{}'", model_name))?;
+        let conn = self.conn()?;
+
+        let field = find_field(model, column)?;
+
+        let sql_func = match func.to_ascii_lowercase().as_str() {
+            "sum" => "SUM",
+            "avg" => "AVG",
+            "min" => "MIN",
+            "max" => "MAX",
+            other => {
+                return Err(format!(
+                    "unsupported aggregate '{}'; expected sum, avg, min, or max",
+                    other
+                ))
+            }
+        };
+
+        let sql = format!(
+            "SELECT {}({}) FROM {}",
+            sql_func, field.name, model.table_name
+        );
+
+        let val: rusqlite::types::Value = conn
+            .query_row(&sql, [], |row| row.get(0))
+            .map_err(|e| format!("aggregate query failed: {e}"))?;
+
+        use rusqlite::types::Value as SqlValue;
+        match val {
+            SqlValue::Null => Ok(None),
+            SqlValue::Integer(i) => Ok(Some(i as f64)),
+            SqlValue::Real(f) => Ok(Some(f)),
+            SqlValue::Text(s) => s.parse::<f64>().map(Some).map_err(|_| {
+                format!(
+                    "aggregate over column '{}' produced non-numeric value '{}'",
+                    field.name, s
+                )
+            }),
+            SqlValue::Blob(_) => Err(format!(
+                "aggregate over column '{}' produced a blob",
+                field.name
+            )),
+        }
+    }
+
+    /// Check every model field present in `record` against its rules.
+    fn check_record(&self, model: &ModelDef, record: &JsonValue) -> Result<(), String> {
+        let Some(obj) = record.as_object() else {
+            return Ok(());
+        };
+        for field in &model.fields {
+            if let Some(value) = obj.get(&field.name) {
+                self.check_rules(model, field, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Enforce `min(..)`, `max(..)` and `match("...")` on one value.
+    /// `null` is left to SQLite's NOT NULL handling.
+    fn check_rules(
+        &self,
+        model: &ModelDef,
+        field: &ModelField,
+        value: &JsonValue,
+    ) -> Result<(), String> {
+        if value.is_null() {
+            return Ok(());
+        }
+
+        if field.min.is_some() || field.max.is_some() {
+            let n = value.as_f64().ok_or_else(|| {
+                format!(
+                    "{}.{} must be a number (got {})",
+                    model.name, field.name, value
+                )
+            })?;
+            if let Some(min) = field.min {
+                if n < min {
+                    return Err(format!(
+                        "{}.{} must be at least {} (got {})",
+                        model.name, field.name, min, value
+                    ));
+                }
+            }
+            if let Some(max) = field.max {
+                if n > max {
+                    return Err(format!(
+                        "{}.{} must be at most {} (got {})",
+                        model.name, field.name, max, value
+                    ));
+                }
+            }
+        }
+
+        if let Some(pattern) = &field.pattern {
+            let text = value.as_str().ok_or_else(|| {
+                format!(
+                    "{}.{} must be a string (got {})",
+                    model.name, field.name, value
+                )
+            })?;
+            if let Some(re) = self.patterns.get(pattern) {
+                if !re.is_match(text) {
+                    return Err(format!(
+                        "{}.{} must match \"{}\" (got {})",
+                        model.name, field.name, pattern, value
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// INSERT one JSON object into `model`'s table on `conn`, using only the
+/// keys that are model fields. Returns the new rowid.
+fn insert_row(conn: &Connection, model: &ModelDef, record: &JsonValue) -> Result<i64, String> {
+    let obj = record
+        .as_object()
+        .ok_or_else(|| "record must be a JSON object".to_string())?;
+
+    let mut cols = Vec::new();
+    let mut placeholders = Vec::new();
+    let mut values: Vec<JsonValue> = Vec::new();
+
+    for field in &model.fields {
+        if let Some(value) = obj.get(&field.name) {
+            cols.push(field.name.clone());
+            placeholders.push("?".to_string());
+            values.push(value.clone());
+        }
+    }
+
+    if cols.is_empty() {
+        return Err("record has no matching fields".to_string());
+    }
+
+    let sql = format!(
+        "INSERT INTO {} ({}) VALUES ({})",
+        model.table_name,
+        cols.join(", "),
+        placeholders.join(", ")
+    );
+
+    let mut stmt = conn
+        .prepare_cached(&sql)
+        .map_err(|e| format!("insert prepare failed: {e}"))?;
+
+    let params_vec: Vec<rusqlite::types::Value> =
+        values.into_iter().map(json_to_sql_value).collect();
+
+    let rows_changed = stmt
+        .execute(rusqlite::params_from_iter(params_vec.iter()))
+        .map_err(|e| format!("insert execute failed: {e}"))?;
+
+    if rows_changed == 0 {
+        return Err("insert affected 0 rows".to_string());
+    }
+
+    Ok(conn.last_insert_rowid())
+}
+
+/// Read the model's fields from a `SELECT *` row into a JSON object.
+fn row_to_json(
+    model: &ModelDef,
+    row: &rusqlite::Row<'_>,
+) -> Result<JsonMap<String, JsonValue>, String> {
+    let mut obj = JsonMap::new();
+    for field in &model.fields {
+        let val: rusqlite::types::Value = row
+            .get(field.name.as_str())
+            .map_err(|e| format!("column get failed: {e}"))?;
+        obj.insert(field.name.clone(), sql_value_to_json(val));
+    }
+    Ok(obj)
+}
+
+/// Look up a declared field on `model`, rejecting unknown column names so
+/// they are never interpolated into SQL.
+fn find_field<'a>(model: &'a ModelDef, column: &str) -> Result<&'a ModelField, String> {
+    model
+        .fields
+        .iter()
+        .find(|f| f.name == column)
+        .ok_or_else(|| format!("model '{}' has no field '{}'", model.name, column))
 }
 
 /// Convert a JSON value into a rusqlite `Value`.
@@ -240,28 +959,81 @@ fn sql_value_to_json(v: rusqlite::types::Value) -> JsonValue {
 /// Initialize the global ORM from the Program's models.
 /// Called from main.rs when starting the server.
 pub fn init_global_orm(program: &Program) -> rusqlite::Result<()> {
-    // Open (or create) the SQLite database in the current working directory.
-    let conn = Connection::open("shrimpl.db")?;
-    let models = program.models.clone();
-    let orm = Orm::new(conn, models)?;
+    // Open (or create) the SQLite database (shrimpl.db in the CWD by default).
+    let orm = Orm::open(&config::database_path(), POOL_SIZE, program.models.clone())?;
+
+    *GLOBAL_ORM.write() = Some(Arc::new(orm));
+
+    Ok(())
+}
 
-    let mut guard = GLOBAL_ORM.lock().expect("GLOBAL_ORM poisoned");
-    *guard = Some(orm);
+/// Initialize the global ORM on a private in-memory database, so
+/// `shrimpl test` never touches the project's real database file. Every
+/// run starts with empty tables.
+pub fn init_global_orm_in_memory(program: &Program) -> rusqlite::Result<()> {
+    // A `:memory:` database belongs to one connection, so this "pool" has
+    // exactly one.
+    let orm = Orm::new(Connection::open_in_memory()?, program.models.clone())?;
+
+    *GLOBAL_ORM.write() = Some(Arc::new(orm));
 
     Ok(())
 }
 
+/// Scope this thread's following ORM calls to one transaction, begun by
+/// the first of them. End the scope with `finish_request_tx`. A scope left
+/// open by an earlier request on this thread is rolled back first. Does
+/// nothing when the ORM isn't initialized.
+pub fn begin_request_tx() {
+    let Ok(orm) = global_orm() else {
+        return;
+    };
+    let stale = REQUEST_TX.with(|slot| {
+        slot.borrow_mut().replace(RequestTx {
+            orm,
+            conn: None,
+            began: false,
+        })
+    });
+    if let Some(stale) = stale {
+        let _ = stale.finish(false);
+    }
+}
+
+/// Commit (`commit = true`) or roll back the scope opened by
+/// `begin_request_tx`. A failed commit is rolled back and reported.
+pub fn finish_request_tx(commit: bool) -> Result<(), String> {
+    match REQUEST_TX.with(|slot| slot.borrow_mut().take()) {
+        Some(tx) => tx.finish(commit),
+        None => Ok(()),
+    }
+}
+
+/// Make every later ORM call on this thread fail with `reason`. Used for
+/// threads that would run outside the request's transaction, such as the
+/// `with_timeout` worker.
+pub fn deny_on_this_thread(reason: String) {
+    DENIED.with(|slot| *slot.borrow_mut() = Some(reason));
+}
+
+/// The global ORM, released from the global lock before use so callers
+/// only contend for pooled connections.
+fn global_orm() -> Result<Arc<Orm>, String> {
+    if let Some(reason) = DENIED.with(|slot| slot.borrow().clone()) {
+        return Err(reason);
+    }
+    GLOBAL_ORM
+        .read()
+        .clone()
+        .ok_or_else(|| "ORM not initialized".to_string())
+}
+
 /// Insert a record into `model_name`.
 ///
 /// - `record_json` must be a JSON object string.
 /// - Returns the rowid (or PK) as a string on success.
 pub fn orm_insert(model_name: &str, record_json: &str) -> Result<String, String> {
-    let guard = GLOBAL_ORM
-        .lock()
-        .map_err(|_| "GLOBAL_ORM poisoned".to_string())?;
-    let orm = guard
-        .as_ref()
-        .ok_or_else(|| "ORM not initialized".to_string())?;
+    let orm = global_orm()?;
 
     let value: JsonValue =
         serde_json::from_str(record_json).map_err(|e| format!("invalid JSON: {e}"))?;
@@ -270,17 +1042,26 @@ pub fn orm_insert(model_name: &str, record_json: &str) -> Result<String, String>
     Ok(rowid.to_string())
 }
 
+/// Insert every object of the JSON array `records_json` into `model_name`
+/// in a single transaction. Returns the number of rows inserted.
+pub fn orm_insert_many(model_name: &str, records_json: &str) -> Result<usize, String> {
+    let orm = global_orm()?;
+
+    let value: JsonValue =
+        serde_json::from_str(records_json).map_err(|e| format!("invalid JSON: {e}"))?;
+    let records = value
+        .as_array()
+        .ok_or_else(|| "records must be a JSON array of objects".to_string())?;
+
+    orm.insert_many(model_name, records)
+}
+
 /// Find a record by primary key.
 ///
 /// - `id_json` can be a JSON scalar (number/string/bool).
 /// - Returns Some(JSON string) or None.
 pub fn orm_find_by_id(model_name: &str, id_json: &str) -> Result<Option<String>, String> {
-    let guard = GLOBAL_ORM
-        .lock()
-        .map_err(|_| "GLOBAL_ORM poisoned".to_string())?;
-    let orm = guard
-        .as_ref()
-        .ok_or_else(|| "ORM not initialized".to_string())?;
+    let orm = global_orm()?;
 
     let id_val: JsonValue =
         serde_json::from_str(id_json).map_err(|e| format!("invalid id JSON: {e}"))?;
@@ -288,3 +1069,240 @@ pub fn orm_find_by_id(model_name: &str, id_json: &str) -> Result<Option<String>,
     let result = orm.find_by_id(model_name, &id_val)?;
     Ok(result.map(|v| v.to_string()))
 }
+
+/// Apply the JSON object `patch_json` to the row of `model_name` whose
+/// primary key is `id_json`. Returns the number of rows changed.
+///
+/// Non-JSON ids (e.g. a bare word like `abc`) are treated as strings.
+pub fn orm_update(model_name: &str, id_json: &str, patch_json: &str) -> Result<usize, String> {
+    let orm = global_orm()?;
+
+    let id_val: JsonValue =
+        serde_json::from_str(id_json).unwrap_or_else(|_| JsonValue::String(id_json.to_string()));
+    let patch: JsonValue =
+        serde_json::from_str(patch_json).map_err(|e| format!("invalid patch JSON: {e}"))?;
+
+    orm.update_json(model_name, &id_val, &patch)
+}
+
+/// Delete the row of `model_name` whose primary key is `id_json`.
+/// Returns the number of rows deleted.
+///
+/// Non-JSON ids (e.g. a bare word like `abc`) are treated as strings.
+pub fn orm_delete(model_name: &str, id_json: &str) -> Result<usize, String> {
+    let orm = global_orm()?;
+
+    let id_val: JsonValue =
+        serde_json::from_str(id_json).unwrap_or_else(|_| JsonValue::String(id_json.to_string()));
+
+    orm.delete_by_id(model_name, &id_val)
+}
+
+/// Field names of `model_name` in declaration order.
+pub fn orm_field_names(model_name: &str) -> Result<Vec<String>, String> {
+    let orm = global_orm()?;
+
+    let model = orm
+        .models
+        .get(model_name)
+        .ok_or_else(|| format!("unknown model '{}'", model_name))?;
+    Ok(model.fields.iter().map(|f| f.name.clone()).collect())
+}
+
+/// Stream every row of `model_name` to `visit` (see `Orm::for_each_row`).
+pub fn orm_for_each_row<F>(model_name: &str, visit: F) -> Result<(), String>
+where
+    F: FnMut(JsonMap<String, JsonValue>) -> bool,
+{
+    let orm = global_orm()?;
+
+    orm.for_each_row(model_name, visit)
+}
+
+/// Count all rows for `model_name`.
+pub fn orm_count(model_name: &str) -> Result<i64, String> {
+    let orm = global_orm()?;
+
+    orm.count(model_name)
+}
+
+/// Count rows for `model_name` where `column` equals the JSON scalar `value_json`.
+///
+/// Non-JSON values (e.g. a bare word like `done`) are treated as strings.
+pub fn orm_count_where(model_name: &str, column: &str, value_json: &str) -> Result<i64, String> {
+    let orm = global_orm()?;
+
+    let value: JsonValue = serde_json::from_str(value_json)
+        .unwrap_or_else(|_| JsonValue::String(value_json.to_string()));
+
+    orm.count_where(model_name, column, &value)
+}
+
+/// Every row of `model_name` as a JSON array string.
+pub fn orm_all(model_name: &str) -> Result<String, String> {
+    let orm = global_orm()?;
+
+    Ok(JsonValue::Array(orm.all(model_name)?).to_string())
+}
+
+/// Rows of `model_name` where `column` equals the JSON scalar `value_json`,
+/// as a JSON array string.
+///
+/// Non-JSON values (e.g. a bare word like `done`) are treated as strings.
+pub fn orm_find_where(model_name: &str, column: &str, value_json: &str) -> Result<String, String> {
+    let orm = global_orm()?;
+
+    let value: JsonValue = serde_json::from_str(value_json)
+        .unwrap_or_else(|_| JsonValue::String(value_json.to_string()));
+
+    Ok(JsonValue::Array(orm.find_where(model_name, column, &value)?).to_string())
+}
+
+/// Compute `func` (`sum`/`avg`/`min`/`max`) over `column` of `model_name`.
+///
+/// Returns None when the table is empty.
+pub fn orm_aggregate(model_name: &str, column: &str, func: &str) -> Result<Option<f64>, String> {
+    let orm = global_orm()?;
+
+    orm.aggregate(model_name, column, func)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory_pool(size: usize) -> ConnPool {
+        ConnPool::new(
+            (0..size)
+                .map(|_| Connection::open_in_memory().unwrap())
+                .collect(),
+        )
+    }
+
+    fn memory_orm() -> Orm {
+        let program = crate::parser::parse_program(
+            "server 3000\n\nmodel User:\n  id: int pk\n  email: string\n  name: string\n  age?: int\n",
+        )
+        .unwrap();
+        Orm::new(Connection::open_in_memory().unwrap(), program.models).unwrap()
+    }
+
+    #[test]
+    fn update_json_patches_only_the_given_fields() {
+        let orm = memory_orm();
+        let id = orm
+            .insert_json(
+                "User",
+                &serde_json::json!({"email": "a@example.com", "name": "Ann", "age": 30}),
+            )
+            .unwrap();
+
+        let changed = orm
+            .update_json(
+                "User",
+                &serde_json::json!(id),
+                &serde_json::json!({"name": "Anna", "age": 31}),
+            )
+            .unwrap();
+        assert_eq!(changed, 1);
+
+        let row = orm
+            .find_by_id("User", &serde_json::json!(id))
+            .unwrap()
+            .unwrap();
+        assert_eq!(row["name"], "Anna");
+        assert_eq!(row["age"], 31);
+        assert_eq!(row["email"], "a@example.com");
+    }
+
+    #[test]
+    fn update_json_rejects_unknown_fields() {
+        let orm = memory_orm();
+        let id = orm
+            .insert_json(
+                "User",
+                &serde_json::json!({"email": "b@example.com", "name": "Bo"}),
+            )
+            .unwrap();
+
+        let err = orm
+            .update_json(
+                "User",
+                &serde_json::json!(id),
+                &serde_json::json!({"name": "Bob", "nickname": "B"}),
+            )
+            .unwrap_err();
+        assert_eq!(err, "model 'User' has no field 'nickname'");
+
+        // The rejected patch wrote nothing.
+        let row = orm
+            .find_by_id("User", &serde_json::json!(id))
+            .unwrap()
+            .unwrap();
+        assert_eq!(row["name"], "Bo");
+    }
+
+    #[test]
+    fn ids_above_2_pow_53_round_trip_exactly() {
+        let orm = memory_orm();
+        let id = 9_007_199_254_740_993_i64;
+        orm.insert_json(
+            "User",
+            &serde_json::json!({"id": id, "email": "c@example.com", "name": "Cy"}),
+        )
+        .unwrap();
+
+        let row = orm
+            .find_by_id("User", &serde_json::json!(id))
+            .unwrap()
+            .expect("row found by its exact id");
+        assert_eq!(row["id"].as_i64(), Some(id));
+        // The neighbouring id that f64 rounding would collapse onto is absent.
+        assert!(orm
+            .find_by_id("User", &serde_json::json!(id - 1))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn pool_checkout_times_out_when_exhausted() {
+        let pool = memory_pool(1);
+        let held = pool.get().unwrap();
+
+        let err = pool.get_within(Duration::from_millis(50)).err().unwrap();
+        assert_eq!(
+            err,
+            "database pool exhausted: no connection was free within 50 ms"
+        );
+
+        drop(held);
+        assert!(pool.get_within(Duration::from_millis(50)).is_ok());
+    }
+
+    #[test]
+    fn stream_slots_are_capped_below_the_pool_size() {
+        const { assert!(MAX_STREAMS < POOL_SIZE) };
+
+        let slots: Vec<StreamSlot> = (0..MAX_STREAMS)
+            .map(|_| try_stream_slot().expect("slot under the cap"))
+            .collect();
+        assert!(try_stream_slot().is_none());
+
+        drop(slots);
+        assert!(try_stream_slot().is_some());
+    }
+
+    #[test]
+    fn pool_checkout_waits_for_a_returned_connection() {
+        let pool = memory_pool(1);
+        let held = pool.get().unwrap().into_inner();
+
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                std::thread::sleep(Duration::from_millis(20));
+                pool.put(held);
+            });
+            assert!(pool.get_within(Duration::from_secs(5)).is_ok());
+        });
+    }
+}
@@ -11,6 +11,9 @@
 // - class method calls: Class.method(a, b)
 // - if / elif / else expressions
 // - repeat N times: expr loop expressions
+//   (loop index bound as `i`, or `repeat N times as k: expr`;
+//   `repeat N times with acc = init: expr` accumulates)
+// - repeat while cond: expr bounded loop expressions
 // - list literals: [1, 2, "x"]
 // - map literals: { key: 1, "other": 2 }
 // - try / catch / finally expressions (single-line style)
@@ -20,6 +23,8 @@ use super::ast::{BinOp, Expr};
 // Token kinds for expression parsing
 #[derive(Debug, Clone)]
 enum TokKind {
+    /// Whole-number literal that fits in an i64 (kept exact).
+    Int(i64),
     Number(f64),
     Str(String),
     Ident(String),
@@ -28,6 +33,7 @@ enum TokKind {
     Minus,
     Star,
     Slash,
+    Percent,
 
     LParen,
     RParen,
@@ -35,6 +41,8 @@ enum TokKind {
     Dot,
     Colon,
 
+    /// Single '=' (only valid in `repeat ... with acc = init`).
+    Assign,
     EqEq,
     BangEq,
     Lt,
@@ -74,6 +82,12 @@ fn tokenize_expr(s: &str) -> Result<Vec<Token>, String> {
                 i += 1;
             }
             let text: String = chars[start..i].iter().collect();
+            if let Ok(int) = text.parse::<i64>() {
+                tokens.push(Token {
+                    kind: TokKind::Int(int),
+                });
+                continue;
+            }
             let value: f64 = text
                 .parse()
                 .map_err(|_| format!("Invalid number literal '{}'", text))?;
@@ -85,15 +99,35 @@ fn tokenize_expr(s: &str) -> Result<Vec<Token>, String> {
 
         match c {
             '"' => {
+                // Same escapes as quoted strings in declarations:
+                // \n \t \r \\ \"
                 i += 1;
-                let start = i;
+                let mut text = String::new();
                 while i < chars.len() && chars[i] != '"' {
+                    if chars[i] == '\\' {
+                        i += 1;
+                        match chars.get(i) {
+                            Some('n') => text.push('\n'),
+                            Some('t') => text.push('\t'),
+                            Some('r') => text.push('\r'),
+                            Some('\\') => text.push('\\'),
+                            Some('"') => text.push('"'),
+                            Some(other) => {
+                                return Err(format!(
+                                    "Unknown escape '\\{}' in string literal",
+                                    other
+                                ))
+                            }
+                            None => break,
+                        }
+                    } else {
+                        text.push(chars[i]);
+                    }
                     i += 1;
                 }
                 if i >= chars.len() {
                     return Err("Unterminated string literal".to_string());
                 }
-                let text: String = chars[start..i].iter().collect();
                 i += 1;
                 tokens.push(Token {
                     kind: TokKind::Str(text),
@@ -123,6 +157,12 @@ fn tokenize_expr(s: &str) -> Result<Vec<Token>, String> {
                 });
                 i += 1;
             }
+            '%' => {
+                tokens.push(Token {
+                    kind: TokKind::Percent,
+                });
+                i += 1;
+            }
             '(' => {
                 tokens.push(Token {
                     kind: TokKind::LParen,
@@ -158,10 +198,10 @@ fn tokenize_expr(s: &str) -> Result<Vec<Token>, String> {
                     });
                     i += 2;
                 } else {
-                    return Err(
-                        "Unexpected '=' in expression; use '==' for equality comparisons"
-                            .to_string(),
-                    );
+                    tokens.push(Token {
+                        kind: TokKind::Assign,
+                    });
+                    i += 1;
                 }
             }
             '!' => {
@@ -321,7 +361,7 @@ impl ExprParser {
 
     // and-expression:  a and b and c
     fn parse_and(&mut self) -> Result<Expr, String> {
-        let mut expr = self.parse_comparison()?;
+        let mut expr = self.parse_not()?;
 
         loop {
             let is_and = matches!(self.peek(), Some(TokKind::Ident(name)) if name == "and");
@@ -331,7 +371,7 @@ impl ExprParser {
             }
 
             self.bump(); // 'and'
-            let right = self.parse_comparison()?;
+            let right = self.parse_not()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
                 op: BinOp::And,
@@ -342,6 +382,16 @@ impl ExprParser {
         Ok(expr)
     }
 
+    // not-expression:  not a, not not a, not a == b
+    fn parse_not(&mut self) -> Result<Expr, String> {
+        if self.peek_ident() == Some("not") {
+            self.bump(); // 'not'
+            let operand = self.parse_not()?;
+            return Ok(Expr::Not(Box::new(operand)));
+        }
+        self.parse_comparison()
+    }
+
     // comparison-expression:  a == b, a != b, a < b, etc.
     fn parse_comparison(&mut self) -> Result<Expr, String> {
         let mut expr = self.parse_add_sub()?;
@@ -354,6 +404,12 @@ impl ExprParser {
                 Some(TokKind::Le) => BinOp::Le,
                 Some(TokKind::Gt) => BinOp::Gt,
                 Some(TokKind::Ge) => BinOp::Ge,
+                Some(TokKind::Assign) => {
+                    return Err(
+                        "Unexpected '=' in expression; use '==' for equality comparisons"
+                            .to_string(),
+                    )
+                }
                 _ => break,
             };
 
@@ -401,13 +457,13 @@ impl ExprParser {
     }
 
     fn parse_mul_div(&mut self) -> Result<Expr, String> {
-        let mut expr = self.parse_factor()?;
+        let mut expr = self.parse_unary()?;
 
         loop {
             match self.peek() {
                 Some(TokKind::Star) => {
                     self.bump();
-                    let right = self.parse_factor()?;
+                    let right = self.parse_unary()?;
                     expr = Expr::Binary {
                         left: Box::new(expr),
                         op: BinOp::Mul,
@@ -416,13 +472,22 @@ impl ExprParser {
                 }
                 Some(TokKind::Slash) => {
                     self.bump();
-                    let right = self.parse_factor()?;
+                    let right = self.parse_unary()?;
                     expr = Expr::Binary {
                         left: Box::new(expr),
                         op: BinOp::Div,
                         right: Box::new(right),
                     };
                 }
+                Some(TokKind::Percent) => {
+                    self.bump();
+                    let right = self.parse_unary()?;
+                    expr = Expr::Binary {
+                        left: Box::new(expr),
+                        op: BinOp::Mod,
+                        right: Box::new(right),
+                    };
+                }
                 _ => break,
             }
         }
@@ -430,8 +495,37 @@ impl ExprParser {
         Ok(expr)
     }
 
+    // unary minus:  -x, - -x, -(a + b)
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        if let Some(TokKind::Minus) = self.peek() {
+            self.bump();
+            let operand = self.parse_unary()?;
+            return Ok(Expr::Neg(Box::new(operand)));
+        }
+        self.parse_postfix()
+    }
+
+    // indexing:  xs[0], row["name"], grid[1][2]
+    fn parse_postfix(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_factor()?;
+        while let Some(TokKind::LBracket) = self.peek() {
+            self.bump(); // '['
+            let index = self.parse_expr()?;
+            match self.bump() {
+                Some(TokKind::RBracket) => {}
+                other => return Err(format!("Expected ']' after index, found {:?}", other)),
+            }
+            expr = Expr::Index {
+                target: Box::new(expr),
+                index: Box::new(index),
+            };
+        }
+        Ok(expr)
+    }
+
     fn parse_factor(&mut self) -> Result<Expr, String> {
         match self.bump() {
+            Some(TokKind::Int(n)) => Ok(Expr::Int(n)),
             Some(TokKind::Number(n)) => Ok(Expr::Number(n)),
             Some(TokKind::Str(s)) => Ok(Expr::Str(s)),
             Some(TokKind::Ident(name)) => {
@@ -443,13 +537,13 @@ impl ExprParser {
 
                 match self.peek() {
                     Some(TokKind::Dot) => {
-                        // ClassName.method(args)
+                        // ClassName.method(args) or ClassName.CONSTANT
                         self.bump(); // '.'
                         let method_name = match self.bump() {
                             Some(TokKind::Ident(m)) => m,
                             other => {
                                 return Err(format!(
-                                    "Expected method name after '.', found {:?}",
+                                    "Expected method or constant name after '.', found {:?}",
                                     other
                                 ))
                             }
@@ -464,7 +558,10 @@ impl ExprParser {
                                     args,
                                 })
                             }
-                            _ => Err("Expected '(' after method name".to_string()),
+                            _ => Ok(Expr::ClassConst {
+                                class_name: name,
+                                name: method_name,
+                            }),
                         }
                     }
                     Some(TokKind::LParen) => {
@@ -536,6 +633,11 @@ impl ExprParser {
             match self.peek() {
                 Some(TokKind::Comma) => {
                     self.bump();
+                    // Trailing comma: `[1, 2, ]`
+                    if matches!(self.peek(), Some(TokKind::RBracket)) {
+                        self.bump();
+                        break;
+                    }
                 }
                 Some(TokKind::RBracket) => {
                     self.bump();
@@ -590,6 +692,11 @@ impl ExprParser {
             match self.peek() {
                 Some(TokKind::Comma) => {
                     self.bump();
+                    // Trailing comma: `{ a: 1, }`
+                    if matches!(self.peek(), Some(TokKind::RBrace)) {
+                        self.bump();
+                        break;
+                    }
                 }
                 Some(TokKind::RBrace) => {
                     self.bump();
@@ -654,7 +761,8 @@ impl ExprParser {
         })
     }
 
-    /// Parse `repeat N times: expr`.
+    /// Parse `repeat N times [as i] [with acc = init]: expr`
+    /// or `repeat while cond [with acc = init]: expr`.
     fn parse_repeat_expr(&mut self) -> Result<Expr, String> {
         match self.bump() {
             Some(TokKind::Ident(name)) if name == "repeat" => {}
@@ -666,6 +774,20 @@ impl ExprParser {
             }
         }
 
+        if self.peek_ident() == Some("while") {
+            self.bump();
+            let cond_expr = self.parse_or()?;
+            let accumulator = self.parse_accumulator()?;
+            self.expect_colon("repeat while")?;
+            let body_expr = self.parse_expr()?;
+
+            return Ok(Expr::While {
+                cond: Box::new(cond_expr),
+                body: Box::new(body_expr),
+                accumulator,
+            });
+        }
+
         let count_expr = self.parse_or()?;
 
         match self.peek() {
@@ -680,15 +802,62 @@ impl ExprParser {
             }
         }
 
+        let index_var = if self.peek_ident() == Some("as") {
+            self.bump();
+            match self.bump() {
+                Some(TokKind::Ident(n)) => n,
+                other => {
+                    return Err(format!(
+                        "Expected loop variable name after 'as', found {:?}",
+                        other
+                    ))
+                }
+            }
+        } else {
+            "i".to_string()
+        };
+
+        let accumulator = self.parse_accumulator()?;
+
         self.expect_colon("repeat")?;
         let body_expr = self.parse_expr()?;
 
         Ok(Expr::Repeat {
             count: Box::new(count_expr),
             body: Box::new(body_expr),
+            index_var,
+            accumulator,
         })
     }
 
+    /// Parse an optional `with acc = init` clause of a `repeat` loop.
+    fn parse_accumulator(&mut self) -> Result<Option<(String, Box<Expr>)>, String> {
+        if self.peek_ident() != Some("with") {
+            return Ok(None);
+        }
+        self.bump();
+        let name = match self.bump() {
+            Some(TokKind::Ident(n)) => n,
+            other => {
+                return Err(format!(
+                    "Expected accumulator name after 'with', found {:?}",
+                    other
+                ))
+            }
+        };
+        match self.bump() {
+            Some(TokKind::Assign) => {}
+            other => {
+                return Err(format!(
+                    "Expected '=' after accumulator name '{}', found {:?}",
+                    name, other
+                ))
+            }
+        }
+        let init = self.parse_or()?;
+        Ok(Some((name, Box::new(init))))
+    }
+
     /// Parse `try`/`catch`/`finally` expression (single-line Shrimpl style):
     ///
     ///   try:
@@ -4,7 +4,10 @@
 // Features:
 // - server <port> [tls]
 // - endpoint METHOD "/path"[: <body>]
+//   (METHOD is GET, POST, PUT, PATCH or DELETE)
 //   Body can be on same line after colon or next non-empty line.
+//   Bodies (endpoints, functions, methods) may continue on following lines
+//   indented deeper than the declaration, e.g. multi-line if/elif/else.
 //   Body is either:
 //     - Text expression (variables, +, -, *, /, calls, class.method calls,
 //       including OpenAI helpers like `openai_chat("...")` and
@@ -13,10 +16,14 @@
 // - func name(a, b): expr
 // - class Name:
 //     method(a, b): expr
+//     NAME = expr          (class constant, read as Name.NAME)
 // - secret NAME = "ENV_VAR_NAME"
 //   (logical secret mapping used by the `secret(...)` builtin)
 // - @rate_limit(max, window_secs) before an endpoint
 //   (or `@rate_limit max window_secs`)
+// - @cache(ttl_secs) before a GET endpoint (or `@cache ttl_secs`)
+// - @env(dev) or `@only dev,staging` before an endpoint
+//   (only registered when SHRIMPL_ENV is one of the listed names)
 // - test "name":
 //     assert <expr>
 //     assert <expr>
@@ -26,18 +33,20 @@
 //     field?: type [pk]
 //
 // Path parameters are written as "/hello/:name" (converted later in interpreter).
-// Lines starting with '#' (after trimming) are comments and are ignored.
+// Lines starting with '#' (after trimming) are comments. A block of comment
+// lines directly above an endpoint (or its attributes), func, or model is
+// kept as that declaration's `doc`; a blank line in between detaches it.
 
 pub mod ast;
 pub mod expr;
 
 use self::ast::{
-    Body, ClassDef, EndpointDecl, FunctionDef, Method, ModelDef, ModelField, Program, RateLimit,
-    SecretDecl, ServerDecl, TestCase,
+    Body, ClassDef, EndpointDecl, Expr, FunctionDef, Method, ModelDef, ModelField, Program,
+    RateLimit, SecretDecl, ServerDecl, TestCase,
 };
 use self::expr::parse_expr;
 
-use std::collections::HashMap;
+use indexmap::IndexMap;
 
 // Public entry point
 pub fn parse_program(source: &str) -> Result<Program, String> {
@@ -46,24 +55,43 @@ pub fn parse_program(source: &str) -> Result<Program, String> {
 
     let mut server: Option<ServerDecl> = None;
     let mut endpoints: Vec<EndpointDecl> = Vec::new();
-    let mut functions: HashMap<String, FunctionDef> = HashMap::new();
-    let mut classes: HashMap<String, ClassDef> = HashMap::new();
+    let mut functions: IndexMap<String, FunctionDef> = IndexMap::new();
+    let mut classes: IndexMap<String, ClassDef> = IndexMap::new();
     let mut secrets: Vec<SecretDecl> = Vec::new();
     let mut tests: Vec<TestCase> = Vec::new();
-    let mut models: HashMap<String, ModelDef> = HashMap::new();
+    let mut models: IndexMap<String, ModelDef> = IndexMap::new();
 
     // Pending attributes that apply to the *next* endpoint encountered.
     let mut pending_rate_limit: Option<RateLimit> = None;
+    let mut pending_cache_ttl: Option<u64> = None;
+    let mut pending_envs: Option<Vec<String>> = None;
+    // Comment lines seen since the last blank line or declaration.
+    let mut pending_doc: Vec<&str> = Vec::new();
 
     while i < lines.len() {
         let raw_line = lines[i];
         let trimmed = raw_line.trim();
 
-        // Skip blank lines and comments
-        if trimmed.is_empty() || trimmed.starts_with('#') {
+        if trimmed.is_empty() {
+            pending_doc.clear();
+            i += 1;
+            continue;
+        }
+        if let Some(comment) = trimmed.strip_prefix('#') {
+            pending_doc.push(comment.strip_prefix(' ').unwrap_or(comment));
             i += 1;
             continue;
         }
+        // Attributes keep the doc comment for the endpoint below them.
+        let doc = if trimmed.starts_with("@rate_limit")
+            || trimmed.starts_with("@cache")
+            || trimmed.starts_with("@env")
+            || trimmed.starts_with("@only")
+        {
+            None
+        } else {
+            doc_from_comments(&mut pending_doc)
+        };
 
         if trimmed.starts_with("server") {
             if server.is_some() {
@@ -72,10 +100,13 @@ pub fn parse_program(source: &str) -> Result<Program, String> {
                     i + 1
                 ));
             }
-            if pending_rate_limit.is_some() {
+            if let Some(attr) =
+                pending_attribute(&pending_rate_limit, pending_cache_ttl, &pending_envs)
+            {
                 return Err(format!(
-                    "Line {}: @rate_limit attribute can only be applied to an 'endpoint' declaration",
-                    i + 1
+                    "Line {}: {} attribute can only be applied to an 'endpoint' declaration",
+                    i + 1,
+                    attr
                 ));
             }
             server = Some(parse_server_line(trimmed, i + 1)?);
@@ -91,19 +122,54 @@ pub fn parse_program(source: &str) -> Result<Program, String> {
             }
             pending_rate_limit = Some(rl);
             i += 1;
+        } else if trimmed.starts_with("@cache") {
+            let ttl = parse_cache_line(trimmed, i + 1)?;
+            if pending_cache_ttl.is_some() {
+                return Err(format!(
+                    "Line {}: multiple @cache attributes before a single endpoint",
+                    i + 1
+                ));
+            }
+            pending_cache_ttl = Some(ttl);
+            i += 1;
+        } else if trimmed.starts_with("@env") || trimmed.starts_with("@only") {
+            let envs = parse_env_line(trimmed, i + 1)?;
+            if pending_envs.is_some() {
+                return Err(format!(
+                    "Line {}: multiple @env/@only attributes before a single endpoint",
+                    i + 1
+                ));
+            }
+            pending_envs = Some(envs);
+            i += 1;
         } else if trimmed.starts_with("endpoint") {
             let (mut ep, next_index) = parse_endpoint(&lines, i)?;
             ep.rate_limit = pending_rate_limit.take();
+            ep.cache_ttl = pending_cache_ttl.take();
+            ep.envs = pending_envs.take();
+            if ep.cache_ttl.is_some() && !matches!(ep.method, Method::Get) {
+                return Err(format!(
+                    "Line {}: @cache can only be applied to a GET endpoint",
+                    i + 1
+                ));
+            }
+            ep.doc = doc;
             endpoints.push(ep);
             i = next_index;
         } else if trimmed.starts_with("func ") {
-            if pending_rate_limit.is_some() {
+            if let Some(attr) =
+                pending_attribute(&pending_rate_limit, pending_cache_ttl, &pending_envs)
+            {
                 return Err(format!(
-                    "Line {}: @rate_limit can only precede an 'endpoint' declaration",
-                    i + 1
+                    "Line {}: {} can only precede an 'endpoint' declaration",
+                    i + 1,
+                    attr
                 ));
             }
-            let func = parse_func_line(trimmed, i + 1)?;
+            let (text, next_index) =
+                collect_body_lines(&lines, trimmed, i + 1, indent_of(raw_line));
+            let mut func = parse_func_line(&text, i + 1)?;
+            func.doc = doc;
             if functions.contains_key(&func.name) {
                 return Err(format!(
                     "Line {}: function '{}' already defined",
@@ -112,12 +178,15 @@ pub fn parse_program(source: &str) -> Result<Program, String> {
                 ));
             }
             functions.insert(func.name.clone(), func);
-            i += 1;
+            i = next_index;
         } else if trimmed.starts_with("class ") {
-            if pending_rate_limit.is_some() {
+            if let Some(attr) =
+                pending_attribute(&pending_rate_limit, pending_cache_ttl, &pending_envs)
+            {
                 return Err(format!(
-                    "Line {}: @rate_limit can only precede an 'endpoint' declaration",
-                    i + 1
+                    "Line {}: {} can only precede an 'endpoint' declaration",
+                    i + 1,
+                    attr
                 ));
             }
             let (class_def, next_index) = parse_class(&lines, i)?;
@@ -131,33 +200,43 @@ pub fn parse_program(source: &str) -> Result<Program, String> {
             classes.insert(class_def.name.clone(), class_def);
             i = next_index;
         } else if trimmed.starts_with("secret ") {
-            if pending_rate_limit.is_some() {
+            if let Some(attr) =
+                pending_attribute(&pending_rate_limit, pending_cache_ttl, &pending_envs)
+            {
                 return Err(format!(
-                    "Line {}: @rate_limit can only precede an 'endpoint' declaration",
-                    i + 1
+                    "Line {}: {} can only precede an 'endpoint' declaration",
+                    i + 1,
+                    attr
                 ));
             }
             let secret = parse_secret_line(trimmed, i + 1)?;
             secrets.push(secret);
             i += 1;
         } else if trimmed.starts_with("test ") {
-            if pending_rate_limit.is_some() {
+            if let Some(attr) =
+                pending_attribute(&pending_rate_limit, pending_cache_ttl, &pending_envs)
+            {
                 return Err(format!(
-                    "Line {}: @rate_limit cannot be applied to a 'test' block; only endpoints are supported",
-                    i + 1
+                    "Line {}: {} cannot be applied to a 'test' block; only endpoints are supported",
+                    i + 1,
+                    attr
                 ));
             }
             let (test_case, next_index) = parse_test(&lines, i)?;
             tests.push(test_case);
             i = next_index;
         } else if trimmed.starts_with("model ") {
-            if pending_rate_limit.is_some() {
+            if let Some(attr) =
+                pending_attribute(&pending_rate_limit, pending_cache_ttl, &pending_envs)
+            {
                 return Err(format!(
-                    "Line {}: @rate_limit can only precede an 'endpoint' declaration",
-                    i + 1
+                    "Line {}: {} can only precede an 'endpoint' declaration",
+                    i + 1,
+                    attr
                 ));
             }
-            let (model_def, next_index) = parse_model(&lines, i)?;
+            let (mut model_def, next_index) = parse_model(&lines, i)?;
+            model_def.doc = doc;
             if models.contains_key(&model_def.name) {
                 return Err(format!(
                     "Line {}: model '{}' already defined",
@@ -169,14 +248,17 @@ pub fn parse_program(source: &str) -> Result<Program, String> {
             i = next_index;
         } else {
             return Err(format!(
-                "Line {}: unrecognized statement (expected 'server', 'endpoint', 'func', 'class', 'secret', 'test', 'model', or '@rate_limit')",
+                "Line {}: unrecognized statement (expected 'server', 'endpoint', 'func', 'class', 'secret', 'test', 'model', '@rate_limit', '@cache', '@env', or '@only')",
                 i + 1
             ));
         }
     }
 
-    if pending_rate_limit.is_some() {
-        return Err("Dangling @rate_limit with no following 'endpoint' declaration".to_string());
+    if let Some(attr) = pending_attribute(&pending_rate_limit, pending_cache_ttl, &pending_envs) {
+        return Err(format!(
+            "Dangling {} with no following 'endpoint' declaration",
+            attr
+        ));
     }
 
     let server = server.ok_or_else(|| "Program must have a 'server' declaration".to_string())?;
@@ -192,6 +274,35 @@ pub fn parse_program(source: &str) -> Result<Program, String> {
     })
 }
 
+/// The endpoint attribute still waiting for its endpoint, if any.
+fn pending_attribute(
+    rate_limit: &Option<RateLimit>,
+    cache_ttl: Option<u64>,
+    envs: &Option<Vec<String>>,
+) -> Option<&'static str> {
+    if rate_limit.is_some() {
+        Some("@rate_limit")
+    } else if cache_ttl.is_some() {
+        Some("@cache")
+    } else if envs.is_some() {
+        Some("@env")
+    } else {
+        None
+    }
+}
+
+/// Join collected comment lines into a doc string (None if all blank) and
+/// reset the collector.
+fn doc_from_comments(pending: &mut Vec<&str>) -> Option<String> {
+    let doc = pending.join("\n").trim().to_string();
+    pending.clear();
+    if doc.is_empty() {
+        None
+    } else {
+        Some(doc)
+    }
+}
+
 // ---------- server ----------
 
 fn parse_server_line(line: &str, line_no: usize) -> Result<ServerDecl, String> {
@@ -319,6 +430,12 @@ fn parse_rate_limit_line(line: &str, line_no: usize) -> Result<RateLimit, String
             line_no, window_str
         )
     })?;
+    if max_requests == 0 || window_secs == 0 {
+        return Err(format!(
+            "Line {}: @rate_limit max_requests and window_secs must be positive",
+            line_no
+        ));
+    }
 
     Ok(RateLimit {
         max_requests,
@@ -326,6 +443,87 @@ fn parse_rate_limit_line(line: &str, line_no: usize) -> Result<RateLimit, String
     })
 }
 
+// ---------- @cache attribute ----------
+
+fn parse_cache_line(line: &str, line_no: usize) -> Result<u64, String> {
+    // Supports:
+    //   @cache(60)
+    //   @cache 60
+    let rest = line
+        .strip_prefix("@cache")
+        .ok_or_else(|| format!("Line {}: cache line must start with '@cache'", line_no))?
+        .trim();
+
+    let ttl_str = match rest.strip_prefix('(') {
+        Some(inner) => inner
+            .strip_suffix(')')
+            .ok_or_else(|| format!("Line {}: expected ')' to close @cache(ttl_secs)", line_no))?
+            .trim(),
+        None => rest,
+    };
+
+    match ttl_str.parse::<u64>() {
+        Ok(ttl) if ttl > 0 => Ok(ttl),
+        _ => Err(format!(
+            "Line {}: @cache expects a positive number of seconds, e.g. '@cache(60)'",
+            line_no
+        )),
+    }
+}
+
+// ---------- @env / @only attribute ----------
+
+fn parse_env_line(line: &str, line_no: usize) -> Result<Vec<String>, String> {
+    // Supports:
+    //   @env(dev)
+    //   @env dev
+    //   @only(dev, staging)
+    //   @only dev,staging
+    let (attr, rest) = if let Some(rest) = line.strip_prefix("@env") {
+        ("@env", rest)
+    } else if let Some(rest) = line.strip_prefix("@only") {
+        ("@only", rest)
+    } else {
+        return Err(format!(
+            "Line {}: environment line must start with '@env' or '@only'",
+            line_no
+        ));
+    };
+    let rest = rest.trim();
+
+    let list = match rest.strip_prefix('(') {
+        Some(inner) => inner
+            .strip_suffix(')')
+            .ok_or_else(|| format!("Line {}: expected ')' to close {}(...)", line_no, attr))?,
+        None => rest,
+    };
+
+    let mut envs = Vec::new();
+    for name in list.split(|c: char| c == ',' || c.is_whitespace()) {
+        if name.is_empty() {
+            continue;
+        }
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(format!(
+                "Line {}: invalid environment name '{}' in {}",
+                line_no, name, attr
+            ));
+        }
+        envs.push(name.to_string());
+    }
+
+    if envs.is_empty() {
+        return Err(format!(
+            "Line {}: {} expects at least one environment name, e.g. '{}(dev)'",
+            line_no, attr, attr
+        ));
+    }
+    Ok(envs)
+}
+
 // ---------- endpoint ----------
 
 // Parse an endpoint starting at line index `start`.
@@ -340,9 +538,12 @@ fn parse_endpoint(lines: &[&str], start: usize) -> Result<(EndpointDecl, usize),
         .trim_start();
 
     let mut parts = rest.splitn(2, ' ');
-    let method_str = parts
-        .next()
-        .ok_or_else(|| format!("Line {}: missing HTTP method (GET/POST)", line_no))?;
+    let method_str = parts.next().ok_or_else(|| {
+        format!(
+            "Line {}: missing HTTP method (GET/POST/PUT/PATCH/DELETE)",
+            line_no
+        )
+    })?;
     let rest_after_method = parts
         .next()
         .ok_or_else(|| format!("Line {}: missing path after method", line_no))?
@@ -351,9 +552,12 @@ fn parse_endpoint(lines: &[&str], start: usize) -> Result<(EndpointDecl, usize),
     let method = match method_str {
         "GET" => Method::Get,
         "POST" => Method::Post,
+        "PUT" => Method::Put,
+        "DELETE" => Method::Delete,
+        "PATCH" => Method::Patch,
         other => {
             return Err(format!(
-                "Line {}: unsupported method '{}'; only GET and POST are supported for now",
+                "Line {}: unsupported method '{}'; expected GET, POST, PUT, PATCH or DELETE",
                 line_no, other
             ))
         }
@@ -369,16 +573,21 @@ fn parse_endpoint(lines: &[&str], start: usize) -> Result<(EndpointDecl, usize),
         )
     })?;
     let after_colon = rest_after_path[colon_pos + 1..].trim_start();
+    let base_indent = indent_of(raw_line);
 
     if !after_colon.is_empty() {
-        let body = parse_body_spec(after_colon, line_no)?;
+        let (text, next_index) = collect_body_lines(lines, after_colon, start + 1, base_indent);
+        let body = parse_body_spec(&text, line_no)?;
         let ep = EndpointDecl {
             method,
             path,
             body,
             rate_limit: None,
+            cache_ttl: None,
+            envs: None,
+            doc: None,
         };
-        return Ok((ep, start + 1));
+        return Ok((ep, next_index));
     }
 
     let mut j = start + 1;
@@ -391,14 +600,18 @@ fn parse_endpoint(lines: &[&str], start: usize) -> Result<(EndpointDecl, usize),
             continue;
         }
 
-        let body = parse_body_spec(body_trimmed, j + 1)?;
+        let (text, next_index) = collect_body_lines(lines, body_trimmed, j + 1, base_indent);
+        let body = parse_body_spec(&text, j + 1)?;
         let ep = EndpointDecl {
             method,
             path,
             body,
             rate_limit: None,
+            cache_ttl: None,
+            envs: None,
+            doc: None,
         };
-        return Ok((ep, j + 1));
+        return Ok((ep, next_index));
     }
 
     Err(format!(
@@ -410,10 +623,16 @@ fn parse_endpoint(lines: &[&str], start: usize) -> Result<(EndpointDecl, usize),
 // Decide whether the body is text expression or JSON.
 // - JSON:   json { "message": "Hello" }
 // - Text:   any expression, e.g. "Hello " + name
+// JSON bodies are read as JSON5 (comments, trailing commas, unquoted keys)
+// and stored as strict JSON, so bad JSON is reported by `check`.
 fn parse_body_spec(s: &str, line_no: usize) -> Result<Body, String> {
     let trimmed = s.trim();
 
-    if let Some(rest) = trimmed.strip_prefix("json") {
+    // `json` must stand alone: `json_merge(...)` is an ordinary expression.
+    if let Some(rest) = trimmed
+        .strip_prefix("json")
+        .filter(|rest| !rest.starts_with(|c: char| c.is_alphanumeric() || c == '_'))
+    {
         let rest = rest.trim_start();
         if rest.is_empty() {
             return Err(format!(
@@ -421,7 +640,9 @@ fn parse_body_spec(s: &str, line_no: usize) -> Result<Body, String> {
                 line_no
             ));
         }
-        Ok(Body::JsonRaw(rest.to_string()))
+        let value: serde_json::Value = json5::from_str(rest)
+            .map_err(|e| format!("Line {}: invalid JSON body: {}", line_no, e))?;
+        Ok(Body::JsonRaw(value.to_string()))
     } else {
         let expr = parse_expr(trimmed)
             .map_err(|e| format!("Line {} (body expression): {}", line_no, e))?;
@@ -433,6 +654,7 @@ fn parse_body_spec(s: &str, line_no: usize) -> Result<Body, String> {
 
 fn parse_func_line(line: &str, line_no: usize) -> Result<FunctionDef, String> {
     // func name(a, b): expr
+    // func name(a: number, b: number) -> number: expr
     let rest = line
         .strip_prefix("func")
         .ok_or_else(|| format!("Line {}: function line must start with 'func'", line_no))?
@@ -453,9 +675,10 @@ fn parse_func_line(line: &str, line_no: usize) -> Result<FunctionDef, String> {
     let colon_pos = after_parens
         .find(':')
         .ok_or_else(|| format!("Line {}: expected ':' after parameter list", line_no))?;
+    let result_type = parse_result_type(&after_parens[..colon_pos], line_no)?;
     let body_str = after_parens[colon_pos + 1..].trim_start();
 
-    let params = parse_param_list(params_str);
+    let (params, param_types) = parse_param_list(params_str, line_no)?;
 
     let body_expr =
         parse_expr(body_str).map_err(|e| format!("Line {} (function body): {}", line_no, e))?;
@@ -463,19 +686,54 @@ fn parse_func_line(line: &str, line_no: usize) -> Result<FunctionDef, String> {
     Ok(FunctionDef {
         name,
         params,
+        param_types,
+        result_type,
         body: body_expr,
+        doc: None,
     })
 }
 
-fn parse_param_list(s: &str) -> Vec<String> {
+/// Parse `a, b` or `a: number, b: list<string>` into names and optional
+/// inline types.
+fn parse_param_list(s: &str, line_no: usize) -> Result<(Vec<String>, Vec<Option<String>>), String> {
+    let mut names = Vec::new();
+    let mut types = Vec::new();
+    for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        match part.split_once(':') {
+            Some((name, ty)) => {
+                let ty = ty.trim();
+                if ty.is_empty() {
+                    return Err(format!(
+                        "Line {}: expected a type after ':' for parameter '{}'",
+                        line_no,
+                        name.trim()
+                    ));
+                }
+                names.push(name.trim().to_string());
+                types.push(Some(ty.to_string()));
+            }
+            None => {
+                names.push(part.to_string());
+                types.push(None);
+            }
+        }
+    }
+    Ok((names, types))
+}
+
+/// Parse what sits between `)` and the body colon: nothing, or `-> type`.
+fn parse_result_type(s: &str, line_no: usize) -> Result<Option<String>, String> {
     let s = s.trim();
     if s.is_empty() {
-        return Vec::new();
+        return Ok(None);
+    }
+    match s.strip_prefix("->").map(str::trim) {
+        Some(ty) if !ty.is_empty() => Ok(Some(ty.to_string())),
+        _ => Err(format!(
+            "Line {}: expected ':' or '-> type:' after parameter list, found '{}'",
+            line_no, s
+        )),
     }
-    s.split(',')
-        .map(|p| p.trim().to_string())
-        .filter(|p| !p.is_empty())
-        .collect()
 }
 
 // ---------- classes ----------
@@ -496,7 +754,8 @@ fn parse_class(lines: &[&str], start: usize) -> Result<(ClassDef, usize), String
         .ok_or_else(|| format!("Line {}: expected ':' in class declaration", line_no))?;
     let name = rest[..colon_pos].trim().to_string();
 
-    let mut methods: HashMap<String, FunctionDef> = HashMap::new();
+    let mut methods: IndexMap<String, FunctionDef> = IndexMap::new();
+    let mut constants: IndexMap<String, Expr> = IndexMap::new();
 
     let mut i = start + 1;
     while i < lines.len() {
@@ -513,7 +772,29 @@ fn parse_class(lines: &[&str], start: usize) -> Result<(ClassDef, usize), String
         }
 
         let line_no = i + 1;
-        let method_def = parse_method_line(trimmed, line_no)?;
+        let (text, next_index) = collect_body_lines(lines, trimmed, i + 1, indent_of(raw));
+
+        if let Some((const_name, value)) = split_class_constant(&text) {
+            if constants.contains_key(const_name) || methods.contains_key(const_name) {
+                return Err(format!(
+                    "Line {}: '{}' already defined in class '{}'",
+                    line_no, const_name, name
+                ));
+            }
+            let expr = parse_expr(value)
+                .map_err(|e| format!("Line {} (class constant): {}", line_no, e))?;
+            constants.insert(const_name.to_string(), expr);
+            i = next_index;
+            continue;
+        }
+
+        let method_def = parse_method_line(&text, line_no)?;
+        if constants.contains_key(&method_def.name) {
+            return Err(format!(
+                "Line {}: '{}' already defined in class '{}'",
+                line_no, method_def.name, name
+            ));
+        }
         if methods.contains_key(&method_def.name) {
             return Err(format!(
                 "Line {}: method '{}' already defined in class '{}'",
@@ -521,10 +802,33 @@ fn parse_class(lines: &[&str], start: usize) -> Result<(ClassDef, usize), String
             ));
         }
         methods.insert(method_def.name.clone(), method_def);
-        i += 1;
+        i = next_index;
     }
 
-    Ok((ClassDef { name, methods }, i))
+    Ok((
+        ClassDef {
+            name,
+            methods,
+            constants,
+        },
+        i,
+    ))
+}
+
+/// Split a class body line of the form `NAME = expr` into its name and
+/// expression text. Method lines (`name(a): ...`) return None.
+fn split_class_constant(text: &str) -> Option<(&str, &str)> {
+    let (name, value) = text.split_once('=')?;
+    let name = name.trim();
+    let valid_name = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid_name || value.starts_with('=') {
+        return None;
+    }
+    Some((name, value.trim()))
 }
 
 fn parse_method_line(line: &str, line_no: usize) -> Result<FunctionDef, String> {
@@ -544,9 +848,10 @@ fn parse_method_line(line: &str, line_no: usize) -> Result<FunctionDef, String>
     let colon_pos = after_parens
         .find(':')
         .ok_or_else(|| format!("Line {}: expected ':' after method parameter list", line_no))?;
+    let result_type = parse_result_type(&after_parens[..colon_pos], line_no)?;
     let body_str = after_parens[colon_pos + 1..].trim_start();
 
-    let params = parse_param_list(params_str);
+    let (params, param_types) = parse_param_list(params_str, line_no)?;
 
     let body_expr =
         parse_expr(body_str).map_err(|e| format!("Line {} (method body): {}", line_no, e))?;
@@ -554,7 +859,10 @@ fn parse_method_line(line: &str, line_no: usize) -> Result<FunctionDef, String>
     Ok(FunctionDef {
         name,
         params,
+        param_types,
+        result_type,
         body: body_expr,
+        doc: None,
     })
 }
 
@@ -618,6 +926,7 @@ fn parse_model(lines: &[&str], start: usize) -> Result<(ModelDef, usize), String
             name,
             table_name,
             fields,
+            doc: None,
         },
         i,
     ))
@@ -625,13 +934,14 @@ fn parse_model(lines: &[&str], start: usize) -> Result<(ModelDef, usize), String
 
 fn parse_model_field(line: &str, line_no: usize) -> Result<ModelField, String> {
     // Grammar:
-    //   field: type [pk]
-    //   field?: type [pk]
+    //   field: type [pk] [min(n)] [max(n)] [match("regex")]
+    //   field?: type [pk] ...
     //
     // Examples:
     //   id: int pk
     //   name: string
-    //   age?: int
+    //   age?: int min(0) max(150)
+    //   email: string match("^[^@]+@[^@]+$")
     let colon_pos = line
         .find(':')
         .ok_or_else(|| format!("Line {}: expected ':' in model field", line_no))?;
@@ -658,23 +968,88 @@ fn parse_model_field(line: &str, line_no: usize) -> Result<ModelField, String> {
         ));
     }
 
-    let mut parts = rest.split_whitespace();
-    let ty = parts
-        .next()
-        .ok_or_else(|| format!("Line {}: missing type for model field '{}'", line_no, name))?
-        .to_string();
+    let ty_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    let ty = rest[..ty_end].to_string();
+    let numeric = matches!(
+        ty.to_ascii_lowercase().as_str(),
+        "int" | "integer" | "number" | "float" | "double" | "real"
+    );
+    let textual = matches!(ty.to_ascii_lowercase().as_str(), "string" | "text");
 
     let mut is_primary_key = false;
-    for token in parts {
-        if token.eq_ignore_ascii_case("pk")
-            || token.eq_ignore_ascii_case("primary")
-            || token.eq_ignore_ascii_case("primary_key")
-        {
-            is_primary_key = true;
-        } else {
+    let mut min = None;
+    let mut max = None;
+    let mut pattern = None;
+
+    for (modifier, arg) in split_field_modifiers(&rest[ty_end..], line_no, &name)? {
+        match (modifier.to_ascii_lowercase().as_str(), arg) {
+            ("pk" | "primary" | "primary_key", None) => is_primary_key = true,
+            (rule @ ("min" | "max"), Some(arg)) => {
+                if !numeric {
+                    return Err(format!(
+                        "Line {}: {}() only applies to numeric fields, but '{}' is '{}'",
+                        line_no, rule, name, ty
+                    ));
+                }
+                let bound: f64 = arg.trim().parse().map_err(|_| {
+                    format!(
+                        "Line {}: {}() in model field '{}' expects a number, got '{}'",
+                        line_no,
+                        rule,
+                        name,
+                        arg.trim()
+                    )
+                })?;
+                if rule == "min" {
+                    min = Some(bound);
+                } else {
+                    max = Some(bound);
+                }
+            }
+            ("match", Some(arg)) => {
+                if !textual {
+                    return Err(format!(
+                        "Line {}: match() only applies to string fields, but '{}' is '{}'",
+                        line_no, name, ty
+                    ));
+                }
+                if !arg.trim_start().starts_with('"') {
+                    return Err(format!(
+                        "Line {}: match() in model field '{}' expects a quoted regex",
+                        line_no, name
+                    ));
+                }
+                let (regex_src, tail) = extract_quoted(&arg, line_no, "regex")?;
+                if !tail.trim().is_empty() {
+                    return Err(format!(
+                        "Line {}: unexpected '{}' after regex in model field '{}'",
+                        line_no,
+                        tail.trim(),
+                        name
+                    ));
+                }
+                regex::Regex::new(&regex_src).map_err(|e| {
+                    format!(
+                        "Line {}: invalid regex in model field '{}': {}",
+                        line_no, name, e
+                    )
+                })?;
+                pattern = Some(regex_src);
+            }
+            _ => {
+                return Err(format!(
+                    "Line {}: unrecognized modifier '{}' in model field '{}'",
+                    line_no, modifier, name
+                ));
+            }
+        }
+    }
+
+    if let (Some(lo), Some(hi)) = (min, max) {
+        if lo > hi {
             return Err(format!(
-                "Line {}: unrecognized modifier '{}' in model field '{}'",
-                line_no, token, name
+                "Line {}: min({}) is greater than max({}) in model field '{}'",
+                line_no, lo, hi, name
             ));
         }
     }
@@ -684,15 +1059,90 @@ fn parse_model_field(line: &str, line_no: usize) -> Result<ModelField, String> {
         ty,
         is_primary_key,
         is_optional,
+        min,
+        max,
+        pattern,
     })
 }
 
+/// Split the text after a field's type into modifiers: bare words (`pk`)
+/// and calls (`min(0)`, `match("a b")`). Call arguments are returned raw;
+/// quoted strings may contain spaces and parentheses.
+fn split_field_modifiers(
+    text: &str,
+    line_no: usize,
+    field: &str,
+) -> Result<Vec<(String, Option<String>)>, String> {
+    let mut out = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut word = String::new();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() || c == '(' {
+                break;
+            }
+            word.push(c);
+            chars.next();
+        }
+
+        if chars.peek() != Some(&'(') {
+            out.push((word, None));
+            continue;
+        }
+        chars.next();
+
+        let mut arg = String::new();
+        let mut in_string = false;
+        let mut closed = false;
+        while let Some(c) = chars.next() {
+            if in_string {
+                arg.push(c);
+                if c == '\\' {
+                    if let Some(escaped) = chars.next() {
+                        arg.push(escaped);
+                    }
+                } else if c == '"' {
+                    in_string = false;
+                }
+            } else if c == '"' {
+                in_string = true;
+                arg.push(c);
+            } else if c == ')' {
+                closed = true;
+                break;
+            } else {
+                arg.push(c);
+            }
+        }
+        if !closed {
+            return Err(format!(
+                "Line {}: missing ')' after '{}(' in model field '{}'",
+                line_no, word, field
+            ));
+        }
+        out.push((word, Some(arg)));
+    }
+
+    Ok(out)
+}
+
 // ---------- tests ----------
 
 fn parse_test(lines: &[&str], start: usize) -> Result<(TestCase, usize), String> {
-    // test "name":
-    //   assert <expr>
+    // test "name" [cases <list>]:
+    //   let name = <expr>
     //   assert <expr>
+    //   assert <expr>, "message"
+    //   teardown:
+    //     <expr>
     let raw_line = lines[start];
     let line_no = start + 1;
     let line = raw_line.trim();
@@ -704,14 +1154,30 @@ fn parse_test(lines: &[&str], start: usize) -> Result<(TestCase, usize), String>
 
     let (name, after_name) = extract_quoted(rest, line_no, "test name")?;
     let after_name = after_name.trim_start();
-    if !after_name.starts_with(':') {
+    let cases = if let Some(cases_rest) = after_name.strip_prefix("cases ") {
+        // test "add" cases [[1, 2, 3], [2, 2, 4]]:
+        let cases_text = cases_rest.trim_end().strip_suffix(':').ok_or_else(|| {
+            format!(
+                "Line {}: expected ':' after the cases list of test '{}'",
+                line_no, name
+            )
+        })?;
+        let expr =
+            parse_expr(cases_text).map_err(|e| format!("Line {} (test cases): {}", line_no, e))?;
+        Some(expr)
+    } else if after_name.starts_with(':') {
+        None
+    } else {
         return Err(format!(
             "Line {}: expected ':' after test name, e.g. test \"name\":",
             line_no
         ));
-    }
+    };
 
-    let mut assertions: Vec<ast::Expr> = Vec::new();
+    let mut steps: Vec<ast::TestStep> = Vec::new();
+    let mut teardown: Vec<ast::Expr> = Vec::new();
+    // Indent of the `teardown:` line once it has been seen.
+    let mut teardown_indent: Option<usize> = None;
     let mut i = start + 1;
 
     while i < lines.len() {
@@ -730,11 +1196,61 @@ fn parse_test(lines: &[&str], start: usize) -> Result<(TestCase, usize), String>
         }
 
         let body_line_no = i + 1;
+
+        if let Some(section_indent) = teardown_indent {
+            if indent_of(raw) <= section_indent {
+                return Err(format!(
+                    "Line {}: 'teardown:' must be the last section of test '{}'",
+                    body_line_no, name
+                ));
+            }
+            let expr = parse_expr(trimmed)
+                .map_err(|e| format!("Line {} (teardown expression): {}", body_line_no, e))?;
+            teardown.push(expr);
+            i += 1;
+            continue;
+        }
+
+        if trimmed == "teardown:" {
+            teardown_indent = Some(indent_of(raw));
+            i += 1;
+            continue;
+        }
+
+        if let Some(let_rest) = trimmed.strip_prefix("let ") {
+            let (var, value) = let_rest.split_once('=').ok_or_else(|| {
+                format!(
+                    "Line {}: expected 'let name = <expr>' inside test '{}'",
+                    body_line_no, name
+                )
+            })?;
+            let var = var.trim();
+            let valid_name = var
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && var.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid_name {
+                return Err(format!(
+                    "Line {}: invalid variable name '{}' in let",
+                    body_line_no, var
+                ));
+            }
+            let expr = parse_expr(value.trim())
+                .map_err(|e| format!("Line {} (let expression): {}", body_line_no, e))?;
+            steps.push(ast::TestStep::Let {
+                name: var.to_string(),
+                expr,
+            });
+            i += 1;
+            continue;
+        }
+
         let assert_rest = trimmed
             .strip_prefix("assert")
             .ok_or_else(|| {
                 format!(
-                    "Line {}: expected 'assert <expr>' inside test '{}'",
+                    "Line {}: expected 'assert <expr>', 'let name = <expr>' or 'teardown:' inside test '{}'",
                     body_line_no, name
                 )
             })?
@@ -747,25 +1263,138 @@ fn parse_test(lines: &[&str], start: usize) -> Result<(TestCase, usize), String>
             ));
         }
 
-        let expr = parse_expr(assert_rest)
+        let (expr_text, message_text) = split_assert_message(assert_rest);
+        let expr = parse_expr(expr_text)
             .map_err(|e| format!("Line {} (assert expression): {}", body_line_no, e))?;
-        assertions.push(expr);
+        let message = match message_text {
+            Some(text) => match parse_expr(text) {
+                Ok(ast::Expr::Str(message)) => Some(message),
+                _ => {
+                    return Err(format!(
+                        "Line {}: expected a string message after ',' in assert, e.g. assert x == 5, \"x should be five\"",
+                        body_line_no
+                    ))
+                }
+            },
+            None => None,
+        };
+        steps.push(ast::TestStep::Assert(ast::Assertion { expr, message }));
 
         i += 1;
     }
 
-    if assertions.is_empty() {
+    if !steps
+        .iter()
+        .any(|step| matches!(step, ast::TestStep::Assert(_)))
+    {
         return Err(format!(
             "Line {}: test '{}' must contain at least one 'assert' line",
             line_no, name
         ));
     }
+    if teardown_indent.is_some() && teardown.is_empty() {
+        return Err(format!(
+            "Line {}: 'teardown:' in test '{}' needs at least one indented expression",
+            line_no, name
+        ));
+    }
 
-    Ok((TestCase { name, assertions }, i))
+    Ok((
+        TestCase {
+            name,
+            cases,
+            steps,
+            teardown,
+        },
+        i,
+    ))
 }
 
 // ---------- helpers ----------
 
+/// Split `expr, "message"` at the last comma outside brackets and string
+/// literals. Commas inside calls, lists and maps stay with the expression.
+fn split_assert_message(s: &str) -> (&str, Option<&str>) {
+    let mut depth = 0i32;
+    let mut in_str = false;
+    let mut escaped = false;
+    let mut split_at = None;
+    for (idx, c) in s.char_indices() {
+        if in_str {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_str = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_str = true,
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            ',' if depth == 0 => split_at = Some(idx),
+            _ => {}
+        }
+    }
+    match split_at {
+        Some(idx) => (s[..idx].trim_end(), Some(s[idx + 1..].trim())),
+        None => (s, None),
+    }
+}
+
+fn indent_of(raw: &str) -> usize {
+    raw.len() - raw.trim_start().len()
+}
+
+// Gather a possibly multi-line body. `first` is the text on the first line;
+// following lines indented deeper than `base_indent` are continuation lines
+// (blank lines and comments inside the block are skipped). The expression
+// tokenizer treats newlines as whitespace, so the joined text parses as one
+// expression, e.g.
+//
+//   endpoint GET "/sign/:x":
+//     if x > 0:
+//       "positive"
+//     else:
+//       "negative"
+//
+// Returns the joined text and the index of the first line after the block.
+fn collect_body_lines(
+    lines: &[&str],
+    first: &str,
+    start: usize,
+    base_indent: usize,
+) -> (String, usize) {
+    let mut text = first.to_string();
+    let mut next = start;
+
+    let mut j = start;
+    while j < lines.len() {
+        let raw = lines[j];
+        let trimmed = raw.trim();
+
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            j += 1;
+            continue;
+        }
+
+        if indent_of(raw) <= base_indent {
+            break;
+        }
+
+        text.push('\n');
+        text.push_str(trimmed);
+        j += 1;
+        next = j;
+    }
+
+    (text, next)
+}
+
+/// Read the first `"..."` string in `s`, decoding `\"`, `\\`, `\n`, `\t` and
+/// `\r` escapes. Returns the decoded content and the text after the closing
+/// quote.
 fn extract_quoted<'a>(s: &'a str, line_no: usize, what: &str) -> Result<(String, &'a str), String> {
     let start = s.find('"').ok_or_else(|| {
         format!(
@@ -774,13 +1403,110 @@ fn extract_quoted<'a>(s: &'a str, line_no: usize, what: &str) -> Result<(String,
         )
     })?;
     let after_start = &s[start + 1..];
-    let end_rel = after_start.find('"').ok_or_else(|| {
-        format!(
-            "Line {}: expected closing '\"' for {} string",
-            line_no, what
+
+    let mut content = String::new();
+    let mut chars = after_start.char_indices();
+    while let Some((idx, ch)) = chars.next() {
+        match ch {
+            '"' => return Ok((content, &after_start[idx + 1..])),
+            '\\' => match chars.next() {
+                Some((_, '"')) => content.push('"'),
+                Some((_, '\\')) => content.push('\\'),
+                Some((_, 'n')) => content.push('\n'),
+                Some((_, 't')) => content.push('\t'),
+                Some((_, 'r')) => content.push('\r'),
+                Some((_, other)) => {
+                    return Err(format!(
+                        "Line {}: unknown escape '\\{}' in {} string",
+                        line_no, other, what
+                    ))
+                }
+                None => break,
+            },
+            _ => content.push(ch),
+        }
+    }
+
+    Err(format!(
+        "Line {}: expected closing '\"' for {} string",
+        line_no, what
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn only_endpoint(src: &str) -> EndpointDecl {
+        let mut program = parse_program(src).unwrap();
+        assert_eq!(program.endpoints.len(), 1);
+        program.endpoints.remove(0)
+    }
+
+    #[test]
+    fn parses_every_http_method() {
+        for (word, method) in [
+            ("GET", Method::Get),
+            ("POST", Method::Post),
+            ("PUT", Method::Put),
+            ("PATCH", Method::Patch),
+            ("DELETE", Method::Delete),
+        ] {
+            let ep = only_endpoint(&format!(
+                "server 3000\n\nendpoint {} \"/items/:id\": \"ok\"\n",
+                word
+            ));
+            assert_eq!(ep.method, method, "{}", word);
+            assert_eq!(ep.path, "/items/:id");
+        }
+    }
+
+    #[test]
+    fn quoted_names_unescape_quotes_and_backslashes() {
+        let ep = only_endpoint(
+            r#"server 3000
+
+endpoint GET "/say/\"hi\"\\x": "ok"
+"#,
+        );
+        assert_eq!(ep.path, r#"/say/"hi"\x"#);
+
+        let program = parse_program(
+            r#"server 3000
+
+test "says \"hi\" \\ bye":
+  assert true
+"#,
         )
-    })?;
-    let content = &after_start[..end_rel];
-    let rest = &after_start[end_rel + 1..];
-    Ok((content.to_string(), rest))
+        .unwrap();
+        assert_eq!(program.tests[0].name, r#"says "hi" \ bye"#);
+    }
+
+    #[test]
+    fn quoted_names_reject_unknown_escapes() {
+        let err = parse_program(
+            r#"server 3000
+
+endpoint GET "/a\qb": "ok"
+"#,
+        )
+        .unwrap_err();
+        assert_eq!(err, r"Line 3: unknown escape '\q' in path string");
+
+        let err = parse_program(
+            r#"server 3000
+
+test "bad \q":
+  assert true
+"#,
+        )
+        .unwrap_err();
+        assert_eq!(err, r"Line 3: unknown escape '\q' in test name string");
+    }
+
+    #[test]
+    fn rejects_unknown_http_method() {
+        let err = parse_program("server 3000\n\nendpoint HEAD \"/items\": \"ok\"\n").unwrap_err();
+        assert!(err.contains("unsupported method 'HEAD'"), "{}", err);
+    }
 }
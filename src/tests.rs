@@ -4,20 +4,32 @@
 //
 // The AST exposes:
 //   - Program.tests: Vec<TestCase>
-//   - TestCase { name: String, assertions: Vec<Expr> }
+//   - TestCase { name: String, steps: Vec<TestStep>, teardown: Vec<Expr> }
+//   - TestStep::Let { name, expr } | TestStep::Assert(Assertion)
+//   - Assertion { expr: Expr, message: Option<String> }
 //
 // This module provides helpers to execute those tests using the current
-// interpreter API (eval::eval_body_expr), which returns a String
-// representation of the evaluated value.
+// interpreter API (eval::TestEnv), which threads `let` bindings through the
+// steps and returns a String representation of each evaluated value.
+//
+// Steps run in order. A failing `let` stops the remaining steps, since later
+// lines usually depend on it. `teardown:` expressions always run afterwards.
+// A table-driven test (`test "name" cases [...]:`) runs all of that once per
+// row, with the row bound to `case`.
 //
 // A test assertion is considered "passing" if it evaluates to the string
 // "true" (case-sensitive) after trimming whitespace. Any other value or
 // runtime error is treated as a failure.
+//
+// Results can be printed as text or serialized with `to_junit_xml` for CI
+// test panels (GitHub Actions, GitLab).
+
+use std::time::{Duration, Instant};
 
-use std::collections::HashMap;
+use serde_json::Value as JsonValue;
 
-use crate::interpreter::eval;
-use crate::parser::ast::{Expr, Program, TestCase};
+use crate::interpreter::eval::TestEnv;
+use crate::parser::ast::{Expr, Program, TestCase, TestStep};
 
 /// Result for a single Shrimpl test case.
 #[derive(Debug, Clone)]
@@ -28,6 +40,8 @@ pub struct TestResult {
     pub passed: bool,
     /// Human-readable descriptions of individual assertion failures.
     pub failures: Vec<String>,
+    /// Wall-clock time spent running the test (all cases and teardown).
+    pub elapsed: Duration,
 }
 
 /// Run all Shrimpl tests embedded in a Program and return a list of
@@ -47,40 +61,106 @@ pub fn run_program_tests(program: &Program) -> Vec<TestResult> {
 
 /// Internal helper: run a single TestCase.
 fn run_single_test(program: &Program, test: &TestCase) -> TestResult {
+    let started = Instant::now();
     let mut failures = Vec::new();
+    let scope = format!("test '{}'", test.name);
+
+    match &test.cases {
+        None => run_steps(program, test, TestEnv::new(), &scope, &mut failures),
+        Some(cases_expr) => match eval_cases(cases_expr, program) {
+            // Each row runs the whole block (steps and teardown) with a
+            // fresh environment holding the row as `case`.
+            Ok(rows) => {
+                for (idx, row) in rows.iter().enumerate() {
+                    let mut env = TestEnv::new();
+                    env.set_json("case", row);
+                    let case_scope = format!("{} case {} ({})", scope, idx + 1, row);
+                    run_steps(program, test, env, &case_scope, &mut failures);
+                }
+            }
+            Err(err_msg) => failures.push(format!("cases of {}: {}", scope, err_msg)),
+        },
+    }
+
+    TestResult {
+        name: test.name.clone(),
+        passed: failures.is_empty(),
+        failures,
+        elapsed: started.elapsed(),
+    }
+}
+
+/// Run the steps and teardown of `test` once, recording failures prefixed
+/// with `scope` (the test name, plus the case for table-driven tests).
+fn run_steps(
+    program: &Program,
+    test: &TestCase,
+    mut env: TestEnv,
+    scope: &str,
+    failures: &mut Vec<String>,
+) {
+    let mut assert_idx = 0;
 
-    // Currently Shrimpl tests run with an empty "vars" environment.
-    // If you later add support for injecting variables into tests,
-    // this is the map to populate.
-    let vars: HashMap<String, String> = HashMap::new();
+    // Variables bound by `let` lines are shared with later steps and teardown.
+    for step in &test.steps {
+        let assertion = match step {
+            TestStep::Let { name, expr } => {
+                if let Err(err_msg) = env.bind(name, expr, program) {
+                    failures.push(format!(
+                        "let {} in {} failed with error: {}",
+                        name, scope, err_msg
+                    ));
+                    break;
+                }
+                continue;
+            }
+            TestStep::Assert(assertion) => assertion,
+        };
+        assert_idx += 1;
 
-    for (idx, expr) in test.assertions.iter().enumerate() {
-        match eval_assertion(expr, program, &vars) {
+        // `assert x == 5, "x should be five"` names the assertion in the report.
+        let label = match &assertion.message {
+            Some(message) => format!("assertion {} ({})", assert_idx, message),
+            None => format!("assertion {}", assert_idx),
+        };
+        match eval_assertion(&assertion.expr, program, &env) {
             Ok(true) => {
                 // assertion passed
             }
             Ok(false) => {
                 failures.push(format!(
-                    "assertion {} in test '{}' evaluated to a non-true value",
-                    idx + 1,
-                    test.name
+                    "{} in {} evaluated to a non-true value",
+                    label, scope
                 ));
             }
             Err(err_msg) => {
                 failures.push(format!(
-                    "assertion {} in test '{}' failed with error: {}",
-                    idx + 1,
-                    test.name,
-                    err_msg
+                    "{} in {} failed with error: {}",
+                    label, scope, err_msg
                 ));
             }
         }
     }
 
-    TestResult {
-        name: test.name.clone(),
-        passed: failures.is_empty(),
-        failures,
+    for (idx, expr) in test.teardown.iter().enumerate() {
+        if let Err(err_msg) = env.eval(expr, program) {
+            failures.push(format!(
+                "teardown line {} in {} failed with error: {}",
+                idx + 1,
+                scope,
+                err_msg
+            ));
+        }
+    }
+}
+
+/// Evaluate the `cases <list>` expression of a table-driven test into its
+/// rows.
+fn eval_cases(expr: &Expr, program: &Program) -> Result<Vec<JsonValue>, String> {
+    let text = TestEnv::new().eval(expr, program)?;
+    match serde_json::from_str::<JsonValue>(&text) {
+        Ok(JsonValue::Array(rows)) => Ok(rows),
+        _ => Err(format!("expected a list of cases, got '{}'", text)),
     }
 }
 
@@ -90,13 +170,9 @@ fn run_single_test(program: &Program, test: &TestCase) -> TestResult {
 ///   Ok(true)  – assertion evaluated to "true"
 ///   Ok(false) – assertion evaluated successfully but to a non-true value
 ///   Err(..)   – runtime error while evaluating the expression
-fn eval_assertion(
-    expr: &Expr,
-    program: &Program,
-    vars: &HashMap<String, String>,
-) -> Result<bool, String> {
-    // Use the new interpreter helper that returns a String.
-    match eval::eval_body_expr(expr, program, vars) {
+fn eval_assertion(expr: &Expr, program: &Program, env: &TestEnv) -> Result<bool, String> {
+    // Use the interpreter helper that returns a String.
+    match env.eval(expr, program) {
         Ok(value_str) => {
             let trimmed = value_str.trim();
             Ok(trimmed == "true")
@@ -109,6 +185,7 @@ fn eval_assertion(
 ///
 /// This is useful for CLI commands like `shrimpl test` that want a
 /// single success/failure status.
+#[allow(dead_code)]
 pub fn assert_program_tests_pass(program: &Program) -> Result<(), String> {
     let results = run_program_tests(program);
 
@@ -135,3 +212,72 @@ pub fn assert_program_tests_pass(program: &Program) -> Result<(), String> {
         Err(msg)
     }
 }
+
+/// Serialize results as a JUnit XML report: one `<testsuite>` named
+/// `suite_name` (usually the source file) with a `<testcase>` per test and a
+/// `<failure>` per failed assertion.
+pub fn to_junit_xml(suite_name: &str, results: &[TestResult]) -> String {
+    let failed = results.iter().filter(|r| !r.passed).count();
+    let total: Duration = results.iter().map(|r| r.elapsed).sum();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n",
+        results.len(),
+        failed,
+        total.as_secs_f64()
+    ));
+    xml.push_str(&format!(
+        "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"0\" time=\"{:.3}\">\n",
+        xml_escape(suite_name),
+        results.len(),
+        failed,
+        total.as_secs_f64()
+    ));
+
+    for result in results {
+        let open = format!(
+            "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\"",
+            xml_escape(&result.name),
+            xml_escape(suite_name),
+            result.elapsed.as_secs_f64()
+        );
+        if result.passed {
+            xml.push_str(&open);
+            xml.push_str("/>\n");
+            continue;
+        }
+        xml.push_str(&open);
+        xml.push_str(">\n");
+        for failure in &result.failures {
+            let escaped = xml_escape(failure);
+            xml.push_str(&format!(
+                "      <failure message=\"{}\">{}</failure>\n",
+                escaped, escaped
+            ));
+        }
+        xml.push_str("    </testcase>\n");
+    }
+
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+/// Escape text for use in XML attributes and element content.
+fn xml_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            '\n' => out.push_str("&#10;"),
+            // Other control characters are not allowed in XML 1.0.
+            c if (c as u32) < 0x20 && c != '\t' && c != '\r' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -2,7 +2,11 @@
 //
 // Optional static type checker for Shrimpl.
 //
-// Type annotations live in config/config.<env>.json under:
+// Type annotations are written inline:
+//
+//   func add(a: number, b: number) -> number: a + b
+//
+// or, for functions without inline types, in config/config.<env>.json under:
 //
 // "types": {
 //   "functions": {
@@ -12,8 +16,9 @@
 // }
 //
 // The checker:
-// - checks that annotated functions have the right number of params
-// - infers a simple type for the function body (number/string/bool/any)
+// - checks that config-annotated functions have the right number of params
+// - infers a simple type for the function body (number/string/bool/any,
+//   plus list<T>/map<T> from literal elements)
 // - verifies body type is compatible with declared result type
 // - produces diagnostics in the same JSON shape used by docs::build_diagnostics
 
@@ -22,27 +27,54 @@ use crate::parser::ast::{BinOp, Expr, Program};
 use serde_json::{json, Value};
 use std::collections::HashMap;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 enum Ty {
     Number,
     String,
     Bool,
+    /// List with a known element type (`Any` when heterogeneous or unknown).
+    List(Box<Ty>),
+    /// Map with string keys and a known value type.
+    Map(Box<Ty>),
     Any,
 }
 
+/// Parse an annotation such as "number", "list", "list<number>" or
+/// "map<string>". Unknown names are `any`.
 fn parse_type_name(name: &str) -> Ty {
-    match name.to_lowercase().as_str() {
+    let name = name.trim().to_lowercase();
+    if let Some((outer, inner)) = name.split_once('<') {
+        let inner = parse_type_name(inner.strip_suffix('>').unwrap_or(inner));
+        return match outer.trim() {
+            "list" | "array" => Ty::List(Box::new(inner)),
+            "map" | "dict" | "object" => Ty::Map(Box::new(inner)),
+            _ => Ty::Any,
+        };
+    }
+    match name.as_str() {
         "number" | "float" | "int" | "integer" => Ty::Number,
         "string" | "str" => Ty::String,
         "bool" | "boolean" => Ty::Bool,
+        "list" | "array" => Ty::List(Box::new(Ty::Any)),
+        "map" | "dict" | "object" => Ty::Map(Box::new(Ty::Any)),
         _ => Ty::Any,
     }
 }
 
-fn is_assignable(actual: Ty, expected: Ty) -> bool {
-    match expected {
-        Ty::Any => true,
-        _ => actual == expected || actual == Ty::Any,
+fn is_assignable(actual: &Ty, expected: &Ty) -> bool {
+    match (actual, expected) {
+        (_, Ty::Any) | (Ty::Any, _) => true,
+        (Ty::List(a), Ty::List(e)) | (Ty::Map(a), Ty::Map(e)) => is_assignable(a, e),
+        _ => actual == expected,
+    }
+}
+
+/// Common type of a set of element types: the shared type if they all agree,
+/// otherwise `Any` (so `[1, "a"]` is `list<any>`).
+fn join_types(tys: &[Ty]) -> Ty {
+    match tys.first() {
+        Some(first) if tys.iter().all(|t| t == first) => first.clone(),
+        _ => Ty::Any,
     }
 }
 
@@ -54,19 +86,15 @@ fn is_assignable(actual: Ty, expected: Ty) -> bool {
 ///     "name": "<function name>",
 ///     "message": "..." }
 pub fn build_type_diagnostics(program: &Program) -> Vec<Value> {
-    let types_cfg = match config::types_section() {
-        Some(t) => t,
-        None => return Vec::new(),
-    };
-
-    if types_cfg.functions.is_empty() {
+    let annotations = function_annotations(program);
+    if annotations.is_empty() {
         return Vec::new();
     }
 
     let mut diags = Vec::<Value>::new();
 
     for (name, func) in &program.functions {
-        let annot = match types_cfg.functions.get(name) {
+        let annot = match annotations.get(name) {
             Some(a) => a,
             None => continue,
         };
@@ -94,20 +122,20 @@ pub fn build_type_diagnostics(program: &Program) -> Vec<Value> {
         }
 
         let mut local_diags = Vec::<Value>::new();
-        let body_ty = infer_expr_type(&func.body, &env, program, &types_cfg, &mut local_diags);
+        let body_ty = infer_expr_type(&func.body, &env, program, &annotations, &mut local_diags);
         diags.extend(local_diags);
 
         if let Some(result_name) = &annot.result {
             let expected = parse_type_name(result_name);
-            if !is_assignable(body_ty, expected) {
+            if !is_assignable(&body_ty, &expected) {
                 diags.push(json!({
                     "kind": "error",
                     "scope": "function",
                     "name": name,
                     "message": format!(
                         "Return type mismatch: expected {}, got {}",
-                        display_ty(expected),
-                        display_ty(body_ty)
+                        display_ty(&expected),
+                        display_ty(&body_ty)
                     )
                 }));
             }
@@ -117,12 +145,61 @@ pub fn build_type_diagnostics(program: &Program) -> Vec<Value> {
     diags
 }
 
-fn display_ty(t: Ty) -> &'static str {
+/// Function name -> type annotation (same shape as `config.types.functions`).
+type Annotations = HashMap<String, config::FunctionTypeFile>;
+
+/// Effective annotations: inline ones (`func add(a: number) -> number`) win;
+/// `config.types.functions` covers functions without inline types.
+/// Unannotated inline params count as `any`.
+fn function_annotations(program: &Program) -> Annotations {
+    let mut annotations = config::types_section()
+        .map(|t| t.functions)
+        .unwrap_or_default();
+
+    for (name, func) in &program.functions {
+        if !func.has_inline_types() {
+            continue;
+        }
+        let params = func
+            .param_types
+            .iter()
+            .map(|t| t.clone().unwrap_or_else(|| "any".to_string()))
+            .collect();
+        annotations.insert(
+            name.clone(),
+            config::FunctionTypeFile {
+                params,
+                result: func.result_type.clone(),
+            },
+        );
+    }
+
+    annotations
+}
+
+/// Append type diagnostics to a `docs::build_diagnostics`-shaped value
+/// (`{ "errors": [...], "warnings": [...] }`), split by their `kind`.
+pub fn merge_type_diagnostics(diagnostics: &mut Value, program: &Program) {
+    for diag in build_type_diagnostics(program) {
+        let bucket = if diag.get("kind").and_then(|k| k.as_str()) == Some("error") {
+            "errors"
+        } else {
+            "warnings"
+        };
+        if let Some(list) = diagnostics.get_mut(bucket).and_then(|v| v.as_array_mut()) {
+            list.push(diag);
+        }
+    }
+}
+
+fn display_ty(t: &Ty) -> String {
     match t {
-        Ty::Number => "number",
-        Ty::String => "string",
-        Ty::Bool => "bool",
-        Ty::Any => "any",
+        Ty::Number => "number".to_string(),
+        Ty::String => "string".to_string(),
+        Ty::Bool => "bool".to_string(),
+        Ty::List(elem) => format!("list<{}>", display_ty(elem)),
+        Ty::Map(value) => format!("map<{}>", display_ty(value)),
+        Ty::Any => "any".to_string(),
     }
 }
 
@@ -131,24 +208,62 @@ fn infer_expr_type(
     expr: &Expr,
     env: &HashMap<String, Ty>,
     program: &Program,
-    types_cfg: &config::TypesConfigFile,
+    annotations: &Annotations,
     diags: &mut Vec<Value>,
 ) -> Ty {
     match expr {
-        Expr::Number(_) => Ty::Number,
+        Expr::Int(_) | Expr::Number(_) => Ty::Number,
         Expr::Str(_) => Ty::String,
         Expr::Bool(_) => Ty::Bool,
 
-        Expr::Var(name) => env.get(name).copied().unwrap_or(Ty::Any),
+        Expr::Var(name) => env.get(name).cloned().unwrap_or(Ty::Any),
+
+        Expr::Not(operand) => {
+            infer_expr_type(operand, env, program, annotations, diags);
+            Ty::Bool
+        }
 
-        Expr::List(_items) => Ty::Any,
-        Expr::Map(_pairs) => Ty::Any,
+        Expr::Index { target, index } => {
+            infer_expr_type(index, env, program, annotations, diags);
+            match infer_expr_type(target, env, program, annotations, diags) {
+                Ty::List(item) => *item,
+                _ => Ty::Any,
+            }
+        }
+
+        Expr::Neg(operand) => {
+            let ty = infer_expr_type(operand, env, program, annotations, diags);
+            if !is_assignable(&ty, &Ty::Number) {
+                diags.push(json!({
+                    "kind": "warning",
+                    "scope": "expression",
+                    "name": "",
+                    "message": "Unary '-' used with a non-number operand"
+                }));
+            }
+            Ty::Number
+        }
+
+        Expr::List(items) => {
+            let elem_tys: Vec<Ty> = items
+                .iter()
+                .map(|item| infer_expr_type(item, env, program, annotations, diags))
+                .collect();
+            Ty::List(Box::new(join_types(&elem_tys)))
+        }
+        Expr::Map(pairs) => {
+            let value_tys: Vec<Ty> = pairs
+                .iter()
+                .map(|(_, value)| infer_expr_type(value, env, program, annotations, diags))
+                .collect();
+            Ty::Map(Box::new(join_types(&value_tys)))
+        }
 
         Expr::Binary { left, op, right } => match op {
-            BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div => {
-                let lt = infer_expr_type(left, env, program, types_cfg, diags);
-                let rt = infer_expr_type(right, env, program, types_cfg, diags);
-                if !is_assignable(lt, Ty::Number) || !is_assignable(rt, Ty::Number) {
+            BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Mod => {
+                let lt = infer_expr_type(left, env, program, annotations, diags);
+                let rt = infer_expr_type(right, env, program, annotations, diags);
+                if !is_assignable(&lt, &Ty::Number) || !is_assignable(&rt, &Ty::Number) {
                     diags.push(json!({
                         "kind": "warning",
                         "scope": "expression",
@@ -164,7 +279,7 @@ fn infer_expr_type(
 
         Expr::Call { name, args } => {
             // Use annotated function type if known; otherwise Any.
-            if let Some(f_annot) = types_cfg.functions.get(name) {
+            if let Some(f_annot) = annotations.get(name) {
                 // Argument count check
                 if f_annot.params.len() != args.len() {
                     diags.push(json!({
@@ -183,8 +298,8 @@ fn infer_expr_type(
                         args.iter().zip(f_annot.params.iter()).enumerate()
                     {
                         let expected = parse_type_name(param_ty_name);
-                        let actual = infer_expr_type(arg_expr, env, program, types_cfg, diags);
-                        if !is_assignable(actual, expected) {
+                        let actual = infer_expr_type(arg_expr, env, program, annotations, diags);
+                        if !is_assignable(&actual, &expected) {
                             diags.push(json!({
                                 "kind": "error",
                                 "scope": "call",
@@ -193,8 +308,8 @@ fn infer_expr_type(
                                     "Argument {} to '{}' has type {} but annotation expects {}",
                                     idx + 1,
                                     name,
-                                    display_ty(actual),
-                                    display_ty(expected)
+                                    display_ty(&actual),
+                                    display_ty(&expected)
                                 )
                             }));
                         }
@@ -206,13 +321,12 @@ fn infer_expr_type(
                     .map(|r| parse_type_name(r))
                     .unwrap_or(Ty::Any)
             } else {
-                // Unknown function: treat as dynamic.
-                Ty::Any
+                builtin_result_type(name, args, env, program, annotations, diags)
             }
         }
 
-        Expr::MethodCall { .. } => {
-            // For now, treat methods as dynamic.
+        Expr::MethodCall { .. } | Expr::ClassConst { .. } => {
+            // For now, treat methods and class constants as dynamic.
             Ty::Any
         }
 
@@ -222,25 +336,22 @@ fn infer_expr_type(
         } => {
             let mut branch_tys = Vec::new();
             for (cond, body) in branches {
-                let _cond_ty = infer_expr_type(cond, env, program, types_cfg, diags);
-                branch_tys.push(infer_expr_type(body, env, program, types_cfg, diags));
+                let _cond_ty = infer_expr_type(cond, env, program, annotations, diags);
+                branch_tys.push(infer_expr_type(body, env, program, annotations, diags));
             }
             if let Some(else_expr) = else_branch {
-                branch_tys.push(infer_expr_type(else_expr, env, program, types_cfg, diags));
+                branch_tys.push(infer_expr_type(else_expr, env, program, annotations, diags));
             }
             // Simple join: if all same non-any type, keep it; else Any.
-            if let Some(first) = branch_tys.first() {
-                if branch_tys.iter().all(|t| t == first) {
-                    *first
-                } else {
-                    Ty::Any
-                }
-            } else {
-                Ty::Any
-            }
+            join_types(&branch_tys)
         }
 
-        Expr::Repeat { body, .. } => infer_expr_type(body, env, program, types_cfg, diags),
+        Expr::Repeat { body, .. } => infer_expr_type(body, env, program, annotations, diags),
+
+        Expr::While { cond, body, .. } => {
+            let _ = infer_expr_type(cond, env, program, annotations, diags);
+            infer_expr_type(body, env, program, annotations, diags)
+        }
 
         Expr::Try {
             try_body,
@@ -248,14 +359,14 @@ fn infer_expr_type(
             finally_body,
             ..
         } => {
-            let try_ty = infer_expr_type(try_body, env, program, types_cfg, diags);
+            let try_ty = infer_expr_type(try_body, env, program, annotations, diags);
             let catch_ty = catch_body
                 .as_ref()
-                .map(|b| infer_expr_type(b, env, program, types_cfg, diags))
+                .map(|b| infer_expr_type(b, env, program, annotations, diags))
                 .unwrap_or(Ty::Any);
             let _finally_ty = finally_body
                 .as_ref()
-                .map(|b| infer_expr_type(b, env, program, types_cfg, diags))
+                .map(|b| infer_expr_type(b, env, program, annotations, diags))
                 .unwrap_or(Ty::Any);
 
             if try_ty == catch_ty {
@@ -266,3 +377,33 @@ fn infer_expr_type(
         }
     }
 }
+
+/// Result types for the few builtins whose type follows from their
+/// arguments. Other unannotated calls are dynamic (`Any`).
+fn builtin_result_type(
+    name: &str,
+    args: &[Expr],
+    env: &HashMap<String, Ty>,
+    program: &Program,
+    annotations: &Annotations,
+    diags: &mut Vec<Value>,
+) -> Ty {
+    match (name, args) {
+        // Reordering / deduplicating keeps the element type.
+        ("sort" | "sort_desc" | "unique", [list]) => {
+            match infer_expr_type(list, env, program, annotations, diags) {
+                list_ty @ Ty::List(_) => list_ty,
+                _ => Ty::List(Box::new(Ty::Any)),
+            }
+        }
+        ("len", [_]) => Ty::Number,
+        ("now_ms", []) | ("timeit", [_, ..]) => Ty::Number,
+        ("format_number", [_, _]) => Ty::String,
+        ("floor" | "ceil" | "round" | "abs" | "sqrt", [_]) | ("pow", [_, _]) => Ty::Number,
+        ("trim", [_]) | ("join", [_, _]) | ("replace", [_, _, _]) => Ty::String,
+        ("split", [_, _]) => Ty::List(Box::new(Ty::String)),
+        ("to_yaml" | "to_toml", [_]) => Ty::String,
+        ("feature", [_]) => Ty::Bool,
+        _ => Ty::Any,
+    }
+}
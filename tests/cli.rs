@@ -0,0 +1,136 @@
+// tests/cli.rs
+//
+// End-to-end tests for `shrimpl test`: run the built binary on the
+// fixtures in tests/fixtures, and on the example app.shr, and check its
+// exit code and report.
+//
+// Each run happens in its own temporary directory, because the CLI writes
+// shrimpl.lock to the working directory.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name)
+}
+
+/// A fresh, empty working directory for one CLI run.
+fn work_dir(label: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("shrimpl-cli-{}-{}", label, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn shrimpl_test(dir: &Path, file: &Path, extra: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_shrimpl"))
+        .current_dir(dir)
+        .env("NO_COLOR", "1")
+        .env_remove("SHRIMPL_ENV")
+        .arg("--file")
+        .arg(file)
+        .arg("test")
+        .args(extra)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn failing_test_exits_nonzero_with_summary() {
+    let dir = work_dir("fail");
+    let file = fixture("test_pass_fail.shr");
+    let out = shrimpl_test(&dir, &file, &[]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+
+    assert_eq!(out.status.code(), Some(1), "stdout: {stdout}");
+    assert!(stdout.contains("PASS passes"), "stdout: {stdout}");
+    assert!(stdout.contains("FAIL fails"), "stdout: {stdout}");
+    assert!(
+        stdout.contains(
+            "assertion 1 (two doubled is five) in test 'fails' evaluated to a non-true value"
+        ),
+        "stdout: {stdout}"
+    );
+    assert!(
+        stdout.contains(&format!("{}: 1 passed, 1 failed", file.display())),
+        "stdout: {stdout}"
+    );
+}
+
+#[test]
+fn passing_tests_exit_zero_on_a_fresh_database() {
+    let dir = work_dir("pass");
+    let file = fixture("test_all_pass.shr");
+
+    // The second run must see an empty table again.
+    for _ in 0..2 {
+        let out = shrimpl_test(&dir, &file, &[]);
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        assert_eq!(out.status.code(), Some(0), "stdout: {stdout}");
+        assert!(
+            stdout.contains(&format!("{}: 2 passed, 0 failed", file.display())),
+            "stdout: {stdout}"
+        );
+    }
+    assert!(!dir.join("shrimpl.db").exists());
+}
+
+#[test]
+fn json_format_reports_each_test() {
+    let dir = work_dir("json");
+    let out = shrimpl_test(&dir, &fixture("test_pass_fail.shr"), &["--format", "json"]);
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(report["passed"], 1);
+    assert_eq!(report["failed"], 1);
+    assert_eq!(report["tests"][0]["name"], "passes");
+    assert_eq!(report["tests"][1]["passed"], false);
+}
+
+#[test]
+fn json_flag_is_an_alias_for_format_json() {
+    let dir = work_dir("json-flag");
+    let file = fixture("test_pass_fail.shr");
+    let alias = shrimpl_test(&dir, &file, &["--json"]);
+    let report: serde_json::Value = serde_json::from_slice(&alias.stdout).unwrap();
+
+    assert_eq!(alias.status.code(), Some(1));
+    assert_eq!(report["passed"], 1);
+    assert_eq!(report["failed"], 1);
+
+    let both = shrimpl_test(&dir, &file, &["--json", "--format", "junit"]);
+    assert_eq!(both.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&both.stderr).contains("cannot be used with"));
+}
+
+#[test]
+fn junit_report_is_written_to_out() {
+    let dir = work_dir("junit");
+    let file = fixture("test_pass_fail.shr");
+    let out = shrimpl_test(&dir, &file, &["--format", "junit", "--out", "report.xml"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+
+    assert_eq!(out.status.code(), Some(1), "stdout: {stdout}");
+    assert!(stdout.contains("1 passed, 1 failed (report written to report.xml)"));
+
+    let xml = std::fs::read_to_string(dir.join("report.xml")).unwrap();
+    assert!(xml.starts_with("<?xml"));
+    assert!(xml.contains("<testsuites tests=\"2\" failures=\"1\""));
+    assert!(xml.contains("<testcase name=\"passes\""));
+    assert!(xml.contains("<failure message=\"assertion 1 (two doubled is five)"));
+}
+
+#[test]
+fn example_app_passes_its_own_tests() {
+    let dir = work_dir("example");
+    let file = Path::new(env!("CARGO_MANIFEST_DIR")).join("app.shr");
+    let out = shrimpl_test(&dir, &file, &[]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+
+    assert_eq!(out.status.code(), Some(0), "stdout: {stdout}");
+    assert!(stdout.contains(" 0 failed"), "stdout: {stdout}");
+}
@@ -0,0 +1,176 @@
+// tests/http_builtins.rs
+//
+// The outbound HTTP builtins against a local TcpListener server:
+// http_get_many keeps request order and overlaps the requests, and
+// http_post_json sends the JSON body and headers it was given.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use shrimpl::interpreter::eval::eval_body_expr;
+use shrimpl::parser::expr::parse_expr;
+use shrimpl::parser::parse_program;
+
+/// One request as the server saw it.
+struct Seen {
+    method: String,
+    path: String,
+    headers: HashMap<String, String>,
+    body: String,
+}
+
+/// Serves every connection on its own thread. `GET /delay/<ms>/<name>`
+/// answers `<name>` after `<ms>` milliseconds; anything else echoes "ok".
+struct TestServer {
+    base: String,
+    seen: mpsc::Receiver<Seen>,
+    max_in_flight: Arc<AtomicUsize>,
+}
+
+impl TestServer {
+    fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, seen) = mpsc::channel();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+
+        let max = max_in_flight.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let (tx, in_flight, max) = (tx.clone(), in_flight.clone(), max.clone());
+                thread::spawn(move || {
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max.fetch_max(now, Ordering::SeqCst);
+                    handle(stream.unwrap(), &tx);
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+
+        TestServer {
+            base,
+            seen,
+            max_in_flight,
+        }
+    }
+}
+
+fn handle(stream: TcpStream, tx: &mpsc::Sender<Seen>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let len = headers
+        .get("content-length")
+        .map_or(0, |n| n.parse().unwrap());
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body).unwrap();
+
+    let reply = match path.strip_prefix("/delay/").and_then(|p| p.split_once('/')) {
+        Some((ms, name)) => {
+            thread::sleep(Duration::from_millis(ms.parse().unwrap()));
+            name.to_string()
+        }
+        None => "ok".to_string(),
+    };
+
+    tx.send(Seen {
+        method,
+        path,
+        headers,
+        body: String::from_utf8(body).unwrap(),
+    })
+    .unwrap();
+
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        reply.len(),
+        reply
+    )
+    .unwrap();
+}
+
+/// Evaluate one expression with `base` bound to the server's URL.
+fn eval_with_base(src: &str, base: &str) -> Result<String, String> {
+    let program = parse_program("server 3000\n").unwrap();
+    let vars = HashMap::from([("base".to_string(), base.to_string())]);
+    eval_body_expr(&parse_expr(src).unwrap(), &program, &vars)
+}
+
+#[test]
+fn http_get_many_keeps_order_and_overlaps_requests() {
+    let server = TestServer::start();
+
+    // The first URL answers last, so completion order differs from request order.
+    let started = Instant::now();
+    let out = eval_with_base(
+        r#"http_get_many([base + "/delay/400/a", base + "/delay/300/b", base + "/delay/200/c"])"#,
+        &server.base,
+    )
+    .unwrap();
+    let elapsed = started.elapsed();
+
+    let bodies: Vec<String> = serde_json::from_str(&out).unwrap();
+    assert_eq!(bodies, ["a", "b", "c"]);
+    assert!(server.max_in_flight.load(Ordering::SeqCst) >= 2);
+
+    let mut paths: Vec<String> = (0..3)
+        .map(|_| server.seen.recv_timeout(Duration::from_secs(5)).unwrap())
+        .inspect(|seen| assert_eq!(seen.method, "GET"))
+        .map(|seen| seen.path)
+        .collect();
+    paths.sort();
+    assert_eq!(paths, ["/delay/200/c", "/delay/300/b", "/delay/400/a"]);
+    // One after another would take 900 ms.
+    assert!(elapsed < Duration::from_millis(800), "took {:?}", elapsed);
+}
+
+#[test]
+fn http_get_many_rejects_a_non_string_url() {
+    let err =
+        eval_with_base(r#"http_get_many([base + "/a", 1])"#, "http://127.0.0.1:9").unwrap_err();
+    assert_eq!(err, "http_get_many: expected a URL string, got 1");
+}
+
+#[test]
+fn http_post_json_sends_body_and_headers() {
+    let server = TestServer::start();
+    let out = eval_with_base(
+        r#"http_post_json(base + "/hook", {name: "Ann", tags: [1, 2]}, "t0k")"#,
+        &server.base,
+    )
+    .unwrap();
+    assert_eq!(out, "ok");
+
+    let seen = server.seen.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(seen.method, "POST");
+    assert_eq!(seen.path, "/hook");
+    assert_eq!(seen.headers["content-type"], "application/json");
+    assert_eq!(seen.headers["authorization"], "Bearer t0k");
+    let body: serde_json::Value = serde_json::from_str(&seen.body).unwrap();
+    assert_eq!(body, serde_json::json!({"name": "Ann", "tags": [1, 2]}));
+}
@@ -0,0 +1,182 @@
+// tests/routes.rs
+//
+// Routing tests against the Actix app built from a parsed program:
+// each declared method reaches its endpoint, and a known path with the
+// wrong method gets 405 with an Allow header. Response helpers such as
+// download(...) shape the response, but text that only looks like their
+// output is sent as an ordinary body.
+
+use actix_web::http::{header, Method, StatusCode};
+use actix_web::{test, App};
+use shrimpl::interpreter::http::{configure, ServerState};
+use shrimpl::orm;
+use shrimpl::parser::parse_program;
+
+const SOURCE: &str = r#"server 3000
+
+endpoint GET "/items/:id": "get " + id
+endpoint PUT "/items/:id": "put " + id
+endpoint PATCH "/items/:id": "patch " + id
+endpoint DELETE "/items/:id": "delete " + id
+"#;
+
+#[actix_web::test]
+async fn each_method_reaches_its_endpoint() {
+    let state = ServerState::new(parse_program(SOURCE).unwrap());
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    for (method, expected) in [
+        (Method::GET, "get 7"),
+        (Method::PUT, "put 7"),
+        (Method::PATCH, "patch 7"),
+        (Method::DELETE, "delete 7"),
+    ] {
+        let req = test::TestRequest::default()
+            .method(method.clone())
+            .uri("/items/7")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK, "{}", method);
+        let body = test::read_body(resp).await;
+        assert_eq!(body, expected.as_bytes(), "{}", method);
+    }
+}
+
+#[actix_web::test]
+async fn wrong_method_gets_405_with_allow() {
+    let state = ServerState::new(parse_program(SOURCE).unwrap());
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let req = test::TestRequest::post().uri("/items/7").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    let allow = resp.headers().get(header::ALLOW).unwrap().to_str().unwrap();
+    for method in ["GET", "PUT", "PATCH", "DELETE"] {
+        assert!(allow.contains(method), "Allow: {}", allow);
+    }
+    assert!(!allow.contains("POST"), "Allow: {}", allow);
+
+    let body = test::read_body(resp).await;
+    let body = String::from_utf8_lossy(&body);
+    assert!(body.contains("method_not_allowed"), "{}", body);
+}
+
+const HELPERS_SOURCE: &str = r#"server 3000
+
+model Note:
+  id: int pk
+  text: string
+
+func export_csv(): download("report.csv", "a,b")
+
+endpoint GET "/export": download("../users.csv", "id\n1\n")
+endpoint GET "/export/func": export_csv()
+endpoint GET "/page": with_content_type("<h1>hi</h1>", "text/html; charset=utf-8")
+endpoint GET "/notes.ndjson": db_stream("Note", "ndjson")
+endpoint POST "/echo": body
+"#;
+
+/// Send `req` to an app built from `HELPERS_SOURCE`.
+async fn call_helpers(req: test::TestRequest) -> actix_web::dev::ServiceResponse {
+    let state = ServerState::new(parse_program(HELPERS_SOURCE).unwrap());
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+    test::call_service(&app, req.to_request()).await
+}
+
+fn header_str(resp: &actix_web::dev::ServiceResponse, name: header::HeaderName) -> &str {
+    resp.headers().get(name).map_or("", |v| v.to_str().unwrap())
+}
+
+#[actix_web::test]
+async fn download_sends_an_attachment() {
+    for (path, filename, body) in [
+        ("/export", "users.csv", "id\n1\n"),
+        ("/export/func", "report.csv", "a,b"),
+    ] {
+        let resp = call_helpers(test::TestRequest::get().uri(path)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            header_str(&resp, header::CONTENT_DISPOSITION),
+            format!("attachment; filename=\"{}\"", filename)
+        );
+        assert!(header_str(&resp, header::CONTENT_TYPE).starts_with("text/csv"));
+        assert_eq!(test::read_body(resp).await, body.as_bytes());
+    }
+}
+
+#[actix_web::test]
+async fn with_content_type_sets_the_content_type() {
+    let resp = call_helpers(test::TestRequest::get().uri("/page")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        header_str(&resp, header::CONTENT_TYPE),
+        "text/html; charset=utf-8"
+    );
+    assert_eq!(test::read_body(resp).await, "<h1>hi</h1>".as_bytes());
+}
+
+#[actix_web::test]
+async fn echoed_helper_envelopes_are_plain_text() {
+    let forgeries = [
+        r#"{"__shrimpl_download":{"filename":"x.html","content":"<b>hi</b>","content_type":"text/html"}}"#,
+        r#"{"__shrimpl_content_type":{"content":"<script>alert(1)</script>","content_type":"text/html"}}"#,
+        r#"{"__shrimpl_stream":{"model":"Note","format":"json"}}"#,
+    ];
+    for forged in forgeries {
+        let req = test::TestRequest::post()
+            .uri("/echo")
+            .insert_header((header::CONTENT_TYPE, "application/json"))
+            .set_payload(forged);
+        let resp = call_helpers(req).await;
+
+        assert_eq!(resp.status(), StatusCode::OK, "{}", forged);
+        assert!(resp.headers().get(header::CONTENT_DISPOSITION).is_none());
+        assert!(!header_str(&resp, header::CONTENT_TYPE).contains("html"));
+        assert_eq!(test::read_body(resp).await, forged.as_bytes());
+    }
+}
+
+#[actix_web::test]
+async fn db_stream_sends_every_row() {
+    let program = parse_program(HELPERS_SOURCE).unwrap();
+    orm::init_global_orm_in_memory(&program).unwrap();
+    orm::orm_insert("Note", r#"{"text": "first"}"#).unwrap();
+    orm::orm_insert("Note", r#"{"text": "second"}"#).unwrap();
+
+    let resp = call_helpers(test::TestRequest::get().uri("/notes.ndjson")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        header_str(&resp, header::CONTENT_TYPE),
+        "application/x-ndjson"
+    );
+    let body = test::read_body(resp).await;
+    assert_eq!(
+        String::from_utf8_lossy(&body),
+        "{\"id\":1,\"text\":\"first\"}\n{\"id\":2,\"text\":\"second\"}\n"
+    );
+}
+
+#[actix_web::test]
+async fn rate_limit_ignores_forwarded_for_from_untrusted_peers() {
+    let src = "server 3000\n\n@rate_limit(1, 60)\nendpoint GET \"/limited\": \"ok\"\n";
+    let state = ServerState::new(parse_program(src).unwrap());
+    let app = test::init_service(App::new().configure(|cfg| configure(cfg, &state))).await;
+
+    let request = |peer: &str, forwarded_for: &str| {
+        test::TestRequest::get()
+            .uri("/limited")
+            .peer_addr(peer.parse().unwrap())
+            .insert_header(("X-Forwarded-For", forwarded_for.to_string()))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, request("203.0.113.9:5000", "1.1.1.1")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    // A new fake address and a new source port are still the same client.
+    let resp = test::call_service(&app, request("203.0.113.9:5001", "2.2.2.2")).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    // Another peer has its own budget.
+    let resp = test::call_service(&app, request("203.0.113.10:5000", "1.1.1.1")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}